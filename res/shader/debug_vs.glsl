@@ -0,0 +1,14 @@
+#version 330 core
+
+in vec3 position;
+in vec4 color;
+
+out vec4 line_color;
+
+uniform mat4 view;
+uniform mat4 perspective;
+
+void main() {
+    line_color = color;
+    gl_Position = perspective * view * vec4(position, 1.0);
+}
@@ -1,16 +0,0 @@
-#version 460
-
-// Interpolated values from the vertex shaders
-in vec2 UV;
-
-// Ouput data
-out vec3 color;
-
-// Values that stay constant for the whole mesh.
-uniform sampler2D myTextureSampler;
-
-void main(){
-
-    // Output color = color of the texture at the specified UV
-    color = texture( myTextureSampler, UV ).rgb;
-}
\ No newline at end of file
@@ -0,0 +1,12 @@
+// model, view and projection shared by every mesh variant
+
+uniform mat4 view;
+uniform mat4 perspective;
+
+#ifdef INSTANCED
+in mat4 instance_model;
+#define MODEL_MATRIX instance_model
+#else
+uniform mat4 model;
+#define MODEL_MATRIX model
+#endif
@@ -0,0 +1,21 @@
+// tangent space normal mapping without tangents in the vertices, the frame is rebuilt per
+// fragment from the screen space derivatives of the position and the uv
+
+mat3 cotangent_frame(vec3 n, vec3 p, vec2 uv) {
+    vec3 dp1 = dFdx(p);
+    vec3 dp2 = dFdy(p);
+    vec2 duv1 = dFdx(uv);
+    vec2 duv2 = dFdy(uv);
+    vec3 dp2perp = cross(dp2, n);
+    vec3 dp1perp = cross(n, dp1);
+    vec3 t = dp2perp * duv1.x + dp1perp * duv2.x;
+    vec3 b = dp2perp * duv1.y + dp1perp * duv2.y;
+    float scale = inversesqrt(max(max(dot(t, t), dot(b, b)), 1e-20));
+    return mat3(t * scale, b * scale, n);
+}
+
+// world space normal, the map stores xyz in 0..1
+vec3 mapped_normal(vec3 n, vec3 p, vec2 uv, sampler2D normal_map) {
+    vec3 m = texture(normal_map, uv).xyz * 2.0 - 1.0;
+    return normalize(cotangent_frame(normalize(n), p, uv) * m);
+}
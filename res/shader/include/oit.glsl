@@ -0,0 +1,21 @@
+// weighted blended order independent transparency, see post/oit_composite_fs.glsl
+
+// es needs explicit locations with more than one output, 150 has none and glium finds them by name
+#if __VERSION__ >= 300
+layout(location = 0) out vec4 accum;
+layout(location = 1) out vec4 weight;
+#else
+out vec4 accum;
+out vec4 weight;
+#endif
+
+float oit_weight(float a) {
+    return clamp(pow(min(1.0, a * 10.0) + 0.01, 3.0) * 1e8 * pow(1.0 - gl_FragCoord.z * 0.9, 3.0), 1e-2, 3e3);
+}
+
+void write_oit(vec4 c) {
+    float w = oit_weight(c.a);
+    // rgb is summed, alpha multiplies up to the revealage
+    accum = vec4(c.rgb * c.a * w, c.a);
+    weight = vec4(c.a * w, 0.0, 0.0, c.a);
+}
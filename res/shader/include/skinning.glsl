@@ -0,0 +1,44 @@
+// linear blend skinning with up to four joints per vertex.
+// MAX_JOINTS is injected by the renderer, instanced draws get one palette per instance.
+// without storage buffers the palette is a std140 uniform block of a single mesh, the renderer
+// does not instance skinned meshes then
+
+in vec4 weights;
+in ivec4 joint_mi;
+in int joint_c;
+
+#ifdef HAS_STORAGE_BUFFERS
+layout(column_major) buffer MyBlock {
+    mat4 transform_m[];
+};
+#else
+layout(std140, column_major) uniform MyBlock {
+    mat4 transform_m[MAX_JOINTS];
+};
+#endif
+
+vec4 skin(vec3 position, int palette) {
+    vec4 acc = vec4(0.0);
+    for (int i = 0; i < joint_c; i++) {
+        if (weights[i] > 0.0) {
+            acc += (vec4(position, 1.0) * transform_m[palette + joint_mi[i]]) * weights[i];
+        } else {
+            break;
+        }
+    }
+    acc.w = 1.0;
+    return acc;
+}
+
+// the normal blended with the same weights, not renormalized
+vec3 skin_normal(vec3 normal, int palette) {
+    vec3 acc = vec3(0.0);
+    for (int i = 0; i < joint_c; i++) {
+        if (weights[i] > 0.0) {
+            acc += (vec4(normal, 0.0) * transform_m[palette + joint_mi[i]]).xyz * weights[i];
+        } else {
+            break;
+        }
+    }
+    return acc;
+}
@@ -0,0 +1,47 @@
+#version 330 core
+
+in vec2 UV;
+
+#ifdef OIT
+#include "include/oit.glsl"
+#else
+out vec4 color;
+#endif
+
+#ifdef TEXTURED
+uniform sampler2D myTextureSampler;
+#endif
+#ifdef HAS_NORMAL_MAP
+in vec3 world_normal;
+in vec3 world_position;
+uniform sampler2D normalMapSampler;
+#include "include/normal_map.glsl"
+#endif
+uniform float opacity;
+// cutout materials discard below this alpha, 0 for none
+uniform float alpha_cutoff;
+
+vec4 base_color() {
+#ifdef TEXTURED
+    return texture(myTextureSampler, UV);
+#else
+    return vec4(1.0, 0.0, 0.0, 1.0);
+#endif
+}
+
+void main() {
+    vec4 c = base_color();
+#ifdef HAS_NORMAL_MAP
+    vec3 n = mapped_normal(world_normal, world_position, UV, normalMapSampler);
+    c.rgb *= 0.3 + 0.7 * max(dot(n, normalize(vec3(0.3, 1.0, 0.5))), 0.0);
+#endif
+    c.a *= opacity;
+#ifdef OIT
+    write_oit(c);
+#else
+    if (c.a < alpha_cutoff) {
+        discard;
+    }
+    color = c;
+#endif
+}
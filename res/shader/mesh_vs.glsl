@@ -0,0 +1,39 @@
+#version 330 core
+
+// every mesh program is a variant of this, see MeshVariant in renderer.rs
+
+#include "include/camera.glsl"
+
+in vec3 position;
+in vec3 normal;
+in vec2 texture;
+
+#ifdef SKINNED
+#include "include/skinning.glsl"
+#endif
+
+out vec2 UV;
+#ifdef HAS_NORMAL_MAP
+out vec3 world_normal;
+out vec3 world_position;
+#endif
+
+void main() {
+#if defined(SKINNED) && defined(INSTANCED)
+    vec4 p = skin(position, gl_InstanceID * MAX_JOINTS);
+    vec3 n = skin_normal(normal, gl_InstanceID * MAX_JOINTS);
+#elif defined(SKINNED)
+    vec4 p = skin(position, 0);
+    vec3 n = skin_normal(normal, 0);
+#else
+    vec4 p = vec4(position, 1.0);
+    vec3 n = normal;
+#endif
+    vec4 world = MODEL_MATRIX * p;
+    gl_Position = perspective * view * world;
+    UV = texture;
+#ifdef HAS_NORMAL_MAP
+    world_normal = mat3(MODEL_MATRIX) * n;
+    world_position = world.xyz;
+#endif
+}
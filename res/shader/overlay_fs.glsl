@@ -0,0 +1,15 @@
+#version 330 core
+
+in vec2 v_tex_coords;
+in vec4 v_color;
+
+out vec4 color;
+
+uniform sampler2D overlay_texture;
+uniform bool alpha_only;
+
+void main() {
+    vec4 t = texture(overlay_texture, v_tex_coords);
+    // the font atlas only has coverage in the red channel
+    color = alpha_only ? vec4(v_color.rgb, v_color.a * t.r) : v_color * t;
+}
@@ -0,0 +1,17 @@
+#version 330 core
+
+in vec2 position;
+in vec2 tex_coords;
+in vec4 color;
+
+out vec2 v_tex_coords;
+out vec4 v_color;
+
+uniform vec2 screen_size;
+
+void main() {
+    v_tex_coords = tex_coords;
+    v_color = color;
+    // pixels from the top left corner to clip space
+    gl_Position = vec4(position.x / screen_size.x * 2.0 - 1.0, 1.0 - position.y / screen_size.y * 2.0, 0.0, 1.0);
+}
@@ -0,0 +1,14 @@
+#version 330 core
+
+in vec2 UV;
+
+out vec4 color;
+
+uniform sampler2D source;
+uniform sampler2D bloom;
+uniform float intensity;
+
+void main() {
+    vec4 scene = texture(source, UV);
+    color = vec4(scene.rgb + texture(bloom, UV).rgb * intensity, scene.a);
+}
@@ -0,0 +1,20 @@
+#version 330 core
+
+in vec2 UV;
+
+out vec4 color;
+
+uniform sampler2D source;
+// one texel along the blur axis
+uniform vec2 direction;
+
+const float weight[5] = float[](0.227027, 0.1945946, 0.1216216, 0.054054, 0.016216);
+
+void main() {
+    vec3 result = texture(source, UV).rgb * weight[0];
+    for (int i = 1; i < 5; i++) {
+        result += texture(source, UV + direction * i).rgb * weight[i];
+        result += texture(source, UV - direction * i).rgb * weight[i];
+    }
+    color = vec4(result, 1.0);
+}
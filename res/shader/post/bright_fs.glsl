@@ -0,0 +1,14 @@
+#version 330 core
+
+in vec2 UV;
+
+out vec4 color;
+
+uniform sampler2D source;
+uniform float threshold;
+
+void main() {
+    vec3 c = texture(source, UV).rgb;
+    float brightness = dot(c, vec3(0.2126, 0.7152, 0.0722));
+    color = vec4(c * max(brightness - threshold, 0.0) / max(brightness, 0.0001), 1.0);
+}
@@ -0,0 +1,11 @@
+#version 330 core
+
+in vec2 UV;
+
+out vec4 color;
+
+uniform sampler2D source;
+
+void main() {
+    color = texture(source, UV);
+}
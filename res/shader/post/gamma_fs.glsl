@@ -0,0 +1,13 @@
+#version 330 core
+
+in vec2 UV;
+
+out vec4 color;
+
+uniform sampler2D source;
+uniform float gamma;
+
+void main() {
+    vec4 linear = texture(source, UV);
+    color = vec4(pow(linear.rgb, vec3(1.0 / gamma)), linear.a);
+}
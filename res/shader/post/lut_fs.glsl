@@ -0,0 +1,26 @@
+#version 330 core
+
+in vec2 UV;
+
+out vec4 color;
+
+uniform sampler2D source;
+// 16x16x16 lut laid out as a 256x16 strip, blue selects the slice
+uniform sampler2D lut;
+
+const float LUT_SIZE = 16.0;
+
+vec3 sample_slice(float slice, vec3 c) {
+    float x = (slice * LUT_SIZE + c.r * (LUT_SIZE - 1.0) + 0.5) / (LUT_SIZE * LUT_SIZE);
+    float y = (c.g * (LUT_SIZE - 1.0) + 0.5) / LUT_SIZE;
+    return texture(lut, vec2(x, y)).rgb;
+}
+
+void main() {
+    vec4 c = texture(source, UV);
+    vec3 graded = clamp(c.rgb, 0.0, 1.0);
+    float b = graded.b * (LUT_SIZE - 1.0);
+    float lo = floor(b);
+    float hi = min(lo + 1.0, LUT_SIZE - 1.0);
+    color = vec4(mix(sample_slice(lo, graded), sample_slice(hi, graded), b - lo), c.a);
+}
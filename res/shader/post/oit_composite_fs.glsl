@@ -0,0 +1,18 @@
+#version 330 core
+
+in vec2 UV;
+
+out vec4 color;
+
+uniform sampler2D accum;
+uniform sampler2D weight;
+
+void main() {
+    vec4 a = texture(accum, UV);
+    float revealage = a.a;
+    if (revealage >= 1.0) {
+        discard;
+    }
+    vec3 average = a.rgb / max(texture(weight, UV).r, 1e-5);
+    color = vec4(average, 1.0 - revealage);
+}
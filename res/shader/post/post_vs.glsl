@@ -0,0 +1,11 @@
+#version 330 core
+
+in vec2 position;
+in vec2 tex_coords;
+
+out vec2 UV;
+
+void main() {
+    UV = tex_coords;
+    gl_Position = vec4(position, 0.0, 1.0);
+}
@@ -0,0 +1,23 @@
+#version 330 core
+
+in vec2 UV;
+
+out vec4 color;
+
+uniform sampler2D source;
+uniform float exposure;
+
+// ACES filmic curve fit by Krzysztof Narkowicz
+vec3 aces(vec3 x) {
+    const float a = 2.51;
+    const float b = 0.03;
+    const float c = 2.43;
+    const float d = 0.59;
+    const float e = 0.14;
+    return clamp((x * (a * x + b)) / (x * (c * x + d) + e), 0.0, 1.0);
+}
+
+void main() {
+    vec4 hdr = texture(source, UV);
+    color = vec4(aces(hdr.rgb * exposure), hdr.a);
+}
@@ -0,0 +1,16 @@
+#version 330 core
+
+in vec2 UV;
+
+out vec4 color;
+
+uniform sampler2D source;
+uniform float strength;
+uniform float radius;
+
+void main() {
+    vec4 c = texture(source, UV);
+    float d = distance(UV, vec2(0.5));
+    float v = 1.0 - smoothstep(radius, radius + 0.5, d) * strength;
+    color = vec4(c.rgb * v, c.a);
+}
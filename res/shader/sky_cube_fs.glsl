@@ -0,0 +1,11 @@
+#version 330 core
+
+in vec3 direction;
+
+out vec4 color;
+
+uniform samplerCube environment;
+
+void main() {
+    color = vec4(texture(environment, direction).rgb, 1.0);
+}
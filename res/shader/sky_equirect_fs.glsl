@@ -0,0 +1,15 @@
+#version 330 core
+
+in vec3 direction;
+
+out vec4 color;
+
+uniform sampler2D environment;
+
+const float PI = 3.14159265359;
+
+void main() {
+    vec3 d = normalize(direction);
+    vec2 uv = vec2(atan(d.z, d.x) / (2.0 * PI) + 0.5, asin(clamp(d.y, -1.0, 1.0)) / PI + 0.5);
+    color = vec4(texture(environment, uv).rgb, 1.0);
+}
@@ -0,0 +1,15 @@
+#version 330 core
+
+in vec3 direction;
+
+out vec4 color;
+
+uniform vec3 zenith;
+uniform vec3 horizon;
+uniform vec3 ground;
+
+void main() {
+    float h = normalize(direction).y;
+    vec3 sky = h >= 0.0 ? mix(horizon, zenith, pow(h, 0.5)) : mix(horizon, ground, pow(-h, 0.5));
+    color = vec4(sky, 1.0);
+}
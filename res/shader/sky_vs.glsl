@@ -0,0 +1,18 @@
+#version 330 core
+
+in vec3 position;
+
+out vec3 direction;
+
+uniform mat4 view;
+uniform mat4 perspective;
+// 1, or 0 with reversed z
+uniform float far_ndc;
+
+void main() {
+    direction = position;
+    // rotation only, the sky stays centered on the camera
+    vec4 p = perspective * mat4(mat3(view)) * vec4(position, 1.0);
+    // every fragment on the far plane
+    gl_Position = vec4(p.xy, p.w * far_ndc, p.w);
+}
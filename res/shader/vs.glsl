@@ -1,13 +0,0 @@
-#version 460
-
-in vec3 position;
-in vec3 normal;
-
-uniform mat4 model;
-uniform mat4 view;
-uniform mat4 perspective;
-
-void main() {
-    mat4 modelview = view * model;
-    gl_Position = perspective * modelview * vec4(position, 1.0);
-}
\ No newline at end of file
@@ -76,7 +76,7 @@ impl DummyGame {
 
         let fv = cgmath::Rad(std::f64::consts::PI / 3.0);
         let perspective: cgmath::Matrix4<f32> =
-            cgmath::perspective(fv, (4 / 3) as f32, 0.1, 1024.0);
+            cgmath::perspective(fv, 4.0 / 3.0, 0.1, 1024.0);
 
         //let kp = Vec::new();
 
@@ -112,7 +112,7 @@ impl DummyGame {
         let mut size_changed = false;
         let fv = cgmath::Rad(std::f64::consts::PI / 3.0);
         let mut perspective_new: cgmath::Matrix4<f32> = 
-            cgmath::perspective(fv, (4 / 3) as f32, 0.1, 1024.0);
+            cgmath::perspective(fv, 4.0 / 3.0, 0.1, 1024.0);
         let mut current_position: glutin::dpi::LogicalPosition =
             glutin::dpi::LogicalPosition::new(0.0, 0.0);
 
@@ -0,0 +1,252 @@
+use cgmath::{Matrix4,Vector3,Vector4,InnerSpace};
+
+#[derive(Copy, Clone, Debug)]
+pub struct Aabb {
+    pub min: Vector3<f32>,
+    pub max: Vector3<f32>,
+}
+
+#[derive(Copy, Clone, Debug)]
+pub struct BoundingSphere {
+    pub center: Vector3<f32>,
+    pub radius: f32,
+}
+
+#[derive(Copy, Clone, Debug)]
+pub struct Bounds {
+    pub aabb: Aabb,
+    pub sphere: BoundingSphere,
+}
+
+impl Aabb {
+    pub fn empty() -> Aabb {
+        Aabb{
+            min: Vector3{x: f32::MAX, y: f32::MAX, z: f32::MAX},
+            max: Vector3{x: f32::MIN, y: f32::MIN, z: f32::MIN},
+        }
+    }
+
+    pub fn from_points<I: IntoIterator<Item = [f32; 3]>>(points: I) -> Aabb {
+        let mut aabb = Aabb::empty();
+        for p in points {
+            aabb.extend(Vector3{x: p[0], y: p[1], z: p[2]});
+        }
+        aabb
+    }
+
+    pub fn extend(&mut self, p: Vector3<f32>) {
+        self.min.x = self.min.x.min(p.x);
+        self.min.y = self.min.y.min(p.y);
+        self.min.z = self.min.z.min(p.z);
+        self.max.x = self.max.x.max(p.x);
+        self.max.y = self.max.y.max(p.y);
+        self.max.z = self.max.z.max(p.z);
+    }
+
+    pub fn center(&self) -> Vector3<f32> {
+        (self.min + self.max) * 0.5
+    }
+
+    pub fn corners(&self) -> [Vector3<f32>; 8] {
+        let (a, b) = (self.min, self.max);
+        [
+            Vector3{x: a.x, y: a.y, z: a.z},
+            Vector3{x: b.x, y: a.y, z: a.z},
+            Vector3{x: a.x, y: b.y, z: a.z},
+            Vector3{x: b.x, y: b.y, z: a.z},
+            Vector3{x: a.x, y: a.y, z: b.z},
+            Vector3{x: b.x, y: a.y, z: b.z},
+            Vector3{x: a.x, y: b.y, z: b.z},
+            Vector3{x: b.x, y: b.y, z: b.z},
+        ]
+    }
+
+    // distance along dir to the first hit, 0 if the origin is inside. dir doesn't have to be normalized,
+    // the distance is then in units of its length
+    pub fn ray_distance(&self, origin: Vector3<f32>, dir: Vector3<f32>) -> Option<f32> {
+        let mut near = 0.0f32;
+        let mut far = f32::MAX;
+        for axis in 0..3 {
+            if dir[axis].abs() < 1e-8 {
+                if origin[axis] < self.min[axis] || origin[axis] > self.max[axis] {
+                    return None;
+                }
+                continue;
+            }
+            let inv = 1.0 / dir[axis];
+            let t0 = (self.min[axis] - origin[axis]) * inv;
+            let t1 = (self.max[axis] - origin[axis]) * inv;
+            near = near.max(t0.min(t1));
+            far = far.min(t0.max(t1));
+            if near > far {
+                return None;
+            }
+        }
+        Some(near)
+    }
+
+    pub fn transformed(&self, m: &Matrix4<f32>) -> Aabb {
+        let mut aabb = Aabb::empty();
+        for c in self.corners().iter() {
+            aabb.extend((*m * c.extend(1.0)).truncate());
+        }
+        aabb
+    }
+}
+
+impl BoundingSphere {
+    pub fn transformed(&self, m: &Matrix4<f32>) -> BoundingSphere {
+        let scale = m.x.truncate().magnitude()
+            .max(m.y.truncate().magnitude())
+            .max(m.z.truncate().magnitude());
+        BoundingSphere{
+            center: (*m * self.center.extend(1.0)).truncate(),
+            radius: self.radius * scale,
+        }
+    }
+}
+
+impl Bounds {
+    pub fn from_aabb(aabb: Aabb) -> Bounds {
+        let center = aabb.center();
+        let radius = aabb.corners().iter()
+            .map(|c| (*c - center).magnitude())
+            .fold(0.0, f32::max);
+        Bounds{
+            aabb,
+            sphere: BoundingSphere{center, radius},
+        }
+    }
+
+    pub fn from_points<I: IntoIterator<Item = [f32; 3]>>(points: I) -> Bounds {
+        Bounds::from_aabb(Aabb::from_points(points))
+    }
+
+    pub fn transformed(&self, m: &Matrix4<f32>) -> Bounds {
+        Bounds{
+            aabb: self.aabb.transformed(m),
+            sphere: self.sphere.transformed(m),
+        }
+    }
+}
+
+pub struct Frustum {
+    // left, right, bottom, top, near, far; xyz is the inward normal, w the distance
+    pub planes: [Vector4<f32>; 6],
+}
+
+impl Frustum {
+    pub fn from_matrix(m: Matrix4<f32>) -> Frustum {
+        let row = |i: usize| Vector4{x: m.x[i], y: m.y[i], z: m.z[i], w: m.w[i]};
+        let (r0, r1, r2, r3) = (row(0), row(1), row(2), row(3));
+
+        let mut planes = [r3 + r0, r3 - r0, r3 + r1, r3 - r1, r3 + r2, r3 - r2];
+        for p in planes.iter_mut() {
+            let len = p.truncate().magnitude();
+            // an infinite far plane has no normal, nothing is behind it
+            *p = if len > 0.0 { *p / len } else { Vector4{x: 0.0, y: 0.0, z: 0.0, w: 1.0} };
+        }
+        Frustum{ planes }
+    }
+
+    pub fn intersects_sphere(&self, sphere: &BoundingSphere) -> bool {
+        self.planes.iter().all(|p| p.truncate().dot(sphere.center) + p.w >= -sphere.radius)
+    }
+
+    pub fn intersects_aabb(&self, aabb: &Aabb) -> bool {
+        self.planes.iter().all(|p| {
+            // corner furthest along the plane normal
+            let v = Vector3{
+                x: if p.x >= 0.0 { aabb.max.x } else { aabb.min.x },
+                y: if p.y >= 0.0 { aabb.max.y } else { aabb.min.y },
+                z: if p.z >= 0.0 { aabb.max.z } else { aabb.min.z },
+            };
+            p.truncate().dot(v) + p.w >= 0.0
+        })
+    }
+
+    pub fn intersects(&self, bounds: &Bounds) -> bool {
+        self.intersects_sphere(&bounds.sphere) && self.intersects_aabb(&bounds.aabb)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use cgmath::{Deg,Point3,SquareMatrix};
+
+    // 90 degrees wide from the origin down -z, at z = -10 the sides are at x and y = +-10
+    fn frustum() -> Frustum {
+        let view = Matrix4::look_at(Point3::new(0.0, 0.0, 0.0), Point3::new(0.0, 0.0, -1.0), Vector3::unit_y());
+        Frustum::from_matrix(cgmath::perspective(Deg(90.0), 1.0, 0.1, 100.0) * view)
+    }
+
+    fn cube(center: Vector3<f32>, half: f32) -> Aabb {
+        Aabb{ min: center - Vector3::new(half, half, half), max: center + Vector3::new(half, half, half) }
+    }
+
+    fn sphere(x: f32, y: f32, z: f32, radius: f32) -> BoundingSphere {
+        BoundingSphere{ center: Vector3::new(x, y, z), radius }
+    }
+
+    #[test]
+    fn planes_are_normalized_and_face_inwards() {
+        let frustum = frustum();
+        let inside = Vector3::new(0.0, 0.0, -10.0);
+        for p in frustum.planes.iter() {
+            assert!((p.truncate().magnitude() - 1.0).abs() < 1e-5);
+            assert!(p.truncate().dot(inside) + p.w > 0.0);
+        }
+        // near and far are at their distances
+        let near = frustum.planes[4];
+        let far = frustum.planes[5];
+        assert!((near.truncate().dot(Vector3::new(0.0, 0.0, -0.1)) + near.w).abs() < 1e-4);
+        assert!((far.truncate().dot(Vector3::new(0.0, 0.0, -100.0)) + far.w).abs() < 1e-2);
+    }
+
+    #[test]
+    fn spheres_are_culled_against_every_plane() {
+        let frustum = frustum();
+        assert!(frustum.intersects_sphere(&sphere(0.0, 0.0, -10.0, 1.0)));
+        assert!(!frustum.intersects_sphere(&sphere(15.0, 0.0, -10.0, 1.0)));
+        assert!(!frustum.intersects_sphere(&sphere(0.0, -15.0, -10.0, 1.0)));
+        assert!(!frustum.intersects_sphere(&sphere(0.0, 0.0, -200.0, 1.0)));
+        // across the left plane, the center is outside
+        assert!(frustum.intersects_sphere(&sphere(-10.5, 0.0, -10.0, 1.0)));
+        // behind the camera, one reaching past the near plane
+        assert!(frustum.intersects_sphere(&sphere(0.0, 0.0, 0.5, 1.0)));
+        assert!(!frustum.intersects_sphere(&sphere(0.0, 0.0, 5.0, 1.0)));
+    }
+
+    #[test]
+    fn boxes_are_culled_against_every_plane() {
+        let frustum = frustum();
+        assert!(frustum.intersects_aabb(&cube(Vector3::new(0.0, 0.0, -10.0), 1.0)));
+        assert!(!frustum.intersects_aabb(&cube(Vector3::new(15.0, 0.0, -10.0), 1.0)));
+        assert!(!frustum.intersects_aabb(&cube(Vector3::new(0.0, 15.0, -10.0), 1.0)));
+        assert!(!frustum.intersects_aabb(&cube(Vector3::new(0.0, 0.0, -200.0), 1.0)));
+        assert!(frustum.intersects_aabb(&cube(Vector3::new(10.5, 0.0, -10.0), 1.0)));
+        // around the camera, and one wholly behind it
+        assert!(frustum.intersects_aabb(&cube(Vector3::new(0.0, 0.0, 0.0), 1.0)));
+        assert!(!frustum.intersects_aabb(&cube(Vector3::new(0.0, 0.0, 5.0), 1.0)));
+        let bounds = Bounds::from_aabb(cube(Vector3::new(0.0, 0.0, 5.0), 1.0));
+        assert!(!frustum.intersects(&bounds));
+    }
+
+    #[test]
+    fn transformed_boxes_hold_the_transformed_corners() {
+        let aabb = cube(Vector3::new(0.0, 0.0, 0.0), 1.0);
+        let m = Matrix4::from_translation(Vector3::new(1.0, 2.0, 3.0)) * Matrix4::from_angle_y(Deg(45.0)) * Matrix4::from_scale(2.0);
+        let moved = aabb.transformed(&m);
+        let reach = 2.0 * 2f32.sqrt();
+        assert!((moved.min - Vector3::new(1.0 - reach, 0.0, 3.0 - reach)).magnitude() < 1e-5, "{:?}", moved);
+        assert!((moved.max - Vector3::new(1.0 + reach, 4.0, 3.0 + reach)).magnitude() < 1e-5, "{:?}", moved);
+        for c in aabb.corners().iter() {
+            let p = (m * c.extend(1.0)).truncate();
+            assert!((0..3).all(|i| p[i] >= moved.min[i] - 1e-5 && p[i] <= moved.max[i] + 1e-5));
+        }
+
+        let same = aabb.transformed(&Matrix4::identity());
+        assert_eq!((same.min, same.max), (aabb.min, aabb.max));
+    }
+}
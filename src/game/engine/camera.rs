@@ -0,0 +1,320 @@
+use cgmath::{Point3,Vector3,InnerSpace,EuclideanSpace};
+use crate::my_game_engine::my_game_logic::my_bounds::Aabb;
+use crate::my_game_engine::my_game_logic::my_renderer::{Cam,ModelRst};
+use crate::my_game_engine::my_game_logic::my_camera_path::PathPlayer;
+
+// 1 - e^(-rate * dt), the share of the remaining distance covered in dt seconds independent of the frame rate
+fn smoothing(rate: f32, dt: f32) -> f32 {
+    1.0 - (-rate * dt).exp()
+}
+
+// the movement of the free fly camera. the velocity eases towards the wanted one instead of
+// jumping to it, faster when speeding up than when coming to a stop
+pub struct FreeFly {
+    // world units per ms
+    pub walk_speed: f32,
+    pub sprint_speed: f32,
+    // how fast the velocity follows the input, per second
+    pub acceleration: f32,
+    pub deceleration: f32,
+    velocity: Vector3<f32>,
+}
+
+impl Default for FreeFly {
+    fn default() -> FreeFly {
+        FreeFly::new()
+    }
+}
+
+impl FreeFly {
+    pub fn new() -> FreeFly {
+        FreeFly{
+            walk_speed: 0.001,
+            sprint_speed: 0.005,
+            acceleration: 12.0,
+            deceleration: 8.0,
+            velocity: Vector3::new(0.0, 0.0, 0.0),
+        }
+    }
+
+    // input as for Cam::move_dir, at most 1 long so analog sticks can move slower than walking
+    pub fn update(&mut self, cam: &mut Cam, input: Vector3<f32>, sprint: bool, dt: &f32) {
+        let speed = if sprint { self.sprint_speed } else { self.walk_speed };
+        let wanted = cam.move_dir(input) * speed;
+        let rate = if wanted.magnitude2() > 0.0 { self.acceleration } else { self.deceleration };
+        self.velocity += (wanted - self.velocity) * smoothing(rate, *dt / 1000.0);
+        cam.pos += self.velocity * *dt;
+    }
+}
+
+// the camera orbits a point, looking at it from distance along its view direction
+pub struct Orbit {
+    pub target: Point3<f32>,
+    pub distance: f32,
+    pub min_distance: f32,
+    pub max_distance: f32,
+    // world units per second at distance 1, panning gets faster further out
+    pub pan_speed: f32,
+}
+
+impl Orbit {
+    pub fn new(target: Point3<f32>, distance: f32) -> Orbit {
+        Orbit{
+            target,
+            distance,
+            min_distance: 0.5,
+            max_distance: 100.0,
+            pan_speed: 0.5,
+        }
+    }
+}
+
+// third person follow, the arm reaches back from a pivot above the target along the view direction.
+// it is pulled in where it would end inside a collider and eases back out once free
+pub struct SpringArm {
+    pub length: f32,
+    pub height: f32,
+    // how fast the pivot catches up with the target and the arm extends, per second
+    pub stiffness: f32,
+    // kept between the camera and whatever the arm hit
+    pub margin: f32,
+    pub min_length: f32,
+    current_length: f32,
+    pivot: Option<Point3<f32>>,
+}
+
+impl SpringArm {
+    pub fn new(length: f32, height: f32) -> SpringArm {
+        SpringArm{
+            length,
+            height,
+            stiffness: 8.0,
+            margin: 0.2,
+            min_length: 0.3,
+            current_length: length,
+            pivot: None,
+        }
+    }
+
+    // the first hit along the arm, shortened by the margin
+    fn clear_length(&self, pivot: Point3<f32>, dir: Vector3<f32>, colliders: &[Aabb]) -> f32 {
+        // a collider the pivot is inside of is hit at 0 and does not hold the arm back
+        let hit = colliders.iter()
+            .filter_map(|c| c.ray_distance(pivot.to_vec(), dir))
+            .filter(|&t| t > 0.0)
+            .fold(f32::MAX, f32::min);
+        (hit - self.margin).max(self.min_length).min(self.length)
+    }
+}
+
+// how the camera is moved, switched at runtime with next()
+pub enum CameraController {
+    // moved by the movement input, see steer
+    FreeFly(FreeFly),
+    Orbit(Orbit),
+    Follow(SpringArm),
+    // scripted, see CameraPath
+    Path(PathPlayer),
+}
+
+impl CameraController {
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            CameraController::FreeFly(_) => "free fly",
+            CameraController::Orbit(_) => "orbit",
+            CameraController::Follow(_) => "follow",
+            CameraController::Path(_) => "path",
+        }
+    }
+
+    // free fly -> orbit around the point in front of the camera -> follow -> free fly, a path is left for free fly.
+    // each starts where the camera is, follow with its pivot in front of it so it eases over to the target
+    pub fn next(&self, cam: &Cam) -> CameraController {
+        match self {
+            CameraController::FreeFly(_) => CameraController::Orbit(Orbit::new(cam.pos + cam.forward_v() * 5.0, 5.0)),
+            CameraController::Orbit(_) => {
+                let mut arm = SpringArm::new(4.0, 1.5);
+                arm.pivot = Some(cam.pos + cam.forward_v() * arm.length);
+                CameraController::Follow(arm)
+            },
+            CameraController::Follow(_) | CameraController::Path(_) => CameraController::FreeFly(FreeFly::new()),
+        }
+    }
+
+    // steps of the mouse wheel, positive moves closer
+    pub fn zoom(&mut self, steps: f32) {
+        match self {
+            CameraController::Orbit(orbit) => {
+                orbit.distance = (orbit.distance * 0.9f32.powf(steps)).max(orbit.min_distance).min(orbit.max_distance);
+            },
+            CameraController::Follow(arm) => {
+                arm.length = (arm.length * 0.9f32.powf(steps)).max(arm.min_length + arm.margin);
+            },
+            CameraController::FreeFly(_) | CameraController::Path(_) => (),
+        }
+    }
+
+    // the movement input as for Cam::move_dir. free fly moves the camera with it, orbit pans
+    // its target in the camera plane, right with x and up with z, kept level
+    pub fn steer(&mut self, cam: &mut Cam, input: Vector3<f32>, sprint: bool, dt: &f32) {
+        match self {
+            CameraController::FreeFly(fly) => fly.update(cam, input, sprint, dt),
+            CameraController::Orbit(orbit) => {
+                let right = Vector3::new(cam.right_v().x, 0.0, cam.right_v().z);
+                let right = if right.magnitude2() > 0.0 { right.normalize() } else { right };
+                let step = orbit.pan_speed * orbit.distance * *dt / 1000.0;
+                orbit.target += right * input.x * step + Vector3::unit_y() * input.z * step;
+            },
+            CameraController::Follow(_) | CameraController::Path(_) => (),
+        }
+    }
+
+    // places the camera for this frame. dt in ms like the rest of the camera, the target is
+    // what follow tracks and colliders what its arm can not pass through
+    pub fn update(&mut self, cam: &mut Cam, dt: &f32, target: Option<&ModelRst>, colliders: &[Aabb]) {
+        match self {
+            CameraController::FreeFly(_) => (),
+            CameraController::Orbit(orbit) => {
+                cam.pos = orbit.target - cam.forward_v() * orbit.distance;
+            },
+            CameraController::Follow(arm) => {
+                let target = match target {
+                    Some(t) => t.position() + Vector3::unit_y() * arm.height,
+                    None => return,
+                };
+                let t = smoothing(arm.stiffness, *dt / 1000.0);
+                let pivot = match arm.pivot {
+                    Some(p) => p + (target - p) * t,
+                    None => target,
+                };
+                arm.pivot = Some(pivot);
+
+                let back = -cam.forward_v();
+                let clear = arm.clear_length(pivot, back, colliders);
+                // pulled in at once so the view never ends up inside something, extended smoothly
+                arm.current_length = if clear < arm.current_length {
+                    clear
+                } else {
+                    arm.current_length + (clear - arm.current_length) * t
+                };
+                cam.pos = pivot + back * arm.current_length;
+            },
+            CameraController::Path(player) => player.update(cam, dt),
+        }
+    }
+
+    // how far the camera is from what it looks at, only orbit and follow look at something
+    pub fn distance(&self) -> Option<f32> {
+        match self {
+            CameraController::Orbit(orbit) => Some(orbit.distance),
+            CameraController::Follow(arm) => Some(arm.length),
+            CameraController::FreeFly(_) | CameraController::Path(_) => None,
+        }
+    }
+
+    // free fly places the camera in steer, the others in update
+    pub fn is_free(&self) -> bool {
+        matches!(self, CameraController::FreeFly(_))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use cgmath::{Matrix4,SquareMatrix};
+    use crate::my_game_engine::my_game_logic::my_renderer::Projection;
+
+    // at the origin looking down -z
+    fn cam() -> Cam {
+        Cam::new(Point3::new(0.0, 0.0, 0.0), Vector3::new(0.0, 0.0, -1.0), Projection::default_perspective())
+    }
+
+    fn target_at(p: Vector3<f32>) -> ModelRst {
+        ModelRst{ rotation: Matrix4::identity(), scale: Matrix4::identity(), translation: Matrix4::from_translation(p) }
+    }
+
+    fn distance(a: Point3<f32>, b: Point3<f32>) -> f32 {
+        (a - b).magnitude()
+    }
+
+    #[test]
+    fn the_spring_arm_is_pulled_in_and_recovers() {
+        let mut cam = cam();
+        let target = target_at(Vector3::new(0.0, 0.0, 0.0));
+        let mut follow = CameraController::Follow(SpringArm::new(4.0, 0.0));
+        // the arm reaches back along +z, the wall sits across it at z 2..3
+        let wall = Aabb{ min: Vector3::new(-5.0, -5.0, 2.0), max: Vector3::new(5.0, 5.0, 3.0) };
+
+        follow.update(&mut cam, &16.0, Some(&target), &[]);
+        assert!((cam.pos.z - 4.0).abs() < 1e-4, "{:?}", cam.pos);
+
+        // pulled in at once, the margin short of the wall
+        follow.update(&mut cam, &16.0, Some(&target), &[wall]);
+        assert!((cam.pos.z - 1.8).abs() < 1e-4, "{:?}", cam.pos);
+
+        // and eased back out once it is gone
+        follow.update(&mut cam, &16.0, Some(&target), &[]);
+        assert!(cam.pos.z > 1.8 && cam.pos.z < 4.0, "{:?}", cam.pos);
+        for _ in 0..200 {
+            follow.update(&mut cam, &16.0, Some(&target), &[]);
+        }
+        assert!((cam.pos.z - 4.0).abs() < 1e-3, "{:?}", cam.pos);
+
+        // a wall right at the pivot still leaves the minimum length
+        let close = Aabb{ min: Vector3::new(-5.0, -5.0, 0.1), max: Vector3::new(5.0, 5.0, 3.0) };
+        follow.update(&mut cam, &16.0, Some(&target), &[close]);
+        assert!((cam.pos.z - 0.3).abs() < 1e-4, "{:?}", cam.pos);
+    }
+
+    #[test]
+    fn orbit_zoom_is_clamped() {
+        let mut orbit = CameraController::Orbit(Orbit::new(Point3::new(0.0, 0.0, -5.0), 5.0));
+        orbit.zoom(1.0);
+        assert!((orbit.distance().unwrap() - 4.5).abs() < 1e-5);
+        orbit.zoom(1000.0);
+        assert_eq!(orbit.distance(), Some(0.5));
+        orbit.zoom(-1000.0);
+        assert_eq!(orbit.distance(), Some(100.0));
+
+        let mut follow = CameraController::Follow(SpringArm::new(4.0, 1.5));
+        follow.zoom(1000.0);
+        assert!((follow.distance().unwrap() - 0.5).abs() < 1e-5);
+    }
+
+    #[test]
+    fn orbit_pans_its_target_level_in_the_camera_plane() {
+        let mut cam = cam();
+        cam.look_dir(Vector3::new(0.0, -1.0, -1.0));
+        let mut orbit = CameraController::Orbit(Orbit::new(Point3::new(0.0, 0.0, -5.0), 2.0));
+
+        // a second to the right moves pan_speed * distance along the level right vector
+        orbit.steer(&mut cam, Vector3::new(1.0, 0.0, 0.0), false, &1000.0);
+        // and up with z
+        orbit.steer(&mut cam, Vector3::new(0.0, 0.0, 1.0), false, &500.0);
+        match orbit {
+            CameraController::Orbit(ref o) => assert!(distance(o.target, Point3::new(1.0, 0.5, -5.0)) < 1e-5, "{:?}", o.target),
+            _ => unreachable!(),
+        }
+        orbit.update(&mut cam, &16.0, None, &[]);
+        assert!(distance(cam.pos + cam.forward_v() * 2.0, Point3::new(1.0, 0.5, -5.0)) < 1e-5);
+    }
+
+    #[test]
+    fn switching_controllers_keeps_the_camera_in_place() {
+        let mut cam = cam();
+        cam.pos = Point3::new(1.0, 2.0, 3.0);
+        let target = target_at(Vector3::new(10.0, 0.0, -10.0));
+
+        let mut controller = CameraController::FreeFly(FreeFly::new());
+        for name in ["orbit", "follow", "free fly"].iter() {
+            controller = controller.next(&cam);
+            assert_eq!(controller.name(), *name);
+            let before = cam.pos;
+            controller.update(&mut cam, &16.0, Some(&target), &[]);
+            // follow eases over to its target, the first frame only covers a small part of the way
+            let allowed = if *name == "follow" { 0.2 * distance(before, target.position()) } else { 1e-4 };
+            assert!(distance(cam.pos, before) < allowed, "switching to {} moved the camera from {:?} to {:?}", name, before, cam.pos);
+        }
+    }
+}
@@ -0,0 +1,386 @@
+use cgmath::{Point3,Vector3,InnerSpace,EuclideanSpace};
+use crate::my_game_engine::my_game_logic::my_renderer::{Cam,Projection};
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Interpolation {
+    // through every key, the handles are ignored
+    CatmullRom,
+    // cubic segments from each key along its out handle into the next one against its in handle
+    Bezier,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Easing {
+    Linear,
+    EaseIn,
+    EaseOut,
+    EaseInOut,
+}
+
+impl Easing {
+    pub fn apply(&self, t: f32) -> f32 {
+        let t = t.clamp(0.0, 1.0);
+        match self {
+            Easing::Linear => t,
+            Easing::EaseIn => t * t,
+            Easing::EaseOut => t * (2.0 - t),
+            Easing::EaseInOut => t * t * (3.0 - 2.0 * t),
+        }
+    }
+
+    fn parse(name: &str) -> Option<Easing> {
+        match name {
+            "linear" => Some(Easing::Linear),
+            "ease_in" => Some(Easing::EaseIn),
+            "ease_out" => Some(Easing::EaseOut),
+            "ease_in_out" => Some(Easing::EaseInOut),
+            _ => None,
+        }
+    }
+}
+
+// times are in seconds from the start of the path
+#[derive(Copy, Clone, Debug)]
+pub struct PathKey {
+    pub time: f32,
+    pub position: Point3<f32>,
+    // relative to position, only used by bezier paths
+    pub in_handle: Vector3<f32>,
+    pub out_handle: Vector3<f32>,
+}
+
+#[derive(Copy, Clone, Debug)]
+pub struct LookKey {
+    pub time: f32,
+    pub target: Point3<f32>,
+}
+
+#[derive(Copy, Clone, Debug)]
+pub struct FovKey {
+    pub time: f32,
+    // radians
+    pub fov: f32,
+}
+
+#[derive(Clone, Debug)]
+pub struct PathEvent {
+    pub time: f32,
+    pub name: String,
+}
+
+// a scripted camera move. without look keys the camera looks along the path, without fov keys
+// the projection is left alone. the easing remaps the time of the whole path
+#[derive(Clone, Debug)]
+pub struct CameraPath {
+    pub interpolation: Interpolation,
+    pub easing: Easing,
+    pub looping: bool,
+    pub keys: Vec<PathKey>,
+    pub look_keys: Vec<LookKey>,
+    pub fov_keys: Vec<FovKey>,
+    pub events: Vec<PathEvent>,
+}
+
+// the key before t and how far t is towards the next one, keys have to be sorted by time
+fn segment<K, F: Fn(&K) -> f32>(keys: &[K], time: F, t: f32) -> (usize, f32) {
+    let next = keys.iter().position(|k| time(k) > t).unwrap_or(keys.len());
+    if next == 0 {
+        return (0, 0.0);
+    }
+    if next == keys.len() {
+        return (keys.len() - 1, 0.0);
+    }
+    let (t0, t1) = (time(&keys[next - 1]), time(&keys[next]));
+    (next - 1, if t1 > t0 { (t - t0) / (t1 - t0) } else { 0.0 })
+}
+
+fn catmull_rom(p0: Vector3<f32>, p1: Vector3<f32>, p2: Vector3<f32>, p3: Vector3<f32>, u: f32) -> Vector3<f32> {
+    let (u2, u3) = (u * u, u * u * u);
+    (p1 * 2.0 + (p2 - p0) * u + (p0 * 2.0 - p1 * 5.0 + p2 * 4.0 - p3) * u2 + (p1 * 3.0 - p0 - p2 * 3.0 + p3) * u3) * 0.5
+}
+
+fn bezier(p0: Vector3<f32>, c0: Vector3<f32>, c1: Vector3<f32>, p1: Vector3<f32>, u: f32) -> Vector3<f32> {
+    let v = 1.0 - u;
+    p0 * (v * v * v) + c0 * (3.0 * v * v * u) + c1 * (3.0 * v * u * u) + p1 * (u * u * u)
+}
+
+impl CameraPath {
+
+    pub fn new(interpolation: Interpolation) -> CameraPath {
+        CameraPath{
+            interpolation,
+            easing: Easing::Linear,
+            looping: false,
+            keys: Vec::new(),
+            look_keys: Vec::new(),
+            fov_keys: Vec::new(),
+            events: Vec::new(),
+        }
+    }
+
+    // one statement per line, # starts a comment. times in seconds, fov in degrees
+    //   interpolation catmull_rom|bezier
+    //   easing linear|ease_in|ease_out|ease_in_out
+    //   loop
+    //   key <time> <x> <y> <z> [<in x> <in y> <in z> <out x> <out y> <out z>]
+    //   look <time> <x> <y> <z>
+    //   fov <time> <degrees>
+    //   event <time> <name>
+    pub fn load(path: &str) -> Result<CameraPath, String> {
+        let text = std::fs::read_to_string(path).map_err(|e| format!("{}: {}", path, e))?;
+        let mut camera_path = CameraPath::new(Interpolation::CatmullRom);
+
+        for (i, line) in text.lines().enumerate() {
+            let line = line.split('#').next().unwrap_or("").trim();
+            if line.is_empty() {
+                continue;
+            }
+            let error = |what: &str| format!("{}:{}: {}", path, i + 1, what);
+            let words: Vec<&str> = line.split_whitespace().collect();
+            let numbers = |from: usize| -> Result<Vec<f32>, String> {
+                words[from..].iter().map(|w| w.parse::<f32>().map_err(|_| error(&format!("not a number: {}", w)))).collect()
+            };
+
+            match words[0] {
+                "interpolation" => camera_path.interpolation = match words.get(1) {
+                    Some(&"catmull_rom") => Interpolation::CatmullRom,
+                    Some(&"bezier") => Interpolation::Bezier,
+                    _ => return Err(error("expected catmull_rom or bezier")),
+                },
+                "easing" => camera_path.easing = words.get(1).and_then(|w| Easing::parse(w)).ok_or_else(|| error("unknown easing"))?,
+                "loop" => camera_path.looping = true,
+                "key" => {
+                    let n = numbers(1)?;
+                    let zero = Vector3::new(0.0, 0.0, 0.0);
+                    let (in_handle, out_handle) = match n.len() {
+                        4 => (zero, zero),
+                        10 => (Vector3::new(n[4], n[5], n[6]), Vector3::new(n[7], n[8], n[9])),
+                        _ => return Err(error("key needs a time, a position and optionally two handles")),
+                    };
+                    camera_path.keys.push(PathKey { time: n[0], position: Point3::new(n[1], n[2], n[3]), in_handle, out_handle });
+                },
+                "look" => {
+                    let n = numbers(1)?;
+                    if n.len() != 4 {
+                        return Err(error("look needs a time and a position"));
+                    }
+                    camera_path.look_keys.push(LookKey { time: n[0], target: Point3::new(n[1], n[2], n[3]) });
+                },
+                "fov" => {
+                    let n = numbers(1)?;
+                    if n.len() != 2 {
+                        return Err(error("fov needs a time and an angle"));
+                    }
+                    camera_path.fov_keys.push(FovKey { time: n[0], fov: n[1].to_radians() });
+                },
+                "event" => {
+                    if words.len() != 3 {
+                        return Err(error("event needs a time and a name"));
+                    }
+                    let time = words[1].parse::<f32>().map_err(|_| error(&format!("not a number: {}", words[1])))?;
+                    camera_path.events.push(PathEvent { time, name: words[2].to_string() });
+                },
+                other => return Err(error(&format!("unknown statement {}", other))),
+            }
+        }
+
+        if camera_path.keys.is_empty() {
+            return Err(format!("{}: no keys", path));
+        }
+        camera_path.sort();
+        Ok(camera_path)
+    }
+
+    pub fn sort(&mut self) {
+        let by_time = |a: f32, b: f32| a.partial_cmp(&b).unwrap_or(std::cmp::Ordering::Equal);
+        self.keys.sort_by(|a, b| by_time(a.time, b.time));
+        self.look_keys.sort_by(|a, b| by_time(a.time, b.time));
+        self.fov_keys.sort_by(|a, b| by_time(a.time, b.time));
+        self.events.sort_by(|a, b| by_time(a.time, b.time));
+    }
+
+    pub fn duration(&self) -> f32 {
+        self.keys.last().map_or(0.0, |k| k.time)
+    }
+
+    // the time the keys and events are looked up at, t seconds after the start
+    pub fn eased_time(&self, t: f32) -> f32 {
+        let duration = self.duration();
+        if duration > 0.0 { self.easing.apply(t / duration) * duration } else { 0.0 }
+    }
+
+    pub fn position(&self, t: f32) -> Point3<f32> {
+        if self.keys.is_empty() {
+            return Point3::new(0.0, 0.0, 0.0);
+        }
+        let (i, u) = segment(&self.keys, |k| k.time, t);
+        if i + 1 >= self.keys.len() {
+            return self.keys[i].position;
+        }
+        let (k1, k2) = (&self.keys[i], &self.keys[i + 1]);
+        let p = match self.interpolation {
+            Interpolation::CatmullRom => {
+                // the end keys are repeated for the missing neighbours
+                let k0 = &self.keys[i.saturating_sub(1)];
+                let k3 = &self.keys[(i + 2).min(self.keys.len() - 1)];
+                catmull_rom(k0.position.to_vec(), k1.position.to_vec(), k2.position.to_vec(), k3.position.to_vec(), u)
+            },
+            Interpolation::Bezier => {
+                bezier(k1.position.to_vec(), (k1.position + k1.out_handle).to_vec(), (k2.position + k2.in_handle).to_vec(), k2.position.to_vec(), u)
+            },
+        };
+        Point3::from_vec(p)
+    }
+
+    pub fn look_target(&self, t: f32) -> Option<Point3<f32>> {
+        if self.look_keys.is_empty() {
+            return None;
+        }
+        let (i, u) = segment(&self.look_keys, |k| k.time, t);
+        let a = self.look_keys[i].target;
+        let b = self.look_keys[(i + 1).min(self.look_keys.len() - 1)].target;
+        Some(a + (b - a) * u)
+    }
+
+    pub fn fov(&self, t: f32) -> Option<f32> {
+        if self.fov_keys.is_empty() {
+            return None;
+        }
+        let (i, u) = segment(&self.fov_keys, |k| k.time, t);
+        let a = self.fov_keys[i].fov;
+        let b = self.fov_keys[(i + 1).min(self.fov_keys.len() - 1)].fov;
+        Some(a + (b - a) * u)
+    }
+}
+
+// plays a path on a camera
+pub struct PathPlayer {
+    pub path: CameraPath,
+    // seconds since the start, before easing
+    pub time: f32,
+    pub speed: f32,
+    // names of the events passed since the last drain
+    pub fired: Vec<String>,
+}
+
+impl PathPlayer {
+
+    pub fn new(path: CameraPath) -> PathPlayer {
+        PathPlayer{
+            path,
+            time: 0.0,
+            speed: 1.0,
+            fired: Vec::new(),
+        }
+    }
+
+    // the events in from..to, from itself only if included
+    fn fire(&mut self, from: f32, to: f32, include_from: bool) {
+        for event in self.path.events.iter() {
+            if (event.time > from || (include_from && event.time >= from)) && event.time <= to {
+                self.fired.push(event.name.clone());
+            }
+        }
+    }
+
+    pub fn finished(&self) -> bool {
+        !self.path.looping && self.time >= self.path.duration()
+    }
+
+    // dt in ms like the rest of the camera
+    pub fn update(&mut self, cam: &mut Cam, dt: &f32) {
+        let duration = self.path.duration();
+        let previous = self.time;
+        self.time += *dt / 1000.0 * self.speed;
+
+        // events are on the eased time like the keys, so they fire where the camera is when it gets
+        // there. a loop fires the ones it passed at the end, the first step also fires the ones at 0
+        let wrapped = self.path.looping && duration > 0.0 && self.time >= duration;
+        let end = if wrapped { duration } else { self.time.min(duration) };
+        self.fire(self.path.eased_time(previous), self.path.eased_time(end), previous <= 0.0);
+        if wrapped {
+            self.time -= duration;
+            self.fire(0.0, self.path.eased_time(self.time), true);
+        }
+        self.time = self.time.min(duration);
+
+        let t = self.path.eased_time(self.time);
+        let position = self.path.position(t);
+        cam.pos = position;
+        match self.path.look_target(t) {
+            Some(target) => cam.look_at_point(target),
+            None => {
+                // along the path, looking back from the end so the last key still has a direction
+                let step = 0.01;
+                let ahead = if t + step <= duration { self.path.position(t + step) - position } else { position - self.path.position(t - step) };
+                if ahead.magnitude2() > 1e-10 {
+                    cam.look_dir(ahead);
+                }
+            },
+        }
+        if let Some(fov) = self.path.fov(t) {
+            if let Projection::Perspective { near, far, infinite, .. } = cam.projection {
+                cam.set_projection(Projection::Perspective { fov, near, far, infinite });
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn path(easing: Easing, looping: bool) -> CameraPath {
+        let mut path = CameraPath::new(Interpolation::CatmullRom);
+        path.easing = easing;
+        path.looping = looping;
+        for (i, x) in [0.0, 1.0, 2.0].iter().enumerate() {
+            let zero = Vector3::new(0.0, 0.0, 0.0);
+            path.keys.push(PathKey { time: i as f32, position: Point3::new(*x, 0.0, 0.0), in_handle: zero, out_handle: zero });
+        }
+        path.events.push(PathEvent { time: 0.0, name: "start".to_string() });
+        path.events.push(PathEvent { time: 1.0, name: "middle".to_string() });
+        path
+    }
+
+    fn cam() -> Cam {
+        Cam::new(Point3::new(0.0, 0.0, 0.0), Vector3::new(0.0, 0.0, -1.0), Projection::default_perspective())
+    }
+
+    // the raw time in seconds the event fired at, stepping 10 ms
+    fn fired_at(player: &mut PathPlayer, name: &str) -> Option<f32> {
+        let mut cam = cam();
+        for _ in 0..400 {
+            player.update(&mut cam, &10.0);
+            if player.fired.drain(..).any(|n| n == name) {
+                return Some(player.time);
+            }
+        }
+        None
+    }
+
+    #[test]
+    fn events_fire_on_the_eased_time() {
+        let mut player = PathPlayer::new(path(Easing::EaseIn, false));
+        // ease in reaches half of the path after sqrt(0.5) of the time
+        let at = fired_at(&mut player, "middle").unwrap();
+        assert!((at - 2.0 * 0.5f32.sqrt()).abs() < 0.011, "fired at {}", at);
+
+        let mut cam = cam();
+        let mut player = PathPlayer::new(path(Easing::EaseIn, false));
+        player.update(&mut cam, &10.0);
+        assert_eq!(player.fired, vec!["start".to_string()]);
+    }
+
+    #[test]
+    fn looping_fires_every_round() {
+        let mut player = PathPlayer::new(path(Easing::EaseInOut, true));
+        let mut cam = cam();
+        let mut fired = Vec::new();
+        for _ in 0..450 {
+            player.update(&mut cam, &10.0);
+            fired.append(&mut player.fired);
+        }
+        assert_eq!(fired, vec!["start", "middle", "start", "middle", "start"]);
+    }
+}
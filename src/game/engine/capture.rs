@@ -0,0 +1,85 @@
+use glium::texture::Texture2d;
+
+pub fn texture_to_image(texture: &Texture2d) -> image::RgbaImage {
+    let raw: glium::texture::RawImage2d<u8> = texture.read();
+    let image: image::RgbaImage = image::ImageBuffer::from_raw(raw.width, raw.height, raw.data.into_owned()).unwrap();
+    // gl rows start at the bottom
+    image::imageops::flip_vertical(&image)
+}
+
+pub fn screenshot_path() -> String {
+    format!("./screenshot_{}.png", time::get_time().sec)
+}
+
+// the name typed into the capture window, only letters, digits, _ and - are kept so it can
+// not point outside the working directory. the timestamped path if nothing is left
+pub fn named_screenshot_path(name: &str) -> String {
+    let name: String = name.chars().filter(|c| c.is_ascii_alphanumeric() || *c == '_' || *c == '-').collect();
+    if name.is_empty() {
+        screenshot_path()
+    }else {
+        format!("./{}.png", name)
+    }
+}
+
+pub fn save_image(image: &image::RgbaImage, path: &str) {
+    match image.save(path) {
+        Ok(_) => println!("saved {}", path),
+        Err(e) => println!("could not save {}: {}", path, e),
+    }
+}
+
+// dumps every nth rendered frame as a numbered png, the simulation advances
+// by frame_mcs per rendered frame instead of wall clock time
+pub struct FrameRecorder {
+    pub directory: String,
+    pub every_nth: u64,
+    pub frame_mcs: i64,
+    frame: u64,
+    saved: u64,
+}
+
+impl FrameRecorder {
+    pub fn new(directory: &str, every_nth: u64, frame_mcs: i64) -> FrameRecorder {
+        std::fs::create_dir_all(directory).unwrap();
+        FrameRecorder{
+            directory: directory.to_string(),
+            every_nth: every_nth.max(1),
+            frame_mcs,
+            frame: 0,
+            saved: 0,
+        }
+    }
+
+    pub fn should_capture(&mut self) -> bool {
+        let capture = self.frame % self.every_nth == 0;
+        self.frame += 1;
+        capture
+    }
+
+    pub fn save(&mut self, image: &image::RgbaImage) {
+        let path = format!("{}/frame_{:06}.png", self.directory, self.saved);
+        if let Err(e) = image.save(&path) {
+            println!("could not save {}: {}", path, e);
+        }
+        self.saved += 1;
+    }
+
+    pub fn saved(&self) -> u64 {
+        self.saved
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn screenshot_names_stay_in_the_working_directory() {
+        assert_eq!(named_screenshot_path("shot_01-a"), "./shot_01-a.png");
+        assert_eq!(named_screenshot_path("../../etc/passwd"), "./etcpasswd.png");
+        assert_eq!(named_screenshot_path("C:\\temp\\x"), "./Ctempx.png");
+        assert!(named_screenshot_path("/..").starts_with("./screenshot_"));
+        assert!(named_screenshot_path("").starts_with("./screenshot_"));
+    }
+}
@@ -3,6 +3,8 @@ use glium::Surface;
 #[path = "../game_s.rs"]
 pub mod my_game_logic;
 
+use crate::my_game_engine::my_game_logic::my_capture::{self,FrameRecorder};
+
 
 pub struct CCGameEngine{
     pub event_loop: glutin::EventsLoop,
@@ -10,6 +12,8 @@ pub struct CCGameEngine{
     running: bool,
     game_logic: my_game_logic::CCGame,
     renderer: my_game_logic::my_renderer::Renderer,
+    screenshot_path: Option<String>,
+    recorder: Option<FrameRecorder>,
 }
 
 impl CCGameEngine {
@@ -22,9 +26,9 @@ impl CCGameEngine {
         let context = glutin::ContextBuilder::new()
             .with_depth_buffer(24)
             .with_vsync(vsync);
-        let mut display = glium::Display::new(window, context, &events_loop).unwrap();
+        let display = glium::Display::new(window, context, &events_loop).unwrap();
 
-        let renderer = my_game_logic::my_renderer::Renderer::new(&mut display);
+        let renderer = my_game_logic::my_renderer::Renderer::new(&display, &|symbol| glutin::GlContext::get_proc_address(&*display.gl_window(), symbol) as *const _);
 
 
         CCGameEngine{
@@ -33,11 +37,36 @@ impl CCGameEngine {
             running: true,
             game_logic: game,
             renderer: (renderer),
+            screenshot_path: None,
+            recorder: None,
+        }
+    }
+
+    // saved after the next rendered frame
+    pub fn screenshot(&mut self, path: &str){
+        self.screenshot_path = Some(path.to_string());
+    }
+
+    pub fn start_recording(&mut self, directory: &str, every_nth: u64, frame_mcs: i64){
+        println!("recording to {}", directory);
+        self.recorder = Some(FrameRecorder::new(directory, every_nth, frame_mcs));
+    }
+
+    pub fn stop_recording(&mut self){
+        if let Some(recorder) = self.recorder.take() {
+            println!("recorded {} frames to {}", recorder.saved(), recorder.directory);
         }
     }
 
     fn init(&mut self){
-        self.game_logic.init(&mut self.display);
+        self.game_logic.init(&self.display);
+        // a replay only matches its recording if the models arrive at the same tick
+        if !self.game_logic.session.is_live() {
+            self.game_logic.finish_loading(&self.display);
+        }
+        self.game_logic.ui.hidpi_factor = self.display.gl_window().window().get_hidpi_factor();
+        let (width, height) = self.display.get_framebuffer_dimensions();
+        self.game_logic.cam.set_viewport(width as f64, height as f64);
         self.display.gl_window().window().grab_cursor(true).unwrap();
         self.display.gl_window().window().hide_cursor(true);
     }
@@ -51,7 +80,7 @@ impl CCGameEngine {
         if !self.game_logic.running {
             self.running = false;
         }
-        self.game_logic.update(&dt);
+        self.game_logic.update(dt);
 
         if self.game_logic.mode_changed{
             if self.game_logic.ego_mode {
@@ -64,13 +93,48 @@ impl CCGameEngine {
             self.game_logic.mode_changed = false;
         }
 
+        if self.game_logic.screenshot_requested {
+            let path = my_capture::named_screenshot_path(&self.game_logic.screenshot_name);
+            self.screenshot(&path);
+            self.game_logic.screenshot_requested = false;
+        }
+        if self.game_logic.recording_toggled {
+            if self.recorder.is_some() {
+                self.stop_recording();
+            }else {
+                self.start_recording("./recording", 1, 1_000_000/60);
+            }
+            self.game_logic.recording_toggled = false;
+        }
+
     }
 
     fn render(&mut self){
+        let record_frame = match self.recorder {
+            Some(ref mut recorder) => recorder.should_capture(),
+            None => false,
+        };
+        self.renderer.capture_requested = self.screenshot_path.is_some() || record_frame;
+
         let mut target_frame = self.display.draw();
-        target_frame.clear_color_and_depth((1.0, 1.0, 0.0, 1.0), 1.0);
-        self.game_logic.render(&mut self.renderer,&mut target_frame,&mut self.display);
+        let game_logic = &mut self.game_logic;
+        let display = &self.display;
+        self.renderer.render_frame(display,&mut target_frame,|renderer,scene|{
+            scene.clear_color_and_depth(game_logic.clear_color, game_logic.cam.clear_depth());
+            game_logic.render(renderer,scene,display);
+        });
         target_frame.finish().unwrap();
+
+        if let Some(image) = self.renderer.captured.take() {
+            if let Some(path) = self.screenshot_path.take() {
+                my_capture::save_image(&image, &path);
+            }
+            if record_frame {
+                if let Some(ref mut recorder) = self.recorder {
+                    recorder.save(&image);
+                }
+            }
+        }
     }
 
     pub fn start(&mut self){
@@ -89,13 +153,13 @@ impl CCGameEngine {
             self.input();
 
             let current = PreciseTime::now();
-            let elapsed: i64;
-            match previous.to(current).num_microseconds() {
-                Some(x) => elapsed = x,
-                None => elapsed = std::i64::MAX,
-            }
+            let elapsed = previous.to(current).num_microseconds().unwrap_or(i64::MAX);
             previous = current;
-            lag += elapsed;
+            // while recording the simulation follows the recorded frame rate, not the wall clock
+            lag += match self.recorder {
+                Some(ref recorder) => recorder.frame_mcs,
+                None => elapsed,
+            };
 
             while lag >= mcs_per_update {
                 self.update(&(mcs_per_update as f32 / 1000.0));
@@ -105,16 +169,288 @@ impl CCGameEngine {
             let end = PreciseTime::now();
             fpsc+=1;
             if start.to(end).num_seconds()>=1{
-                println!("fps: {}",fpsc);
+                let stats = self.renderer.stats;
+                println!("fps: {} draw calls: {} culled: {}/{}",fpsc,stats.draw_calls,stats.culled,stats.submitted);
                 fpsc=0;
                 start = PreciseTime::now();
             }
-            if self.game_logic.ready_in_que.load(std::sync::atomic::Ordering::SeqCst) > 0  {
-                self.game_logic.load_que(&mut self.display);
-                self.game_logic.ready_in_que.fetch_sub(1,std::sync::atomic::Ordering::SeqCst);
-            }
+            self.game_logic.load_que(&self.display);
             self.render();
         }
+        self.game_logic.session.finish();
+    }
+}
+
+// renders without a window or display server through a surfaceless egl context, frames are
+// read back as images. needs mesa, llvmpipe is enough
+pub struct CCHeadlessEngine{
+    context: std::rc::Rc<glium::backend::Context>,
+    game_logic: my_game_logic::CCGame,
+    renderer: my_game_logic::my_renderer::Renderer,
+    dimensions: (u32, u32),
+}
+
+impl CCHeadlessEngine {
+
+    pub fn new(mut game: my_game_logic::CCGame, width: u32, height: u32) -> CCHeadlessEngine{
+        let context = my_game_logic::my_egl::SurfacelessContext::new((width, height)).unwrap();
+        let context = context.into_glium().unwrap();
+
+        let renderer = my_game_logic::my_renderer::Renderer::new(&context, &my_game_logic::my_egl::get_proc_address);
+
+        game.cam.set_viewport(width as f64, height as f64);
+
+        CCHeadlessEngine{
+            context,
+            game_logic: game,
+            renderer,
+            dimensions: (width, height),
+        }
+    }
+
+    // the frames only compare if every model is there before the first tick
+    pub fn init(&mut self){
+        self.game_logic.init(&self.context);
+        self.game_logic.finish_loading(&self.context);
+    }
+
+    // runs ticks fixed updates at the step of CCGameEngine::start, a replay is played this way
+    pub fn run_ticks(&mut self, ticks: u64){
+        for _ in 0..ticks {
+            self.update(&10.0);
+        }
+    }
+
+    pub fn update(&mut self, dt: &f32){
+        self.game_logic.update(dt);
+    }
+
+    pub fn render_to_image(&mut self) -> image::RgbaImage{
+        let game_logic = &mut self.game_logic;
+        let context = &self.context;
+        render_offscreen(context, &mut self.renderer, self.dimensions, |renderer,scene|{
+            scene.clear_color_and_depth(game_logic.clear_color, game_logic.cam.clear_depth());
+            game_logic.render(renderer,scene,context);
+        })
     }
 }
 
+// one frame through the whole renderer into a texture, read back top row first
+pub fn render_offscreen<F, D>(context: &F, renderer: &mut my_game_logic::my_renderer::Renderer, dimensions: (u32, u32), draw_scene: D) -> image::RgbaImage
+    where F: glium::backend::Facade, D: FnOnce(&mut my_game_logic::my_renderer::Renderer, &mut glium::framebuffer::SimpleFrameBuffer) {
+    use glium::texture::{Texture2d,UncompressedFloatFormat,MipmapsOption};
+
+    let (width, height) = dimensions;
+    let target = Texture2d::empty_with_format(context, UncompressedFloatFormat::U8U8U8U8, MipmapsOption::NoMipmap, width, height).unwrap();
+    {
+        let mut target_frame = glium::framebuffer::SimpleFrameBuffer::new(context, &target).unwrap();
+        renderer.render_frame(context, &mut target_frame, draw_scene);
+    }
+    my_capture::texture_to_image(&target)
+}
+
+// mean absolute channel difference in 0..1, None if the sizes differ
+pub fn image_difference(a: &image::RgbaImage, b: &image::RgbaImage) -> Option<f64>{
+    if a.dimensions() != b.dimensions() {
+        return None;
+    }
+    let total: u64 = a.pixels().zip(b.pixels())
+        .map(|(p, q)| p.data.iter().zip(q.data.iter()).map(|(x, y)| (*x as i64 - *y as i64).unsigned_abs()).sum::<u64>())
+        .sum();
+    Some(total as f64 / (a.width() as f64 * a.height() as f64 * 4.0 * 255.0))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use cgmath::{Matrix4,Point3,Vector3,InnerSpace};
+    use crate::my_game_engine::my_game_logic::my_egl;
+    use crate::my_game_engine::my_game_logic::my_loaders::loaders;
+    use crate::my_game_engine::my_game_logic::my_renderer::{Renderer,RenderQueue,Material,AlphaMode,Cam,Projection,ModelRst,AnimatedMesh};
+    use crate::my_game_engine::my_game_logic::my_skybox::Sky;
+
+    const SIZE: (u32, u32) = (320, 240);
+
+    fn camera() -> Cam {
+        let mut cam = Cam::new(Point3::new(1.0, 1.2, 3.5), Vector3::new(0.0, 0.0, -1.0), Projection::default_perspective());
+        cam.look_at_point(Point3::new(0.0, 0.6, 0.0));
+        cam.set_viewport(SIZE.0 as f64, SIZE.1 as f64);
+        cam
+    }
+
+    // compares with res/golden/<name>.png, UPDATE_GOLDEN=1 writes the reference instead
+    fn check_golden(name: &str, frame: &image::RgbaImage) {
+        let path = format!("./res/golden/{}.png", name);
+        if std::env::var("UPDATE_GOLDEN").is_ok() {
+            frame.save(&path).unwrap();
+            return;
+        }
+        let golden = image::open(&path).unwrap().to_rgba();
+        let difference = image_difference(frame, &golden);
+        if difference.map_or(true, |d| d > 0.01) {
+            frame.save(format!("./res/golden/{}_failed.png", name)).unwrap();
+            panic!("{} differs from the reference by {:?}, see res/golden/{}_failed.png", name, difference, name);
+        }
+    }
+
+    // with capture the frame read back by the renderer has to be the one it presented
+    fn render_scene<D>(name: &str, capture: bool, draw: D)
+        where D: FnOnce(&std::rc::Rc<glium::backend::Context>, &mut Renderer, &Cam, &mut glium::framebuffer::SimpleFrameBuffer) {
+        let Some(context) = my_egl::test_context(SIZE) else { return };
+        let mut renderer = Renderer::new(&context, &my_egl::get_proc_address);
+        renderer.capture_requested = capture;
+        let cam = camera();
+        let frame = render_offscreen(&context, &mut renderer, SIZE, |renderer, scene| {
+            scene.clear_color_and_depth((0.0, 0.0, 0.0, 1.0), cam.clear_depth());
+            draw(&context, renderer, &cam, scene);
+        });
+        check_golden(name, &frame);
+        if capture {
+            let captured = renderer.captured.take().unwrap();
+            assert_eq!(image_difference(&captured, &frame), Some(0.0), "the captured frame differs from the presented one");
+        }
+    }
+
+    fn draw_static(context: &std::rc::Rc<glium::backend::Context>, renderer: &mut Renderer, cam: &Cam, scene: &mut glium::framebuffer::SimpleFrameBuffer) {
+        let mut model = loaders::load_static_collada_mesh(context, "./res/cubeStackBendingRotating.dae");
+        model.0.scale = Matrix4::from_scale(0.2);
+        let sky = Sky::default_gradient();
+        let mut queue = RenderQueue::new();
+        queue.set_sky(&sky);
+        queue.submit_static(&model, Material::Flat);
+        renderer.draw_queue(scene, context, cam, &mut queue);
+    }
+
+    #[test]
+    fn static_scene() {
+        render_scene("static", false, draw_static);
+    }
+
+    #[test]
+    fn captured_scene() {
+        render_scene("static", true, draw_static);
+    }
+
+    #[test]
+    fn textured_scene() {
+        render_scene("textured", false, |context, renderer, cam, scene| {
+            let texture = loaders::load_texture(context, "./res/cubeTex.png");
+            let mut model = loaders::load_static_collada_mesh(context, "./res/cubeStackBendingRotating.dae");
+            model.0.scale = Matrix4::from_scale(0.2);
+            let mut queue = RenderQueue::new();
+            queue.submit_static(&model, Material::Textured(&texture));
+            renderer.draw_queue(scene, context, cam, &mut queue);
+        });
+    }
+
+    // at a fixed point of the clip, not wherever the clock is
+    fn posed_model(context: &std::rc::Rc<glium::backend::Context>) -> (ModelRst, AnimatedMesh) {
+        let mut model = loaders::load_animated_collada_mesh(context, "./res/untitled.dae");
+        model.0.scale = Matrix4::from_scale(0.2);
+        model.1.current_time_sec = 0.5;
+        model.1.calculate_current_pose();
+        model.1.update_joint_buffer();
+        model
+    }
+
+    #[test]
+    fn skinned_scene() {
+        render_scene("skinned", false, |context, renderer, cam, scene| {
+            let texture = loaders::load_texture(context, "./res/cubeTex.png");
+            let model = posed_model(context);
+            let mut queue = RenderQueue::new();
+            queue.submit_animated(&model, Material::Textured(&texture));
+            renderer.draw_queue(scene, context, cam, &mut queue);
+        });
+    }
+
+    #[test]
+    fn flat_skinned_scene() {
+        render_scene("skinned_flat", false, |context, renderer, cam, scene| {
+            let model = posed_model(context);
+            let mut queue = RenderQueue::new();
+            queue.submit_animated(&model, Material::Flat);
+            renderer.draw_queue(scene, context, cam, &mut queue);
+        });
+    }
+
+    // ridges along u, the normal tilts back and forth across them
+    #[test]
+    fn normal_mapped_scene() {
+        render_scene("normal_mapped", false, |context, renderer, cam, scene| {
+            let texture = loaders::load_texture(context, "./res/cubeTex.png");
+            let mut pixels: Vec<u8> = Vec::new();
+            for _ in 0..64 {
+                for x in 0..64 {
+                    let tilt = (x as f32 / 64.0 * std::f32::consts::PI * 8.0).sin() * 0.7;
+                    let normal = Vector3::new(tilt, 0.0, 1.0).normalize();
+                    pixels.extend_from_slice(&[((normal.x * 0.5 + 0.5) * 255.0) as u8, ((normal.y * 0.5 + 0.5) * 255.0) as u8, ((normal.z * 0.5 + 0.5) * 255.0) as u8, 255]);
+                }
+            }
+            let normals = glium::texture::Texture2d::new(context, glium::texture::RawImage2d::from_raw_rgba(pixels, (64, 64))).unwrap();
+            let mut model = loaders::load_static_collada_mesh(context, "./res/cubeStackBendingRotating.dae");
+            model.0.scale = Matrix4::from_scale(0.2);
+            let mut queue = RenderQueue::new();
+            queue.submit_static(&model, Material::NormalMapped(&texture, &normals));
+            renderer.draw_queue(scene, context, cam, &mut queue);
+        });
+    }
+
+    // a half transparent skinned mesh in front of the static one, resolved with oit
+    #[test]
+    fn transparent_scene() {
+        render_scene("transparent", false, |context, renderer, cam, scene| {
+            renderer.oit = true;
+            let texture = loaders::load_texture(context, "./res/cubeTex.png");
+            let mut background = loaders::load_static_collada_mesh(context, "./res/cubeStackBendingRotating.dae");
+            background.0.scale = Matrix4::from_scale(0.2);
+            background.0.translation = Matrix4::from_translation(Vector3::new(0.0, 0.0, -1.5));
+            let model = posed_model(context);
+            let mut queue = RenderQueue::new();
+            queue.submit_static(&background, Material::Flat);
+            queue.submit_animated_with_alpha(&model, Material::Textured(&texture), AlphaMode::Blend(0.5));
+            renderer.draw_queue(scene, context, cam, &mut queue);
+        });
+    }
+
+    // the depth goes the other way, what ends up in front and where the sky shows does not
+    #[test]
+    fn reversed_z_draws_the_same_scene() {
+        let Some(context) = my_egl::test_context(SIZE) else { return };
+        let mut renderer = Renderer::new(&context, &my_egl::get_proc_address);
+        let mut frame = |cam: &Cam| render_offscreen(&context, &mut renderer, SIZE, |renderer, scene| {
+            scene.clear_color_and_depth((0.0, 0.0, 0.0, 1.0), cam.clear_depth());
+            draw_static(&context, renderer, cam, scene);
+        });
+        let usual = frame(&camera());
+        let mut cam = camera();
+        cam.set_reversed_z(true);
+        let reversed = frame(&cam);
+        let difference = image_difference(&usual, &reversed).unwrap();
+        assert!(difference < 0.002, "the reversed z frame differs by {}", difference);
+    }
+
+    #[test]
+    fn the_sky_counts_as_a_draw_call() {
+        let Some(context) = my_egl::test_context(SIZE) else { return };
+        let mut renderer = Renderer::new(&context, &my_egl::get_proc_address);
+        let cam = camera();
+        let mut model = loaders::load_static_collada_mesh(&context, "./res/cubeStackBendingRotating.dae");
+        model.0.scale = Matrix4::from_scale(0.2);
+
+        let mut counts = Vec::new();
+        for sky in [None, Some(Sky::Color), Some(Sky::default_gradient())] {
+            render_offscreen(&context, &mut renderer, SIZE, |renderer, scene| {
+                let mut queue = RenderQueue::new();
+                if let Some(ref sky) = sky {
+                    queue.set_sky(sky);
+                }
+                queue.submit_static(&model, Material::Flat);
+                renderer.draw_queue(scene, &context, &cam, &mut queue);
+            });
+            counts.push((renderer.stats.draw_calls, renderer.stats.triangles));
+        }
+        assert_eq!(counts[1], counts[0]);
+        assert_eq!(counts[2], (counts[0].0 + 1, counts[0].1 + 12));
+    }
+}
@@ -0,0 +1,141 @@
+use crate::my_game_engine::my_game_logic::my_overlay::Overlay;
+use crate::my_game_engine::my_game_logic::my_text_input::{self,TextEvent};
+
+const LOG_LINES: usize = 100;
+const SHOWN_LINES: usize = 12;
+
+// a developer console over the top of the screen. it only collects lines, what they do is up to
+// the game, see CCGame::run_command. while open it has the keyboard
+pub struct Console {
+    pub open: bool,
+    pub line: String,
+    caret: usize,
+    // entered lines, oldest first, browsed with up and down
+    history: Vec<String>,
+    browsing: Option<usize>,
+    // printed lines, oldest first
+    log: Vec<String>,
+}
+
+impl Default for Console {
+    fn default() -> Console {
+        Console::new()
+    }
+}
+
+impl Console {
+
+    pub fn new() -> Console {
+        Console{
+            open: false,
+            line: String::new(),
+            caret: 0,
+            history: Vec::new(),
+            browsing: None,
+            log: Vec::new(),
+        }
+    }
+
+    pub fn print(&mut self, text: &str) {
+        for line in text.lines() {
+            self.log.push(line.to_string());
+        }
+        if self.log.len() > LOG_LINES {
+            let excess = self.log.len() - LOG_LINES;
+            self.log.drain(..excess);
+        }
+    }
+
+    // edits the line, returns the lines entered with enter. escape closes
+    pub fn handle(&mut self, text: &[TextEvent]) -> Vec<String> {
+        let mut entered = Vec::new();
+        if !self.open {
+            return entered;
+        }
+        for event in text {
+            match event {
+                TextEvent::Enter => {
+                    let line = std::mem::take(&mut self.line);
+                    self.caret = 0;
+                    self.browsing = None;
+                    if !line.trim().is_empty() {
+                        self.print(&format!("> {}", line));
+                        self.history.push(line.clone());
+                        entered.push(line);
+                    }
+                },
+                TextEvent::Escape => self.open = false,
+                TextEvent::Up | TextEvent::Down if !self.history.is_empty() => {
+                    let last = self.history.len() - 1;
+                    self.browsing = match (event, self.browsing) {
+                        (TextEvent::Up, None) => Some(last),
+                        (TextEvent::Up, Some(i)) => Some(i.saturating_sub(1)),
+                        (_, Some(i)) if i < last => Some(i + 1),
+                        _ => None,
+                    };
+                    self.line = self.browsing.map_or(String::new(), |i| self.history[i].clone());
+                    self.caret = self.line.chars().count();
+                },
+                _ => {
+                    my_text_input::edit(&mut self.line, &mut self.caret, event);
+                },
+            }
+        }
+        entered
+    }
+
+    // width in physical pixels like the overlay
+    pub fn draw(&self, overlay: &mut Overlay, width: f32) {
+        if !self.open {
+            return;
+        }
+        let line_height = overlay.font.line_height;
+        let height = line_height * (SHOWN_LINES + 1) as f32 + 12.0;
+        overlay.quad(0.0, 0.0, width, height, [0.0, 0.0, 0.0, 0.75]);
+
+        let first = self.log.len().saturating_sub(SHOWN_LINES);
+        let shown = self.log[first..].join("\n");
+        let top = 4.0 + line_height * (SHOWN_LINES - (self.log.len() - first)) as f32;
+        overlay.text(6.0, top, &shown, 1.0, [0.8, 0.8, 0.8, 1.0]);
+        overlay.text(6.0, height - line_height - 6.0, &format!("] {}", my_text_input::with_caret(&self.line, self.caret)), 1.0, [1.0, 1.0, 1.0, 1.0]);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn typed(line: &str) -> Vec<TextEvent> {
+        line.chars().map(TextEvent::Char).chain(std::iter::once(TextEvent::Enter)).collect()
+    }
+
+    #[test]
+    fn history_is_browsed_with_up_and_down() {
+        let mut console = Console::new();
+        assert!(console.handle(&typed("ignored")).is_empty(), "a closed console took text");
+
+        console.open = true;
+        assert_eq!(console.handle(&typed("one")), vec!["one".to_string()]);
+        assert_eq!(console.handle(&typed("   ")), Vec::<String>::new());
+        assert_eq!(console.handle(&typed("two")), vec!["two".to_string()]);
+
+        let mut browse = |event: TextEvent| {
+            console.handle(&[event]);
+            console.line.clone()
+        };
+        assert_eq!(browse(TextEvent::Up), "two");
+        assert_eq!(browse(TextEvent::Up), "one");
+        assert_eq!(browse(TextEvent::Up), "one");
+        assert_eq!(browse(TextEvent::Down), "two");
+        assert_eq!(browse(TextEvent::Down), "");
+        assert_eq!(browse(TextEvent::Up), "two");
+
+        // an entry taken from the history is edited at its end and entered again
+        assert_eq!(console.handle(&typed("!")), vec!["two!".to_string()]);
+        assert_eq!(console.history, vec!["one".to_string(), "two".to_string(), "two!".to_string()]);
+        assert_eq!(console.log, vec!["> one".to_string(), "> two".to_string(), "> two!".to_string()]);
+
+        console.handle(&[TextEvent::Escape]);
+        assert!(!console.open);
+    }
+}
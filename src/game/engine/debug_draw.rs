@@ -0,0 +1,275 @@
+use cgmath::{Matrix4,Vector3,Vector4,InnerSpace,SquareMatrix,Matrix};
+use glium::backend::Facade;
+use crate::my_game_engine::my_game_logic::my_bounds::Aabb;
+use crate::my_game_engine::my_game_logic::my_renderer::{Renderer,StaticMesh,AnimatedMesh};
+use crate::my_game_engine::my_game_logic::my_shaders::{ShaderProgram,GlslTarget};
+
+#[derive(Copy, Clone)]
+pub struct DebugVertex {
+    position: [f32; 3],
+    color: [f32; 4],
+}
+#[allow(deprecated)]
+const _: () = { implement_vertex!(DebugVertex, position, color); };
+
+pub const RED: [f32; 4] = [1.0, 0.0, 0.0, 1.0];
+pub const GREEN: [f32; 4] = [0.0, 1.0, 0.0, 1.0];
+pub const BLUE: [f32; 4] = [0.0, 0.0, 1.0, 1.0];
+pub const WHITE: [f32; 4] = [1.0, 1.0, 1.0, 1.0];
+pub const YELLOW: [f32; 4] = [1.0, 1.0, 0.0, 1.0];
+pub const CYAN: [f32; 4] = [0.0, 1.0, 1.0, 1.0];
+
+struct DebugLine {
+    a: Vector3<f32>,
+    b: Vector3<f32>,
+    color: [f32; 4],
+    // seconds left, lines with 0 are drawn for a single frame
+    remaining: f32,
+}
+
+// world space lines, drawn on top of the scene at the end of the frame
+pub struct DebugDraw {
+    pub enabled: bool,
+    pub show_bounds: bool,
+    pub show_skeletons: bool,
+    pub show_normals: bool,
+    pub show_grid: bool,
+    lines: Vec<DebugLine>,
+    program: ShaderProgram,
+    last_draw: Option<time::PreciseTime>,
+}
+
+impl DebugDraw {
+
+    pub fn new<F: Facade>(display: &F, glsl: &GlslTarget) -> DebugDraw {
+        DebugDraw{
+            enabled: true,
+            show_bounds: false,
+            show_skeletons: false,
+            show_normals: false,
+            show_grid: false,
+            lines: Vec::new(),
+            program: Renderer::load_program(display, glsl, "./res/shader/debug_vs.glsl", "./res/shader/debug_fs.glsl"),
+            last_draw: None,
+        }
+    }
+
+    pub fn reload_shaders<F: Facade>(&mut self, display: &F, changed: &[std::path::PathBuf]) {
+        self.program.reload_if_changed(display, changed);
+    }
+
+    // 0 bounds, 1 skeletons, 2 normals, 3 grid and axes
+    pub fn toggle(&mut self, index: usize) {
+        match index {
+            0 => self.show_bounds = !self.show_bounds,
+            1 => self.show_skeletons = !self.show_skeletons,
+            2 => self.show_normals = !self.show_normals,
+            3 => self.show_grid = !self.show_grid,
+            _ => (),
+        }
+    }
+
+    pub fn line(&mut self, a: Vector3<f32>, b: Vector3<f32>, color: [f32; 4], duration: f32) {
+        self.lines.push(DebugLine { a, b, color, remaining: duration });
+    }
+
+    pub fn aabb(&mut self, aabb: &Aabb, color: [f32; 4], duration: f32) {
+        self.transformed_aabb(aabb, &Matrix4::identity(), color, duration);
+    }
+
+    // the box is transformed as a whole, so rotated objects get an oriented box
+    pub fn transformed_aabb(&mut self, aabb: &Aabb, transform: &Matrix4<f32>, color: [f32; 4], duration: f32) {
+        let c: Vec<Vector3<f32>> = aabb.corners().iter().map(|c| (*transform * c.extend(1.0)).truncate()).collect();
+        let edges = [
+            (0, 1), (2, 3), (4, 5), (6, 7),
+            (0, 2), (1, 3), (4, 6), (5, 7),
+            (0, 4), (1, 5), (2, 6), (3, 7),
+        ];
+        for &(a, b) in edges.iter() {
+            self.line(c[a], c[b], color, duration);
+        }
+    }
+
+    pub fn sphere(&mut self, center: Vector3<f32>, radius: f32, color: [f32; 4], duration: f32) {
+        let segments = 24;
+        for i in 0..segments {
+            let a0 = i as f32 / segments as f32 * std::f32::consts::PI * 2.0;
+            let a1 = (i + 1) as f32 / segments as f32 * std::f32::consts::PI * 2.0;
+            let (s0, c0, s1, c1) = (a0.sin() * radius, a0.cos() * radius, a1.sin() * radius, a1.cos() * radius);
+            self.line(center + Vector3::new(c0, s0, 0.0), center + Vector3::new(c1, s1, 0.0), color, duration);
+            self.line(center + Vector3::new(c0, 0.0, s0), center + Vector3::new(c1, 0.0, s1), color, duration);
+            self.line(center + Vector3::new(0.0, c0, s0), center + Vector3::new(0.0, c1, s1), color, duration);
+        }
+    }
+
+    pub fn arrow(&mut self, from: Vector3<f32>, to: Vector3<f32>, color: [f32; 4], duration: f32) {
+        self.line(from, to, color, duration);
+
+        let dir = to - from;
+        let len = dir.magnitude();
+        if len <= 0.0 {
+            return;
+        }
+        let dir = dir / len;
+        let side = (if dir.y.abs() < 0.99 { dir.cross(Vector3::unit_y()) } else { dir.cross(Vector3::unit_x()) }).normalize();
+        let up = side.cross(dir);
+        let head = len * 0.2;
+        let base = to - dir * head;
+        for offset in [side, -side, up, -up].iter() {
+            self.line(to, base + *offset * head * 0.5, color, duration);
+        }
+    }
+
+    // on the xz plane around center
+    pub fn grid(&mut self, center: Vector3<f32>, size: f32, cells: u32, color: [f32; 4], duration: f32) {
+        let half = size * 0.5;
+        let step = size / cells.max(1) as f32;
+        for i in 0..cells.max(1) + 1 {
+            let o = -half + i as f32 * step;
+            self.line(center + Vector3::new(o, 0.0, -half), center + Vector3::new(o, 0.0, half), color, duration);
+            self.line(center + Vector3::new(-half, 0.0, o), center + Vector3::new(half, 0.0, o), color, duration);
+        }
+    }
+
+    pub fn axes(&mut self, transform: &Matrix4<f32>, size: f32, duration: f32) {
+        let origin = transform.w.truncate();
+        self.arrow(origin, origin + transform.x.truncate().normalize() * size, RED, duration);
+        self.arrow(origin, origin + transform.y.truncate().normalize() * size, GREEN, duration);
+        self.arrow(origin, origin + transform.z.truncate().normalize() * size, BLUE, duration);
+    }
+
+    // the frustum of a camera given its perspective * view
+    pub fn frustum(&mut self, view_projection: Matrix4<f32>, color: [f32; 4], duration: f32) {
+        let inverse = match view_projection.invert() {
+            Some(m) => m,
+            None => return,
+        };
+        let mut c: Vec<Vector3<f32>> = Vec::with_capacity(8);
+        for &z in [-1.0f32, 1.0].iter() {
+            for &y in [-1.0f32, 1.0].iter() {
+                for &x in [-1.0f32, 1.0].iter() {
+                    let p = inverse * Vector4::new(x, y, z, 1.0);
+                    c.push(p.truncate() / p.w);
+                }
+            }
+        }
+        let edges = [
+            (0, 1), (2, 3), (4, 5), (6, 7),
+            (0, 2), (1, 3), (4, 6), (5, 7),
+            (0, 4), (1, 5), (2, 6), (3, 7),
+        ];
+        for &(a, b) in edges.iter() {
+            self.line(c[a], c[b], color, duration);
+        }
+    }
+
+    // bones from every joint to its parent in the current pose
+    pub fn skeleton(&mut self, mesh: &AnimatedMesh, transform: &Matrix4<f32>, color: [f32; 4], duration: f32) {
+        let positions: Vec<Vector3<f32>> = mesh.skeleton.iter().enumerate().map(|(i, joint)| {
+            // current_pose holds the transposed skinning matrix, global pose * inverse bind pose
+            let skin: Matrix4<f32> = Matrix4::from(mesh.current_pose[i]).transpose();
+            let global = skin * joint.inv_bind_pos.invert().unwrap_or(Matrix4::identity());
+            (*transform * global.w).truncate()
+        }).collect();
+
+        for (i, joint) in mesh.skeleton.iter().enumerate() {
+            if joint.parent_i >= 0 {
+                self.line(positions[joint.parent_i as usize], positions[i], color, duration);
+            }
+        }
+    }
+
+    // one line per vertex along its normal
+    pub fn normals(&mut self, mesh: &StaticMesh, transform: &Matrix4<f32>, length: f32, color: [f32; 4], duration: f32) {
+        let normal_matrix = transform.invert().unwrap_or(Matrix4::identity()).transpose();
+        for v in mesh.cpu_vertices.iter() {
+            let p = (*transform * Vector4::new(v.position[0], v.position[1], v.position[2], 1.0)).truncate();
+            let n = (normal_matrix * Vector4::new(v.normal[0], v.normal[1], v.normal[2], 0.0)).truncate();
+            if n.magnitude2() > 0.0 {
+                self.line(p, p + n.normalize() * length, color, duration);
+            }
+        }
+    }
+
+    pub fn draw<F: Facade, S: glium::Surface>(&mut self, display: &F, target_frame: &mut S, view: [[f32;4];4], perspective: [[f32;4];4], depth_test: glium::draw_parameters::DepthTest) {
+        let now = time::PreciseTime::now();
+        let dt = match self.last_draw {
+            Some(last) => last.to(now).num_microseconds().unwrap_or(0) as f32 / 1_000_000.0,
+            None => 0.0,
+        };
+        self.last_draw = Some(now);
+
+        if self.show_grid {
+            self.grid(Vector3::new(0.0, 0.0, 0.0), 20.0, 20, [0.5, 0.5, 0.5, 1.0], 0.0);
+            self.axes(&Matrix4::identity(), 1.0, 0.0);
+        }
+
+        if self.enabled && !self.lines.is_empty() {
+            let mut vertices: Vec<DebugVertex> = Vec::with_capacity(self.lines.len() * 2);
+            for l in self.lines.iter() {
+                vertices.push(DebugVertex { position: l.a.into(), color: l.color });
+                vertices.push(DebugVertex { position: l.b.into(), color: l.color });
+            }
+            let vb = glium::VertexBuffer::new(display, &vertices).unwrap();
+
+            let params = glium::DrawParameters {
+                depth: glium::Depth {
+                    test: depth_test,
+                    write: false,
+                    ..Default::default()
+                },
+                blend: glium::Blend::alpha_blending(),
+                ..Default::default()
+            };
+
+            target_frame.draw(
+                &vb,
+                glium::index::NoIndices(glium::index::PrimitiveType::LinesList),
+                &self.program,
+                &uniform! { view: view, perspective: perspective },
+                &params,
+            ).unwrap();
+        }
+
+        for l in self.lines.iter_mut() {
+            l.remaining -= dt;
+        }
+        self.lines.retain(|l| l.remaining > 0.0);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::rc::Rc;
+    use crate::my_game_engine::my_game_logic::my_bounds::Bounds;
+    use crate::my_game_engine::my_game_logic::my_renderer::MyVertex;
+    use crate::my_game_engine::my_game_logic::my_egl;
+
+    #[test]
+    fn normals_come_from_the_cpu_side_vertices() {
+        let Some(context) = my_egl::test_context((16, 16)) else { return };
+        let glsl = GlslTarget::detect(&context, &my_egl::get_proc_address).unwrap();
+        let mut debug = DebugDraw::new(&context, &glsl);
+
+        let vertices = [
+            MyVertex{ position: [0.0, 0.0, 0.0], normal: [0.0, 1.0, 0.0], texture: [0.0, 0.0] },
+            MyVertex{ position: [1.0, 0.0, 0.0], normal: [2.0, 0.0, 0.0], texture: [0.0, 0.0] },
+        ];
+        let mesh = StaticMesh{
+            vertices: Rc::new(glium::VertexBuffer::new(&context, &vertices).unwrap()),
+            indices: Rc::new(glium::IndexBuffer::new(&context, glium::index::PrimitiveType::TrianglesList, &[0u16, 1, 0]).unwrap()),
+            bounds: Bounds::from_points(vertices.iter().map(|v| v.position)),
+            cpu_vertices: Rc::new(vertices.to_vec()),
+            cpu_indices: Rc::new(vec![0, 1, 0]),
+        };
+
+        let transform = Matrix4::from_translation(Vector3::new(0.0, 0.0, 5.0)) * Matrix4::from_scale(2.0);
+        debug.normals(&mesh, &transform, 0.5, WHITE, 0.0);
+        assert_eq!(debug.lines.len(), 2);
+        assert_eq!(debug.lines[0].a, Vector3::new(0.0, 0.0, 5.0));
+        assert_eq!(debug.lines[0].b, Vector3::new(0.0, 0.5, 5.0));
+        assert_eq!(debug.lines[1].a, Vector3::new(2.0, 0.0, 5.0));
+        assert_eq!(debug.lines[1].b, Vector3::new(2.5, 0.0, 5.0));
+    }
+}
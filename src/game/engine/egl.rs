@@ -0,0 +1,160 @@
+use std::os::raw::{c_char,c_void};
+use std::sync::OnceLock;
+use libloading::Library;
+use glium::backend::Backend;
+
+type EGLDisplay = *mut c_void;
+type EGLContext = *mut c_void;
+type EGLBoolean = u32;
+type EGLint = i32;
+
+const EGL_PLATFORM_SURFACELESS_MESA: u32 = 0x31DD;
+const EGL_OPENGL_API: u32 = 0x30A2;
+const EGL_NONE: EGLint = 0x3038;
+const EGL_CONTEXT_MAJOR_VERSION: EGLint = 0x3098;
+const EGL_CONTEXT_MINOR_VERSION: EGLint = 0x30FB;
+const EGL_CONTEXT_OPENGL_PROFILE_MASK: EGLint = 0x30FD;
+const EGL_CONTEXT_OPENGL_CORE_PROFILE_BIT: EGLint = 0x1;
+
+type GetPlatformDisplay = unsafe extern "C" fn(u32, *mut c_void, *const isize) -> EGLDisplay;
+type Initialize = unsafe extern "C" fn(EGLDisplay, *mut EGLint, *mut EGLint) -> EGLBoolean;
+type BindApi = unsafe extern "C" fn(u32) -> EGLBoolean;
+type CreateContext = unsafe extern "C" fn(EGLDisplay, *mut c_void, EGLContext, *const EGLint) -> EGLContext;
+type DestroyContext = unsafe extern "C" fn(EGLDisplay, EGLContext) -> EGLBoolean;
+type MakeCurrent = unsafe extern "C" fn(EGLDisplay, *mut c_void, *mut c_void, EGLContext) -> EGLBoolean;
+type GetCurrentContext = unsafe extern "C" fn() -> EGLContext;
+type GetProcAddress = unsafe extern "C" fn(*const c_char) -> *const c_void;
+
+// an opengl 3.3 core context without any window or surface, through mesa's surfaceless egl
+// platform. there is no default framebuffer, everything is drawn into textures. works on a
+// machine without a display server, llvmpipe renders on the cpu if there is no gpu
+pub struct SurfacelessContext {
+    display: EGLDisplay,
+    context: EGLContext,
+    dimensions: (u32, u32),
+    make_current: MakeCurrent,
+    get_current_context: GetCurrentContext,
+    get_proc_address: GetProcAddress,
+    destroy_context: DestroyContext,
+}
+
+// loaded once and never unloaded, the function pointers of every context point into it
+fn egl() -> Result<&'static Library, String> {
+    static EGL: OnceLock<Result<Library, String>> = OnceLock::new();
+    EGL.get_or_init(|| Library::new("libEGL.so.1").map_err(|e| format!("could not load libEGL.so.1: {}", e)))
+        .as_ref().map_err(|e| e.clone())
+}
+
+impl SurfacelessContext {
+
+    // dimensions is what glium reports for the missing default framebuffer
+    pub fn new(dimensions: (u32, u32)) -> Result<SurfacelessContext, String> {
+        let egl = egl()?;
+        unsafe {
+            let get_platform_display: GetPlatformDisplay = *egl.get(b"eglGetPlatformDisplay\0").map_err(|e| e.to_string())?;
+            let initialize: Initialize = *egl.get(b"eglInitialize\0").map_err(|e| e.to_string())?;
+            let bind_api: BindApi = *egl.get(b"eglBindAPI\0").map_err(|e| e.to_string())?;
+            let create_context: CreateContext = *egl.get(b"eglCreateContext\0").map_err(|e| e.to_string())?;
+
+            let display = get_platform_display(EGL_PLATFORM_SURFACELESS_MESA, std::ptr::null_mut(), std::ptr::null());
+            if display.is_null() {
+                return Err("no surfaceless egl display, mesa's EGL_MESA_platform_surfaceless is needed".to_string());
+            }
+            if initialize(display, std::ptr::null_mut(), std::ptr::null_mut()) == 0 {
+                return Err("could not initialize the egl display".to_string());
+            }
+            if bind_api(EGL_OPENGL_API) == 0 {
+                return Err("egl has no desktop opengl".to_string());
+            }
+            // without a surface no config is needed, EGL_KHR_no_config_context
+            let attributes = [
+                EGL_CONTEXT_MAJOR_VERSION, 3,
+                EGL_CONTEXT_MINOR_VERSION, 3,
+                EGL_CONTEXT_OPENGL_PROFILE_MASK, EGL_CONTEXT_OPENGL_CORE_PROFILE_BIT,
+                EGL_NONE,
+            ];
+            let context = create_context(display, std::ptr::null_mut(), std::ptr::null_mut(), attributes.as_ptr());
+            if context.is_null() {
+                return Err("could not create an opengl 3.3 core context".to_string());
+            }
+
+            Ok(SurfacelessContext{
+                display,
+                context,
+                dimensions,
+                make_current: *egl.get(b"eglMakeCurrent\0").map_err(|e| e.to_string())?,
+                get_current_context: *egl.get(b"eglGetCurrentContext\0").map_err(|e| e.to_string())?,
+                get_proc_address: *egl.get(b"eglGetProcAddress\0").map_err(|e| e.to_string())?,
+                destroy_context: *egl.get(b"eglDestroyContext\0").map_err(|e| e.to_string())?,
+            })
+        }
+    }
+
+    // makes the context current and hands it to glium
+    pub fn into_glium(self) -> Result<std::rc::Rc<glium::backend::Context>, String> {
+        unsafe {
+            self.make_current();
+            glium::backend::Context::new(self, true, glium::debug::DebugCallbackBehavior::Ignore).map_err(|e| e.to_string())
+        }
+    }
+}
+
+// eglGetProcAddress for the gl functions glium does not wrap
+pub fn get_proc_address(symbol: &str) -> *const c_void {
+    let egl = match egl() {
+        Ok(egl) => egl,
+        Err(_) => return std::ptr::null(),
+    };
+    let symbol = std::ffi::CString::new(symbol).unwrap();
+    unsafe {
+        match egl.get::<GetProcAddress>(b"eglGetProcAddress\0") {
+            Ok(get_proc_address) => get_proc_address(symbol.as_ptr()),
+            Err(_) => std::ptr::null(),
+        }
+    }
+}
+
+// the gl tests skip themselves through this on machines without mesa's surfaceless egl
+#[cfg(test)]
+pub fn test_context(dimensions: (u32, u32)) -> Option<std::rc::Rc<glium::backend::Context>> {
+    match SurfacelessContext::new(dimensions).and_then(|context| context.into_glium()) {
+        Ok(context) => Some(context),
+        Err(e) => {
+            eprintln!("skipped, no surfaceless gl context: {}", e);
+            None
+        },
+    }
+}
+
+unsafe impl Backend for SurfacelessContext {
+    fn swap_buffers(&self) -> Result<(), glium::SwapBuffersError> {
+        Ok(())
+    }
+
+    unsafe fn get_proc_address(&self, symbol: &str) -> *const c_void {
+        let symbol = std::ffi::CString::new(symbol).unwrap();
+        (self.get_proc_address)(symbol.as_ptr())
+    }
+
+    fn get_framebuffer_dimensions(&self) -> (u32, u32) {
+        self.dimensions
+    }
+
+    fn is_current(&self) -> bool {
+        unsafe { (self.get_current_context)() == self.context }
+    }
+
+    unsafe fn make_current(&self) {
+        (self.make_current)(self.display, std::ptr::null_mut(), std::ptr::null_mut(), self.context);
+    }
+}
+
+impl Drop for SurfacelessContext {
+    fn drop(&mut self) {
+        unsafe {
+            (self.make_current)(self.display, std::ptr::null_mut(), std::ptr::null_mut(), std::ptr::null_mut());
+            // the display is shared by every context of the process, so it is not terminated
+            (self.destroy_context)(self.display, self.context);
+        }
+    }
+}
@@ -0,0 +1,291 @@
+use glutin::ElementState;
+use crate::my_game_engine::my_game_logic::my_input::{InputMap,PadButton,PadAxis};
+
+// what a pad reports, ids are the ones gilrs hands out or made up for injected pads
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum PadEvent {
+    Connected(usize),
+    Disconnected(usize),
+    Button(usize, PadButton, ElementState),
+    Axis(usize, PadAxis, f32),
+}
+
+#[cfg(feature = "gamepad")]
+fn button_from_gilrs(button: gilrs::Button) -> Option<PadButton> {
+    use gilrs::Button;
+    match button {
+        Button::South => Some(PadButton::South),
+        Button::East => Some(PadButton::East),
+        Button::North => Some(PadButton::North),
+        Button::West => Some(PadButton::West),
+        Button::LeftTrigger => Some(PadButton::LeftBumper),
+        Button::RightTrigger => Some(PadButton::RightBumper),
+        Button::LeftTrigger2 => Some(PadButton::LeftTrigger),
+        Button::RightTrigger2 => Some(PadButton::RightTrigger),
+        Button::Select => Some(PadButton::Select),
+        Button::Start => Some(PadButton::Start),
+        Button::Mode => Some(PadButton::Mode),
+        Button::LeftThumb => Some(PadButton::LeftThumb),
+        Button::RightThumb => Some(PadButton::RightThumb),
+        Button::DPadUp => Some(PadButton::DPadUp),
+        Button::DPadDown => Some(PadButton::DPadDown),
+        Button::DPadLeft => Some(PadButton::DPadLeft),
+        Button::DPadRight => Some(PadButton::DPadRight),
+        _ => None,
+    }
+}
+
+#[cfg(feature = "gamepad")]
+fn axis_from_gilrs(axis: gilrs::Axis) -> Option<PadAxis> {
+    use gilrs::Axis;
+    match axis {
+        Axis::LeftStickX => Some(PadAxis::LeftX),
+        Axis::LeftStickY => Some(PadAxis::LeftY),
+        Axis::RightStickX => Some(PadAxis::RightX),
+        Axis::RightStickY => Some(PadAxis::RightY),
+        Axis::LeftZ => Some(PadAxis::LeftTrigger),
+        Axis::RightZ => Some(PadAxis::RightTrigger),
+        _ => None,
+    }
+}
+
+#[cfg(feature = "gamepad")]
+fn from_gilrs(event: gilrs::Event) -> Option<PadEvent> {
+    use gilrs::EventType;
+    let id: usize = event.id.into();
+    match event.event {
+        EventType::Connected => Some(PadEvent::Connected(id)),
+        EventType::Disconnected => Some(PadEvent::Disconnected(id)),
+        EventType::ButtonPressed(button, _) => button_from_gilrs(button).map(|b| PadEvent::Button(id, b, ElementState::Pressed)),
+        EventType::ButtonReleased(button, _) => button_from_gilrs(button).map(|b| PadEvent::Button(id, b, ElementState::Released)),
+        // most pads report analog triggers as buttons with a value
+        EventType::ButtonChanged(gilrs::Button::LeftTrigger2, value, _) => Some(PadEvent::Axis(id, PadAxis::LeftTrigger, value)),
+        EventType::ButtonChanged(gilrs::Button::RightTrigger2, value, _) => Some(PadEvent::Axis(id, PadAxis::RightTrigger, value)),
+        EventType::AxisChanged(axis, value, _) => axis_from_gilrs(axis).map(|a| PadEvent::Axis(id, a, value)),
+        _ => None,
+    }
+}
+
+// feeds the pads into the input map. only one pad drives it at a time, the first one connected,
+// the next one takes over when it is unplugged. real pads need the gamepad feature, gilrs
+// links against libudev on linux. without it only injected events arrive
+pub struct Gamepads {
+    // None when there is no gamepad backend, injected events still work
+    #[cfg(feature = "gamepad")]
+    gilrs: Option<gilrs::Gilrs>,
+    injected: Vec<PadEvent>,
+    connected: Vec<usize>,
+    active: Option<usize>,
+}
+
+impl Default for Gamepads {
+    fn default() -> Gamepads {
+        Gamepads::new()
+    }
+}
+
+impl Gamepads {
+
+    #[cfg(not(feature = "gamepad"))]
+    pub fn new() -> Gamepads {
+        Gamepads::without_devices()
+    }
+
+    #[cfg(feature = "gamepad")]
+    pub fn new() -> Gamepads {
+        let gilrs = match gilrs::Gilrs::new() {
+            Ok(gilrs) => Some(gilrs),
+            Err(e) => {
+                println!("no gamepad support: {}", e);
+                None
+            },
+        };
+        let mut gamepads = Gamepads::without_devices();
+        if let Some(ref gilrs) = gilrs {
+            // pads plugged in before the start don't send a connected event
+            for (id, _) in gilrs.gamepads() {
+                gamepads.injected.push(PadEvent::Connected(id.into()));
+            }
+        }
+        gamepads.gilrs = gilrs;
+        gamepads
+    }
+
+    // a virtual pad only driven by inject, for tests and replays
+    pub fn without_devices() -> Gamepads {
+        Gamepads{
+            #[cfg(feature = "gamepad")]
+            gilrs: None,
+            injected: Vec::new(),
+            connected: Vec::new(),
+            active: None,
+        }
+    }
+
+    // handled with the next poll like an event of a real pad
+    pub fn inject(&mut self, event: PadEvent) {
+        self.injected.push(event);
+    }
+
+    pub fn active(&self) -> Option<usize> {
+        self.active
+    }
+
+    pub fn poll(&mut self, input: &mut InputMap) {
+        let mut events: Vec<PadEvent> = self.injected.drain(..).collect();
+        self.poll_devices(&mut events);
+        for event in events {
+            self.handle(event, input);
+        }
+    }
+
+    #[cfg(feature = "gamepad")]
+    fn poll_devices(&mut self, events: &mut Vec<PadEvent>) {
+        if let Some(ref mut gilrs) = self.gilrs {
+            while let Some(event) = gilrs.next_event() {
+                events.extend(from_gilrs(event));
+            }
+        }
+    }
+
+    #[cfg(not(feature = "gamepad"))]
+    fn poll_devices(&mut self, _events: &mut Vec<PadEvent>) {
+    }
+
+    fn handle(&mut self, event: PadEvent, input: &mut InputMap) {
+        match event {
+            PadEvent::Connected(id) => {
+                if !self.connected.contains(&id) {
+                    println!("gamepad {} connected", id);
+                    self.connected.push(id);
+                }
+                if self.active.is_none() {
+                    self.active = Some(id);
+                }
+            },
+            PadEvent::Disconnected(id) => {
+                println!("gamepad {} disconnected", id);
+                self.connected.retain(|c| *c != id);
+                if self.active == Some(id) {
+                    input.release_pad();
+                    self.active = self.connected.first().cloned();
+                }
+            },
+            PadEvent::Button(id, button, state) => {
+                if self.active == Some(id) {
+                    input.handle_pad_button(button, state);
+                }
+            },
+            PadEvent::Axis(id, axis, value) => {
+                if self.active == Some(id) {
+                    input.handle_pad_axis(axis, value);
+                }
+            },
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::my_game_engine::my_game_logic::my_input::{Binding,AxisSource};
+
+    fn map() -> InputMap {
+        let mut input = InputMap::new();
+        input.bind_axis("move_x", AxisSource::Pad(PadAxis::LeftX), 1.0);
+        input.bind_axis("move_z", AxisSource::Pad(PadAxis::LeftY), 1.0);
+        input.bind("left", Binding::PadAxis { axis: PadAxis::LeftX, positive: false });
+        input.bind("right", Binding::PadAxis { axis: PadAxis::LeftX, positive: true });
+        input.bind("jump", Binding::Pad(PadButton::South));
+        input
+    }
+
+    fn poll(pads: &mut Gamepads, input: &mut InputMap, events: &[PadEvent]) {
+        for event in events {
+            pads.inject(*event);
+        }
+        pads.poll(input);
+    }
+
+    #[test]
+    fn axis_of_the_active_pad() {
+        let mut pads = Gamepads::without_devices();
+        let mut input = map();
+        poll(&mut pads, &mut input, &[PadEvent::Axis(0, PadAxis::LeftX, 1.0)]);
+        assert_eq!(input.axis_value("move_x"), 0.0, "no pad is connected yet");
+
+        poll(&mut pads, &mut input, &[PadEvent::Connected(0), PadEvent::Connected(1), PadEvent::Axis(0, PadAxis::LeftX, 1.0)]);
+        assert_eq!(pads.active(), Some(0));
+        assert_eq!(input.axis_value("move_x"), 1.0);
+
+        // only the active pad drives the map
+        poll(&mut pads, &mut input, &[PadEvent::Axis(1, PadAxis::LeftX, -1.0)]);
+        assert_eq!(input.axis_value("move_x"), 1.0);
+    }
+
+    #[test]
+    fn radial_dead_zone() {
+        let mut pads = Gamepads::without_devices();
+        let mut input = map();
+        let dead_zone = input.stick_dead_zone;
+        poll(&mut pads, &mut input, &[PadEvent::Connected(0), PadEvent::Axis(0, PadAxis::LeftX, 0.15)]);
+        assert_eq!(input.axis_value("move_x"), 0.0);
+
+        // each axis alone is inside the dead zone, together the stick is outside of it
+        poll(&mut pads, &mut input, &[PadEvent::Axis(0, PadAxis::LeftY, 0.15)]);
+        let length = (0.15f32 * 0.15 * 2.0).sqrt();
+        let scaled = (length - dead_zone) / (1.0 - dead_zone);
+        assert!((input.axis_value("move_x") - 0.15 / length * scaled).abs() < 1e-6);
+        assert!((input.axis_value("move_z") - 0.15 / length * scaled).abs() < 1e-6);
+
+        // the rest is stretched, so full deflection still reaches 1
+        poll(&mut pads, &mut input, &[PadEvent::Axis(0, PadAxis::LeftY, 0.0), PadEvent::Axis(0, PadAxis::LeftX, -1.0)]);
+        assert_eq!(input.axis_value("move_x"), -1.0);
+    }
+
+    #[test]
+    fn half_way_bindings() {
+        let mut pads = Gamepads::without_devices();
+        let mut input = map();
+        poll(&mut pads, &mut input, &[PadEvent::Connected(0), PadEvent::Axis(0, PadAxis::LeftX, 0.4)]);
+        assert!(!input.pressed("right"));
+
+        poll(&mut pads, &mut input, &[PadEvent::Axis(0, PadAxis::LeftX, 0.9)]);
+        assert!(input.pressed("right") && input.just_pressed("right"));
+        assert!(!input.pressed("left"));
+        input.end_update();
+
+        poll(&mut pads, &mut input, &[PadEvent::Axis(0, PadAxis::LeftX, -0.9)]);
+        assert!(input.just_released("right") && input.just_pressed("left"));
+        input.end_update();
+
+        poll(&mut pads, &mut input, &[PadEvent::Axis(0, PadAxis::LeftX, 0.0)]);
+        assert!(!input.pressed("left") && input.just_released("left"));
+    }
+
+    #[test]
+    fn unplugging_releases_everything() {
+        let mut pads = Gamepads::without_devices();
+        let mut input = map();
+        poll(&mut pads, &mut input, &[
+            PadEvent::Connected(0), PadEvent::Connected(1),
+            PadEvent::Axis(0, PadAxis::LeftX, 0.9),
+            PadEvent::Button(0, PadButton::South, ElementState::Pressed),
+        ]);
+        assert!(input.pressed("jump") && input.pressed("right"));
+        input.end_update();
+
+        poll(&mut pads, &mut input, &[PadEvent::Disconnected(0)]);
+        assert!(!input.pressed("jump") && !input.pressed("right"));
+        assert!(input.just_released("jump") && input.just_released("right"));
+        assert_eq!(input.axis_value("move_x"), 0.0);
+        // the other pad takes over
+        assert_eq!(pads.active(), Some(1));
+        poll(&mut pads, &mut input, &[PadEvent::Axis(1, PadAxis::LeftX, -1.0)]);
+        assert_eq!(input.axis_value("move_x"), -1.0);
+
+        poll(&mut pads, &mut input, &[PadEvent::Disconnected(1)]);
+        assert_eq!(pads.active(), None);
+        assert_eq!(input.axis_value("move_x"), 0.0);
+    }
+}
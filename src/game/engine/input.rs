@@ -0,0 +1,686 @@
+use std::collections::{HashMap,HashSet};
+use glutin::{VirtualKeyCode,MouseButton,ElementState};
+use crate::my_game_engine::my_game_logic::my_text_input::TextEvent;
+
+// a physical input an action can be bound to
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub enum Binding {
+    Key(VirtualKeyCode),
+    // the position on the keyboard whatever the layout says, Z on us keyboards is Y on german ones
+    ScanCode(u32),
+    Mouse(MouseButton),
+    Pad(PadButton),
+    // a gamepad axis pushed more than half way, positive or negative
+    PadAxis { axis: PadAxis, positive: bool },
+}
+
+// gamepad buttons in the position names of gilrs, south is A on xbox and cross on playstation pads
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub enum PadButton {
+    South, East, North, West,
+    LeftBumper, RightBumper, LeftTrigger, RightTrigger,
+    Select, Start, Mode, LeftThumb, RightThumb,
+    DPadUp, DPadDown, DPadLeft, DPadRight,
+}
+
+// sticks in -1..1 with y up, triggers in 0..1
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub enum PadAxis {
+    LeftX, LeftY, RightX, RightY, LeftTrigger, RightTrigger,
+}
+
+const PAD_BUTTONS: &[PadButton] = &[
+    PadButton::South, PadButton::East, PadButton::North, PadButton::West,
+    PadButton::LeftBumper, PadButton::RightBumper, PadButton::LeftTrigger, PadButton::RightTrigger,
+    PadButton::Select, PadButton::Start, PadButton::Mode, PadButton::LeftThumb, PadButton::RightThumb,
+    PadButton::DPadUp, PadButton::DPadDown, PadButton::DPadLeft, PadButton::DPadRight,
+];
+
+const PAD_AXES: &[PadAxis] = &[
+    PadAxis::LeftX, PadAxis::LeftY, PadAxis::RightX, PadAxis::RightY, PadAxis::LeftTrigger, PadAxis::RightTrigger,
+];
+
+impl PadAxis {
+    // the other axis of the same stick, the dead zone is applied to both together
+    fn partner(&self) -> Option<PadAxis> {
+        match self {
+            PadAxis::LeftX => Some(PadAxis::LeftY),
+            PadAxis::LeftY => Some(PadAxis::LeftX),
+            PadAxis::RightX => Some(PadAxis::RightY),
+            PadAxis::RightY => Some(PadAxis::RightX),
+            PadAxis::LeftTrigger | PadAxis::RightTrigger => None,
+        }
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum AxisSource {
+    // 1 while positive is held, -1 while negative is, 0 for both or none
+    Buttons { positive: Binding, negative: Binding },
+    // raw motion since the last update, not scaled by the window size
+    MouseX,
+    MouseY,
+    Wheel,
+    // the position of a stick or trigger after the dead zone, not a motion like the mouse
+    Pad(PadAxis),
+}
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct AxisBinding {
+    pub source: AxisSource,
+    pub scale: f32,
+}
+
+// keys that can be named in the bindings file, by their VirtualKeyCode name
+macro_rules! named_keys {
+    ($($key:ident),*) => {
+        const NAMED_KEYS: &[VirtualKeyCode] = &[$(VirtualKeyCode::$key),*];
+    };
+}
+
+named_keys!(
+    Key1, Key2, Key3, Key4, Key5, Key6, Key7, Key8, Key9, Key0,
+    A, B, C, D, E, F, G, H, I, J, K, L, M, N, O, P, Q, R, S, T, U, V, W, X, Y, Z,
+    Escape, F1, F2, F3, F4, F5, F6, F7, F8, F9, F10, F11, F12,
+    Insert, Home, Delete, End, PageDown, PageUp, Left, Up, Right, Down,
+    Back, Return, Space, Tab,
+    Numpad0, Numpad1, Numpad2, Numpad3, Numpad4, Numpad5, Numpad6, Numpad7, Numpad8, Numpad9,
+    LAlt, LControl, LShift, RAlt, RControl, RShift,
+    Comma, Period, Minus, Equals, Semicolon, Slash, Backslash, Grave, Apostrophe, LBracket, RBracket
+);
+
+fn key_from_name(name: &str) -> Option<VirtualKeyCode> {
+    NAMED_KEYS.iter().cloned().find(|k| format!("{:?}", k) == name)
+}
+
+fn pad_button_from_name(name: &str) -> Option<PadButton> {
+    PAD_BUTTONS.iter().cloned().find(|b| format!("{:?}", b) == name)
+}
+
+fn pad_axis_from_name(name: &str) -> Option<PadAxis> {
+    PAD_AXES.iter().cloned().find(|a| format!("{:?}", a) == name)
+}
+
+fn mouse_from_name(name: &str) -> Option<MouseButton> {
+    match name {
+        "Left" => Some(MouseButton::Left),
+        "Right" => Some(MouseButton::Right),
+        "Middle" => Some(MouseButton::Middle),
+        other => other.parse::<u8>().ok().map(MouseButton::Other),
+    }
+}
+
+impl Binding {
+    // key W, scan 44, mouse Left, pad South, pad_axis LeftY+
+    pub fn parse(kind: &str, name: &str) -> Option<Binding> {
+        match kind {
+            "key" => key_from_name(name).map(Binding::Key),
+            "scan" => name.parse::<u32>().ok().map(Binding::ScanCode),
+            "mouse" => mouse_from_name(name).map(Binding::Mouse),
+            "pad" => pad_button_from_name(name).map(Binding::Pad),
+            "pad_axis" if name.ends_with('+') || name.ends_with('-') => {
+                let (axis, sign) = name.split_at(name.len() - 1);
+                pad_axis_from_name(axis).map(|axis| Binding::PadAxis { axis, positive: sign == "+" })
+            },
+            _ => None,
+        }
+    }
+
+    fn is_key(&self) -> bool {
+        matches!(self, Binding::Key(_) | Binding::ScanCode(_))
+    }
+
+    pub fn describe(&self) -> String {
+        match self {
+            Binding::Key(key) => format!("key {:?}", key),
+            Binding::ScanCode(code) => format!("scan {}", code),
+            Binding::Mouse(MouseButton::Other(n)) => format!("mouse {}", n),
+            Binding::Mouse(button) => format!("mouse {:?}", button),
+            Binding::Pad(button) => format!("pad {:?}", button),
+            Binding::PadAxis { axis, positive } => format!("pad_axis {:?}{}", axis, if *positive { "+" } else { "-" }),
+        }
+    }
+}
+
+// what the map is fed with, the window and pad events boiled down to what changes its state.
+// recordings are made of these, see my_replay
+#[derive(Clone, Debug, PartialEq)]
+pub enum InputEvent {
+    Button(Binding, ElementState),
+    Motion(f32, f32),
+    Wheel(f32),
+    PadAxis(PadAxis, f32),
+    // logical pixels from the top left corner of the window
+    Cursor(f32, f32),
+    // typed text, see my_text_input
+    Text(TextEvent),
+    ReleaseAll,
+    ReleasePad,
+}
+
+impl InputEvent {
+    // press key W, release pad South, motion 3 -2, wheel 1, pad_move LeftX 0.5, cursor 120 80, text char 97, release_all, release_pad
+    pub fn parse(words: &[&str]) -> Option<InputEvent> {
+        let number = |word: &str| word.parse::<f32>().ok();
+        match words {
+            ["press", kind, name] => Binding::parse(kind, name).map(|b| InputEvent::Button(b, ElementState::Pressed)),
+            ["release", kind, name] => Binding::parse(kind, name).map(|b| InputEvent::Button(b, ElementState::Released)),
+            ["motion", x, y] => Some(InputEvent::Motion(number(x)?, number(y)?)),
+            ["wheel", v] => number(v).map(InputEvent::Wheel),
+            ["pad_move", axis, v] => Some(InputEvent::PadAxis(pad_axis_from_name(axis)?, number(v)?)),
+            ["cursor", x, y] => Some(InputEvent::Cursor(number(x)?, number(y)?)),
+            ["text", rest @ ..] => TextEvent::parse(rest).map(InputEvent::Text),
+            ["release_all"] => Some(InputEvent::ReleaseAll),
+            ["release_pad"] => Some(InputEvent::ReleasePad),
+            _ => None,
+        }
+    }
+
+    // floats are printed in full, so parse gives back the same bits
+    pub fn describe(&self) -> String {
+        match self {
+            InputEvent::Button(binding, ElementState::Pressed) => format!("press {}", binding.describe()),
+            InputEvent::Button(binding, ElementState::Released) => format!("release {}", binding.describe()),
+            InputEvent::Motion(x, y) => format!("motion {} {}", x, y),
+            InputEvent::Wheel(v) => format!("wheel {}", v),
+            InputEvent::PadAxis(axis, v) => format!("pad_move {:?} {}", axis, v),
+            InputEvent::Cursor(x, y) => format!("cursor {} {}", x, y),
+            InputEvent::Text(text) => format!("text {}", text.describe()),
+            InputEvent::ReleaseAll => "release_all".to_string(),
+            InputEvent::ReleasePad => "release_pad".to_string(),
+        }
+    }
+}
+
+// named actions and axes on top of the window events. edges and mouse motion collect between
+// two calls of end_update, so every fixed update sees them exactly once
+pub struct InputMap {
+    actions: HashMap<String, Vec<Binding>>,
+    axes: HashMap<String, Vec<AxisBinding>>,
+    down: HashSet<Binding>,
+    pressed: HashSet<Binding>,
+    released: HashSet<Binding>,
+    mouse_delta: (f32, f32),
+    wheel: f32,
+    // raw gamepad positions, see pad_axis for the ones with the dead zone applied
+    pad_axes: HashMap<PadAxis, f32>,
+    // where the cursor was last seen, in logical pixels
+    cursor: (f32, f32),
+    // typed since the last update, in order
+    text: Vec<TextEvent>,
+    // radial for the sticks so diagonals are not cut off
+    pub stick_dead_zone: f32,
+    pub trigger_dead_zone: f32,
+    // the next press is bound to this action instead of triggering anything
+    rebinding: Option<String>,
+    // false while a replay drives the map, the events of the devices are dropped then
+    pub live: bool,
+    // keeps the applied device events for take_captured while set
+    pub capture: bool,
+    // the keyboard is typing into a text field, see suspend_keys
+    keys_suspended: bool,
+    captured: Vec<InputEvent>,
+}
+
+impl Default for InputMap {
+    fn default() -> InputMap {
+        InputMap::new()
+    }
+}
+
+impl InputMap {
+
+    pub fn new() -> InputMap {
+        InputMap{
+            actions: HashMap::new(),
+            axes: HashMap::new(),
+            down: HashSet::new(),
+            pressed: HashSet::new(),
+            released: HashSet::new(),
+            mouse_delta: (0.0, 0.0),
+            wheel: 0.0,
+            pad_axes: HashMap::new(),
+            cursor: (0.0, 0.0),
+            text: Vec::new(),
+            stick_dead_zone: 0.2,
+            trigger_dead_zone: 0.05,
+            rebinding: None,
+            live: true,
+            capture: false,
+            keys_suspended: false,
+            captured: Vec::new(),
+        }
+    }
+
+    // one binding per line, # starts a comment. an action or axis can be listed more than once
+    //   action <name> key <VirtualKeyCode>|scan <code>|mouse Left|Right|Middle|<n>
+    //   axis <name> buttons <positive binding> <negative binding> [<scale>]
+    //   axis <name> mouse_x|mouse_y|wheel <scale>
+    //   axis <name> pad <PadAxis> <scale>
+    pub fn load(path: &str) -> Result<InputMap, String> {
+        let text = std::fs::read_to_string(path).map_err(|e| format!("{}: {}", path, e))?;
+        InputMap::parse(&text, path)
+    }
+
+    // the bindings of res/input.cfg as it was when the game was built
+    pub fn defaults() -> InputMap {
+        InputMap::parse(include_str!("../../../res/input.cfg"), "built in input.cfg").unwrap()
+    }
+
+    // a missing or broken file is reported and the built in bindings are used
+    pub fn load_or_defaults(path: &str) -> InputMap {
+        InputMap::load(path).unwrap_or_else(|e| {
+            println!("could not load the bindings, using the built in ones: {}", e);
+            InputMap::defaults()
+        })
+    }
+
+    // path is only used in the errors
+    fn parse(text: &str, path: &str) -> Result<InputMap, String> {
+        let mut map = InputMap::new();
+
+        for (i, line) in text.lines().enumerate() {
+            let line = line.split('#').next().unwrap_or("").trim();
+            if line.is_empty() {
+                continue;
+            }
+            let error = |what: &str| format!("{}:{}: {}", path, i + 1, what);
+            let words: Vec<&str> = line.split_whitespace().collect();
+            let scale = |word: Option<&&str>| word.and_then(|w| w.parse::<f32>().ok()).ok_or_else(|| error("expected a scale"));
+
+            match (words[0], words.len()) {
+                ("action", 4) => {
+                    let binding = Binding::parse(words[2], words[3]).ok_or_else(|| error(&format!("unknown binding {} {}", words[2], words[3])))?;
+                    map.bind(words[1], binding);
+                },
+                ("axis", 7) | ("axis", 8) if words[2] == "buttons" => {
+                    let positive = Binding::parse(words[3], words[4]).ok_or_else(|| error(&format!("unknown binding {} {}", words[3], words[4])))?;
+                    let negative = Binding::parse(words[5], words[6]).ok_or_else(|| error(&format!("unknown binding {} {}", words[5], words[6])))?;
+                    let scale = if words.len() == 8 { scale(words.get(7))? } else { 1.0 };
+                    map.bind_axis(words[1], AxisSource::Buttons { positive, negative }, scale);
+                },
+                ("axis", 4) => {
+                    let source = match words[2] {
+                        "mouse_x" => AxisSource::MouseX,
+                        "mouse_y" => AxisSource::MouseY,
+                        "wheel" => AxisSource::Wheel,
+                        other => return Err(error(&format!("unknown axis source {}", other))),
+                    };
+                    map.bind_axis(words[1], source, scale(words.get(3))?);
+                },
+                ("axis", 5) if words[2] == "pad" => {
+                    let axis = pad_axis_from_name(words[3]).ok_or_else(|| error(&format!("unknown pad axis {}", words[3])))?;
+                    map.bind_axis(words[1], AxisSource::Pad(axis), scale(words.get(4))?);
+                },
+                _ => return Err(error(&format!("can not read {}", line))),
+            }
+        }
+        Ok(map)
+    }
+
+    // writes the current bindings in the format load reads
+    pub fn save(&self, path: &str) -> std::io::Result<()> {
+        let mut lines: Vec<String> = Vec::new();
+        let mut actions: Vec<&String> = self.actions.keys().collect();
+        actions.sort();
+        for action in actions {
+            for binding in self.actions[action].iter() {
+                lines.push(format!("action {} {}", action, binding.describe()));
+            }
+        }
+        let mut axes: Vec<&String> = self.axes.keys().collect();
+        axes.sort();
+        for axis in axes {
+            for binding in self.axes[axis].iter() {
+                let source = match binding.source {
+                    AxisSource::Buttons { positive, negative } => format!("buttons {} {}", positive.describe(), negative.describe()),
+                    AxisSource::MouseX => "mouse_x".to_string(),
+                    AxisSource::MouseY => "mouse_y".to_string(),
+                    AxisSource::Wheel => "wheel".to_string(),
+                    AxisSource::Pad(axis) => format!("pad {:?}", axis),
+                };
+                lines.push(format!("axis {} {} {}", axis, source, binding.scale));
+            }
+        }
+        lines.push(String::new());
+        std::fs::write(path, lines.join("\n"))
+    }
+
+    pub fn bind(&mut self, action: &str, binding: Binding) {
+        let bindings = self.actions.entry(action.to_string()).or_default();
+        if !bindings.contains(&binding) {
+            bindings.push(binding);
+        }
+    }
+
+    pub fn unbind(&mut self, action: &str) {
+        self.actions.remove(action);
+    }
+
+    // replaces every binding of the action
+    pub fn rebind(&mut self, action: &str, binding: Binding) {
+        self.unbind(action);
+        self.bind(action, binding);
+    }
+
+    pub fn bind_axis(&mut self, axis: &str, source: AxisSource, scale: f32) {
+        self.axes.entry(axis.to_string()).or_default().push(AxisBinding { source, scale });
+    }
+
+    // the next key or mouse button pressed replaces the bindings of the action
+    pub fn start_rebind(&mut self, action: &str) {
+        self.rebinding = Some(action.to_string());
+    }
+
+    pub fn rebinding(&self) -> Option<&str> {
+        self.rebinding.as_deref()
+    }
+
+    // sorted, for listing them
+    pub fn action_names(&self) -> Vec<String> {
+        let mut names: Vec<String> = self.actions.keys().cloned().collect();
+        names.sort();
+        names
+    }
+
+    pub fn describe_action(&self, action: &str) -> String {
+        match self.actions.get(action) {
+            Some(bindings) => bindings.iter().map(|b| b.describe()).collect::<Vec<String>>().join(", "),
+            None => String::new(),
+        }
+    }
+
+    pub fn handle_window_event(&mut self, event: &glutin::WindowEvent) {
+        match event {
+            glutin::WindowEvent::KeyboardInput { input, .. } => {
+                // a press is reported for both, the virtual key and the physical position.
+                // while rebinding a named key wins over its scan code
+                let rebinding = self.rebinding.is_some();
+                if let Some(key) = input.virtual_keycode {
+                    self.device(InputEvent::Button(Binding::Key(key), input.state));
+                }
+                if !(rebinding && input.virtual_keycode.is_some()) {
+                    self.device(InputEvent::Button(Binding::ScanCode(input.scancode), input.state));
+                }
+            },
+            glutin::WindowEvent::MouseInput { state, button, .. } => self.device(InputEvent::Button(Binding::Mouse(*button), *state)),
+            glutin::WindowEvent::CursorMoved { position, .. } => self.device(InputEvent::Cursor(position.x as f32, position.y as f32)),
+            glutin::WindowEvent::MouseWheel { delta, .. } => {
+                let steps = match delta {
+                    glutin::MouseScrollDelta::LineDelta(_, y) => *y,
+                    glutin::MouseScrollDelta::PixelDelta(p) => p.y as f32 / 40.0,
+                };
+                self.device(InputEvent::Wheel(steps));
+            },
+            // nothing stays held down while the window doesn't get the release
+            glutin::WindowEvent::Focused(false) => self.device(InputEvent::ReleaseAll),
+            _ => (),
+        }
+    }
+
+    pub fn handle_device_event(&mut self, event: &glutin::DeviceEvent) {
+        if let glutin::DeviceEvent::MouseMotion { delta } = event {
+            self.device(InputEvent::Motion(delta.0 as f32, delta.1 as f32));
+        }
+    }
+
+    pub fn handle_pad_button(&mut self, button: PadButton, state: ElementState) {
+        self.device(InputEvent::Button(Binding::Pad(button), state));
+    }
+
+    // value is the raw position reported by the pad, the half way bindings follow it
+    pub fn handle_pad_axis(&mut self, axis: PadAxis, value: f32) {
+        self.device(InputEvent::PadAxis(axis, value));
+    }
+
+    // what TextInput::handle_event made of a window event
+    pub fn handle_text(&mut self, event: TextEvent) {
+        self.device(InputEvent::Text(event));
+    }
+
+    // the pad went away, everything it held is released
+    pub fn release_pad(&mut self) {
+        self.device(InputEvent::ReleasePad);
+    }
+
+    // while suspended the keyboard drives no action, held keys are released first.
+    // mouse and gamepad bindings keep working
+    pub fn suspend_keys(&mut self, suspended: bool) {
+        if suspended && !self.keys_suspended {
+            let keys: Vec<Binding> = self.down.iter().cloned().filter(|b| b.is_key()).collect();
+            for key in keys {
+                self.device(InputEvent::Button(key, ElementState::Released));
+            }
+        }
+        self.keys_suspended = suspended;
+    }
+
+    fn device(&mut self, event: InputEvent) {
+        if !self.live {
+            return;
+        }
+        if let InputEvent::Button(binding, _) = event {
+            if self.keys_suspended && binding.is_key() {
+                return;
+            }
+        }
+        if self.capture {
+            self.captured.push(event.clone());
+        }
+        self.apply(event);
+    }
+
+    // the device events applied since the last call, in order
+    pub fn take_captured(&mut self) -> Vec<InputEvent> {
+        self.captured.drain(..).collect()
+    }
+
+    pub fn apply(&mut self, event: InputEvent) {
+        match event {
+            InputEvent::Button(binding, state) => self.set(binding, state),
+            InputEvent::Motion(x, y) => {
+                self.mouse_delta.0 += x;
+                self.mouse_delta.1 += y;
+            },
+            InputEvent::Wheel(steps) => self.wheel += steps,
+            InputEvent::Cursor(x, y) => self.cursor = (x, y),
+            InputEvent::Text(text) => self.text.push(text),
+            InputEvent::PadAxis(axis, value) => {
+                self.pad_axes.insert(axis, value);
+                for axis in std::iter::once(axis).chain(axis.partner()) {
+                    let value = self.pad_axis(axis);
+                    let state = |held: bool| if held { ElementState::Pressed } else { ElementState::Released };
+                    self.set(Binding::PadAxis { axis, positive: true }, state(value > 0.5));
+                    self.set(Binding::PadAxis { axis, positive: false }, state(value < -0.5));
+                }
+            },
+            InputEvent::ReleaseAll => {
+                for binding in self.down.drain() {
+                    self.released.insert(binding);
+                }
+            },
+            InputEvent::ReleasePad => {
+                self.pad_axes.clear();
+                let pad: Vec<Binding> = self.down.iter().cloned().filter(|b| matches!(b, Binding::Pad(_) | Binding::PadAxis { .. })).collect();
+                for binding in pad {
+                    self.set(binding, ElementState::Released);
+                }
+            },
+        }
+    }
+
+    // with the dead zone cut out and the rest stretched, so the value still starts at 0 and reaches 1
+    pub fn pad_axis(&self, axis: PadAxis) -> f32 {
+        let raw = |a: PadAxis| self.pad_axes.get(&a).cloned().unwrap_or(0.0);
+        let value = raw(axis);
+        match axis.partner() {
+            Some(partner) => {
+                let length = (value * value + raw(partner) * raw(partner)).sqrt();
+                if length <= self.stick_dead_zone {
+                    return 0.0;
+                }
+                value / length * ((length - self.stick_dead_zone) / (1.0 - self.stick_dead_zone)).min(1.0)
+            },
+            None => {
+                if value <= self.trigger_dead_zone {
+                    return 0.0;
+                }
+                ((value - self.trigger_dead_zone) / (1.0 - self.trigger_dead_zone)).min(1.0)
+            },
+        }
+    }
+
+    fn set(&mut self, binding: Binding, state: ElementState) {
+        match state {
+            ElementState::Pressed => {
+                // key repeat sends presses without releases in between
+                if self.down.insert(binding) {
+                    if let Some(action) = self.rebinding.take() {
+                        println!("bound {} to {}", action, binding.describe());
+                        self.rebind(&action, binding);
+                        return;
+                    }
+                    self.pressed.insert(binding);
+                }
+            },
+            ElementState::Released => {
+                if self.down.remove(&binding) {
+                    self.released.insert(binding);
+                }
+            },
+        }
+    }
+
+    // logical pixels from the top left corner of the window
+    pub fn cursor(&self) -> (f32, f32) {
+        self.cursor
+    }
+
+    // typed since the last update
+    pub fn text(&self) -> &[TextEvent] {
+        &self.text
+    }
+
+    // the raw state of a binding, for what reads the mouse without an action like the ui
+    pub fn binding_down(&self, binding: Binding) -> bool {
+        self.down.contains(&binding)
+    }
+
+    pub fn binding_pressed(&self, binding: Binding) -> bool {
+        self.pressed.contains(&binding)
+    }
+
+    pub fn binding_released(&self, binding: Binding) -> bool {
+        self.released.contains(&binding)
+    }
+
+    fn bindings(&self, action: &str) -> &[Binding] {
+        self.actions.get(action).map(|b| b.as_slice()).unwrap_or(&[])
+    }
+
+    // held down
+    pub fn pressed(&self, action: &str) -> bool {
+        self.bindings(action).iter().any(|b| self.down.contains(b))
+    }
+
+    // went down since the last update
+    pub fn just_pressed(&self, action: &str) -> bool {
+        self.bindings(action).iter().any(|b| self.pressed.contains(b))
+    }
+
+    pub fn just_released(&self, action: &str) -> bool {
+        self.bindings(action).iter().any(|b| self.released.contains(b))
+    }
+
+    // 1 while positive is held, -1 while negative is, 0 for both or none
+    pub fn action_axis(&self, positive: &str, negative: &str) -> f32 {
+        let value = |held: bool| if held { 1.0 } else { 0.0 };
+        value(self.pressed(positive)) - value(self.pressed(negative))
+    }
+
+    // the sum over every binding of the axis
+    pub fn axis_value(&self, axis: &str) -> f32 {
+        let bindings = match self.axes.get(axis) {
+            Some(b) => b,
+            None => return 0.0,
+        };
+        bindings.iter().map(|b| {
+            let value = match b.source {
+                AxisSource::Buttons { positive, negative } => {
+                    let p = if self.down.contains(&positive) { 1.0 } else { 0.0 };
+                    let n = if self.down.contains(&negative) { 1.0 } else { 0.0 };
+                    p - n
+                },
+                AxisSource::MouseX => self.mouse_delta.0,
+                AxisSource::MouseY => self.mouse_delta.1,
+                AxisSource::Wheel => self.wheel,
+                AxisSource::Pad(axis) => self.pad_axis(axis),
+            };
+            value * b.scale
+        }).sum()
+    }
+
+    // call at the end of every update, held buttons stay down
+    pub fn end_update(&mut self) {
+        self.pressed.clear();
+        self.released.clear();
+        self.mouse_delta = (0.0, 0.0);
+        self.wheel = 0.0;
+        self.text.clear();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn key(key: VirtualKeyCode, state: ElementState) -> InputEvent {
+        InputEvent::Button(Binding::Key(key), state)
+    }
+
+    #[test]
+    fn missing_or_broken_bindings_fall_back_to_the_built_in_ones() {
+        let defaults = InputMap::defaults();
+        assert!(defaults.action_names().contains(&"move_forward".to_string()));
+
+        let missing = InputMap::load_or_defaults("./res/no_such_input.cfg");
+        assert_eq!(missing.action_names(), defaults.action_names());
+
+        let path = std::env::temp_dir().join(format!("cc_broken_input_{}.cfg", std::process::id()));
+        std::fs::write(&path, "action jump key NoSuchKey\n").unwrap();
+        let broken = InputMap::load_or_defaults(&path.to_string_lossy());
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(broken.action_names(), defaults.action_names());
+        assert_eq!(broken.describe_action("move_forward"), defaults.describe_action("move_forward"));
+    }
+
+    #[test]
+    fn a_focused_text_field_suspends_the_key_bindings() {
+        let mut map = InputMap::new();
+        map.bind("jump", Binding::Key(VirtualKeyCode::Space));
+        map.bind("fire", Binding::Mouse(MouseButton::Left));
+
+        map.device(key(VirtualKeyCode::Space, ElementState::Pressed));
+        assert!(map.pressed("jump"));
+        map.end_update();
+
+        // held keys are let go when the field takes the keyboard
+        map.suspend_keys(true);
+        assert!(!map.pressed("jump"));
+        assert!(map.just_released("jump"));
+        map.end_update();
+
+        map.device(key(VirtualKeyCode::Space, ElementState::Pressed));
+        map.device(InputEvent::Text(TextEvent::Char(' ')));
+        map.device(InputEvent::Button(Binding::Mouse(MouseButton::Left), ElementState::Pressed));
+        assert!(!map.just_pressed("jump"));
+        assert!(map.just_pressed("fire"));
+        assert_eq!(map.text, vec![TextEvent::Char(' ')]);
+        map.device(key(VirtualKeyCode::Space, ElementState::Released));
+        map.end_update();
+
+        map.suspend_keys(false);
+        map.device(key(VirtualKeyCode::Space, ElementState::Pressed));
+        assert!(map.just_pressed("jump"));
+    }
+}
@@ -1,22 +1,94 @@
 
 
 
+// written before clippy ran on the crate
+#![allow(clippy::redundant_field_names, clippy::type_complexity, clippy::single_match, clippy::explicit_counter_loop)]
+
 pub mod loaders{
     use assimp::Importer;
     use glium::index::PrimitiveType;
+    use glium::backend::Facade;
 
-    use crate::my_game_engine::my_game_logic::my_renderer::{MyArmatureSkinVertex,VertexWeights,MyJoint,ModelRst,MyVertex,StaticMesh,AnimatedMesh};
+    use crate::my_game_engine::my_game_logic::my_renderer::{MyArmatureSkinVertex,VertexWeights,MyJoint,ModelRst,MyVertex,StaticMesh,AnimatedMesh,MAX_JOINTS,next_pose_id};
     use cgmath::Matrix3;
     use cgmath::Quaternion;
+    use std::rc::Rc;
+    use crate::my_game_engine::my_game_logic::my_bounds::{Aabb,Bounds};
+
+    // poses sampled per key frame interval for the bounds of a clip, the joints rotate along
+    // arcs in between and a straight line between the key frames would cut corners
+    const CLIP_BOUNDS_STEPS: usize = 8;
 
-    pub fn load_texture(display:&mut glium::Display, path:&str) -> glium::texture::SrgbTexture2d {
+    pub fn load_texture<F: Facade>(display:&F, path:&str) -> glium::texture::SrgbTexture2d {
         let image = image::open(path).unwrap().to_rgba();
         let image_dimensions = image.dimensions();
         let image = glium::texture::RawImage2d::from_raw_rgba_reversed(&image.into_raw(), image_dimensions);
         glium::texture::SrgbTexture2d::new(display, image).unwrap()
     }
 
-    pub fn load_static_meshes(display:&mut glium::Display, path:&str) -> Vec<(ModelRst, StaticMesh)>{
+    // flipped like load_texture so both share the uvs, but not srgb decoded
+    pub fn load_normal_map<F: Facade>(display:&F, path:&str) -> glium::texture::Texture2d {
+        let image = image::open(path).unwrap().to_rgba();
+        let image_dimensions = image.dimensions();
+        let image = glium::texture::RawImage2d::from_raw_rgba_reversed(&image.into_raw(), image_dimensions);
+        glium::texture::Texture2d::new(display, image).unwrap()
+    }
+
+    // color grading luts and other data textures must not be srgb decoded
+    pub fn load_linear_texture<F: Facade>(display:&F, path:&str) -> glium::texture::Texture2d {
+        let image = image::open(path).unwrap().to_rgba();
+        let image_dimensions = image.dimensions();
+        let image = glium::texture::RawImage2d::from_raw_rgba(image.into_raw(), image_dimensions);
+        glium::texture::Texture2d::new(display, image).unwrap()
+    }
+
+    // faces in +x, -x, +y, -y, +z, -z order, all the same square size
+    pub fn load_cubemap<F: Facade>(display:&F, paths:[&str;6]) -> glium::texture::SrgbCubemap {
+        use glium::Surface;
+        use glium::texture::CubeLayer;
+        use glium::framebuffer::SimpleFrameBuffer;
+
+        let layers = [CubeLayer::PositiveX, CubeLayer::NegativeX, CubeLayer::PositiveY,
+                      CubeLayer::NegativeY, CubeLayer::PositiveZ, CubeLayer::NegativeZ];
+
+        let size = image::open(paths[0]).unwrap().to_rgba().dimensions().0;
+        let cubemap = glium::texture::SrgbCubemap::empty(display, size).unwrap();
+
+        for (path, layer) in paths.iter().zip(layers.iter()) {
+            let image = image::open(path).unwrap().to_rgba();
+            let image_dimensions = image.dimensions();
+            // cube faces are addressed from the top left, no flip
+            let image = glium::texture::RawImage2d::from_raw_rgba(image.into_raw(), image_dimensions);
+            let face = glium::texture::SrgbTexture2d::new(display, image).unwrap();
+
+            let source = SimpleFrameBuffer::new(display, &face).unwrap();
+            let target = SimpleFrameBuffer::new(display, cubemap.main_level().image(*layer)).unwrap();
+            source.blit_whole_color_to(&target, &glium::BlitTarget {
+                left: 0,
+                bottom: 0,
+                width: size as i32,
+                height: size as i32,
+            }, glium::uniforms::MagnifySamplerFilter::Linear);
+        }
+        cubemap
+    }
+
+    pub fn load_hdr_texture<F: Facade>(display:&F, path:&str) -> glium::texture::Texture2d {
+        use std::io::BufReader;
+        use std::fs::File;
+
+        let decoder = image::hdr::HDRDecoder::new(BufReader::new(File::open(path).unwrap())).unwrap();
+        let meta = decoder.metadata();
+        let pixels = decoder.read_image_hdr().unwrap();
+        let data: Vec<f32> = pixels.iter().flat_map(|p| p.data.iter().cloned()).collect();
+
+        let image = glium::texture::RawImage2d::from_raw_rgb_reversed(&data, (meta.width, meta.height));
+        glium::texture::Texture2d::with_format(display, image,
+            glium::texture::UncompressedFloatFormat::F16F16F16,
+            glium::texture::MipmapsOption::NoMipmap).unwrap()
+    }
+
+    pub fn load_static_meshes<F: Facade>(display:&F, path:&str) -> Vec<(ModelRst, StaticMesh)>{
 
         let mut smv :Vec<(ModelRst, StaticMesh)> = Vec::new();
         let mut importer = Importer::new();
@@ -32,15 +104,11 @@ pub mod loaders{
             let tex:Vec<[f32;3]>= mesh.texture_coords_iter(0).map(|v| v.into()).collect();
 
 
-            let mut verts:Vec<MyVertex> = Vec::new();
-
-            for i in 0..pos.len(){
-                verts.push(MyVertex {
-                    position: pos[i],
-                    normal: norm[i],
-                    texture: [tex[i][0],tex[i][1]]
-                });
-            }
+            let verts:Vec<MyVertex> = pos.iter().zip(norm.iter()).zip(tex.iter()).map(|((p, n), t)| MyVertex {
+                position: *p,
+                normal: *n,
+                texture: [t[0],t[1]]
+            }).collect();
 
             let vb = glium::VertexBuffer::new(display, &verts);
             let mut indices:Vec<u16> = Vec::with_capacity(mesh.num_faces() as usize * 3);
@@ -52,13 +120,11 @@ pub mod loaders{
 
             let ib = glium::IndexBuffer::new(display, PrimitiveType::TrianglesList, &indices);
 
-            let rotation;
-
-            if path.ends_with(".dae"){
-                rotation = Matrix4::from_angle_x(cgmath::Rad(std::f32::consts::PI*3.0/2.0));
+            let rotation = if path.ends_with(".dae"){
+                Matrix4::from_angle_x(cgmath::Rad(std::f32::consts::PI*3.0/2.0))
             }else {
-                rotation =  Matrix4::identity();
-            }
+                Matrix4::identity()
+            };
 
             use cgmath::{Matrix4,SquareMatrix};
              let m =(
@@ -67,8 +133,11 @@ pub mod loaders{
                     scale: Matrix4::identity(),
                     translation: Matrix4::identity()
                 }, StaticMesh{
-                    vertices: (vb.unwrap()),
-                    indices: (ib.unwrap())
+                    vertices: Rc::new(vb.unwrap()),
+                    indices: Rc::new(ib.unwrap()),
+                    bounds: Bounds::from_points(verts.iter().map(|v| v.position)),
+                    cpu_vertices: Rc::new(verts.clone()),
+                    cpu_indices: Rc::new(indices.clone()),
             });
             smv.push(m);
         }
@@ -76,7 +145,7 @@ pub mod loaders{
     }
 
 
-    pub fn load_animated_collada_mesh(display:&mut glium::Display, path:&str) -> (ModelRst,AnimatedMesh){
+    pub fn load_animated_collada_mesh<F: Facade>(display:&F, path:&str) -> (ModelRst,AnimatedMesh){
 
         use cgmath::{Matrix4,SquareMatrix};
         let cd = collada::document::ColladaDocument::from_path(std::path::Path::new(path)).unwrap();
@@ -218,7 +287,7 @@ pub mod loaders{
 
         let c: cgmath::Matrix4<f32> = cgmath::Matrix4::identity();
         let c:[[f32;4];4] = c.into();
-        let current_transforms= [c;64];
+        let current_transforms= [c;MAX_JOINTS];
 
         let mut m =(
             ModelRst{
@@ -226,12 +295,18 @@ pub mod loaders{
                 scale: Matrix4::identity(),
                 translation: Matrix4::identity()
             }, AnimatedMesh{
-                vertices: vb.unwrap(),
-                indices: ib.unwrap(),
+                vertices: Rc::new(vb.unwrap()),
+                indices: Rc::new(ib.unwrap()),
+                cpu_vertices: Rc::new(mesh.clone()),
+                cpu_indices: Rc::new(indices.clone()),
                 skeleton: skeleton,
+                bounds: Bounds::from_points(mesh.iter().map(|v| v.position)),
                 current_pose: current_transforms,
                 current_time_sec: 0.0,
-                running: false
+                running: false,
+                joint_buffer: AnimatedMesh::new_joint_buffer(display),
+                pose_dirty: true,
+                pose_id: next_pose_id()
             }
         );
 
@@ -272,20 +347,39 @@ pub mod loaders{
 
             }
         }
-        let mut i = 0;
-        for pose in &pose_transforms{
-            let mut y = 0;
-            for mat in pose {
-                println!("i:{} y:{} m:{:#?}",i,y,mat);
-                y+=1;
+        // skin every vertex with the poses at and between the key frames, interpolated the way
+        // calculate_current_pose does. the union is the bounds of the whole clip
+        let mut clip_aabb = Aabb::empty();
+        let key_frames = m.1.skeleton[0].time_stamp_c as usize;
+        let mut poses = Vec::new();
+        for kf in 0..key_frames.saturating_sub(1){
+            for s in 0..=CLIP_BOUNDS_STEPS{
+                poses.push((kf, s as f32 / CLIP_BOUNDS_STEPS as f32));
             }
-            i+=1;
+        }
+        for &(kf, a) in &poses{
+            let skin:Vec<Matrix4<f32>> = m.1.skeleton.iter().map(|joint| joint.skin_matrix(kf, a)).collect();
+            for v in &mesh{
+                let p = cgmath::Vector4{x: v.position[0], y: v.position[1], z: v.position[2], w: 1.0};
+                let mut acc = cgmath::Vector4{x: 0.0, y: 0.0, z: 0.0, w: 0.0};
+                for i in 0..v.joint_c as usize{
+                    if v.weights[i] > 0.0 {
+                        acc += (skin[v.joint_mi[i] as usize]*p)*v.weights[i];
+                    }else {
+                        break;
+                    }
+                }
+                clip_aabb.extend(acc.truncate());
+            }
+        }
+        if !poses.is_empty() {
+            m.1.bounds = Bounds::from_aabb(clip_aabb);
         }
 
         m
     }
 
-    pub fn load_static_collada_mesh(display:&mut glium::Display, path:&str) -> (ModelRst, StaticMesh){
+    pub fn load_static_collada_mesh<F: Facade>(display:&F, path:&str) -> (ModelRst, StaticMesh){
 
         let cd = collada::document::ColladaDocument::from_path(std::path::Path::new(path)).unwrap();
 
@@ -364,8 +458,11 @@ pub mod loaders{
                 scale: Matrix4::identity(),
                 translation: Matrix4::identity()
             }, StaticMesh{
-                vertices: (vb.unwrap()),
-                indices: (ib.unwrap())
+                vertices: Rc::new(vb.unwrap()),
+                indices: Rc::new(ib.unwrap()),
+                bounds: Bounds::from_points(mesh.iter().map(|v| v.position)),
+                cpu_vertices: Rc::new(mesh.clone()),
+                cpu_indices: Rc::new(indices.clone()),
             });
         m
     }
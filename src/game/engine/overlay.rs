@@ -0,0 +1,199 @@
+use std::rc::Rc;
+use glium::backend::Facade;
+use glium::texture::Texture2d;
+use glium::uniforms::{Sampler,MinifySamplerFilter,MagnifySamplerFilter};
+use crate::my_game_engine::my_game_logic::my_text::FontAtlas;
+use crate::my_game_engine::my_game_logic::my_renderer::RenderStats;
+use crate::my_game_engine::my_game_logic::my_shaders::{ShaderProgram,GlslTarget};
+
+#[derive(Copy, Clone)]
+pub struct OverlayVertex {
+    position: [f32; 2],
+    tex_coords: [f32; 2],
+    color: [f32; 4],
+}
+#[allow(deprecated)]
+const _: () = { implement_vertex!(OverlayVertex, position, tex_coords, color); };
+
+enum OverlayTexture {
+    // text and solid quads share the font atlas
+    Font,
+    Image(Rc<Texture2d>),
+}
+
+struct OverlayBatch {
+    texture: OverlayTexture,
+    vertices: Vec<OverlayVertex>,
+}
+
+// screen space quads and text in pixels from the top left corner, drawn over the post processed frame.
+// colors are display values, they are written without srgb conversion
+pub struct Overlay {
+    pub font: FontAtlas,
+    batches: Vec<OverlayBatch>,
+    program: ShaderProgram,
+}
+
+impl Overlay {
+
+    pub fn new<F: Facade>(display: &F, glsl: &GlslTarget) -> Overlay {
+        Overlay{
+            font: FontAtlas::load(display, "./res/fonts/DejaVuSansMono.ttf", 16.0),
+            batches: Vec::new(),
+            program: ShaderProgram::load_with_output(display, glsl, "./res/shader/overlay_vs.glsl", "./res/shader/overlay_fs.glsl", true),
+        }
+    }
+
+    pub fn reload_shaders<F: Facade>(&mut self, display: &F, changed: &[std::path::PathBuf]) {
+        self.program.reload_if_changed(display, changed);
+    }
+
+    fn batch(&mut self, texture: OverlayTexture) -> &mut Vec<OverlayVertex> {
+        let reuse = match (self.batches.last(), &texture) {
+            (Some(OverlayBatch { texture: OverlayTexture::Font, .. }), OverlayTexture::Font) => true,
+            (Some(OverlayBatch { texture: OverlayTexture::Image(a), .. }), OverlayTexture::Image(b)) => Rc::ptr_eq(a, b),
+            _ => false,
+        };
+        if !reuse {
+            self.batches.push(OverlayBatch { texture, vertices: Vec::new() });
+        }
+        &mut self.batches.last_mut().unwrap().vertices
+    }
+
+    // position is the top left corner in pixels
+    fn push_quad(vertices: &mut Vec<OverlayVertex>, position: [f32; 2], size: [f32; 2], uv_min: [f32; 2], uv_max: [f32; 2], color: [f32; 4]) {
+        let ([x, y], [w, h]) = (position, size);
+        let corners = [
+            ([x, y], [uv_min[0], uv_min[1]]),
+            ([x + w, y], [uv_max[0], uv_min[1]]),
+            ([x + w, y + h], [uv_max[0], uv_max[1]]),
+            ([x, y + h], [uv_min[0], uv_max[1]]),
+        ];
+        for &i in [0, 1, 2, 0, 2, 3].iter() {
+            vertices.push(OverlayVertex { position: corners[i].0, tex_coords: corners[i].1, color });
+        }
+    }
+
+    pub fn quad(&mut self, x: f32, y: f32, w: f32, h: f32, color: [f32; 4]) {
+        let white = self.font.white_uv;
+        let vertices = self.batch(OverlayTexture::Font);
+        Overlay::push_quad(vertices, [x, y], [w, h], white, white, color);
+    }
+
+    pub fn textured_quad(&mut self, x: f32, y: f32, w: f32, h: f32, texture: Rc<Texture2d>, color: [f32; 4]) {
+        let vertices = self.batch(OverlayTexture::Image(texture));
+        Overlay::push_quad(vertices, [x, y], [w, h], [0.0, 0.0], [1.0, 1.0], color);
+    }
+
+    // y is the top of the first line, scale is relative to the atlas pixel size
+    pub fn text(&mut self, x: f32, y: f32, text: &str, scale: f32, color: [f32; 4]) {
+        let mut quads = Vec::with_capacity(text.len());
+        let mut baseline = y + self.font.ascent * scale;
+        for line in text.lines() {
+            let mut pen = x;
+            for c in line.chars() {
+                let g = self.font.glyph(c);
+                if g.size[0] > 0.0 {
+                    quads.push((pen + g.offset[0] * scale, baseline + g.offset[1] * scale, g.size[0] * scale, g.size[1] * scale, g.uv_min, g.uv_max));
+                }
+                pen += g.advance * scale;
+            }
+            baseline += self.font.line_height * scale;
+        }
+
+        let vertices = self.batch(OverlayTexture::Font);
+        for (qx, qy, qw, qh, uv_min, uv_max) in quads {
+            Overlay::push_quad(vertices, [qx, qy], [qw, qh], uv_min, uv_max, color);
+        }
+    }
+
+    pub fn draw<F: Facade, S: glium::Surface>(&self, display: &F, target_frame: &mut S) {
+        let (w, h) = target_frame.get_dimensions();
+        let screen_size = [w as f32, h as f32];
+        let params = glium::DrawParameters {
+            blend: glium::Blend::alpha_blending(),
+            ..Default::default()
+        };
+        let indices = glium::index::NoIndices(glium::index::PrimitiveType::TrianglesList);
+
+        for batch in self.batches.iter().filter(|b| !b.vertices.is_empty()) {
+            let vb = glium::VertexBuffer::new(display, &batch.vertices).unwrap();
+            match batch.texture {
+                OverlayTexture::Font => {
+                    let atlas = Sampler::new(&self.font.texture)
+                        .minify_filter(MinifySamplerFilter::Linear)
+                        .magnify_filter(MagnifySamplerFilter::Linear);
+                    target_frame.draw(&vb, indices, &self.program,
+                        &uniform! { screen_size: screen_size, overlay_texture: atlas, alpha_only: true },
+                        &params).unwrap();
+                },
+                OverlayTexture::Image(ref texture) => {
+                    let image = Sampler::new(&**texture)
+                        .minify_filter(MinifySamplerFilter::Linear)
+                        .magnify_filter(MagnifySamplerFilter::Linear);
+                    target_frame.draw(&vb, indices, &self.program,
+                        &uniform! { screen_size: screen_size, overlay_texture: image, alpha_only: false },
+                        &params).unwrap();
+                },
+            }
+        }
+    }
+
+    pub fn clear(&mut self) {
+        self.batches.clear();
+    }
+}
+
+// fps, frame time and the renderer stats of the last frame in the top left corner
+pub struct StatsHud {
+    pub visible: bool,
+    pub fps: u32,
+    pub frame_ms: f32,
+    frames: u32,
+    window_start: time::PreciseTime,
+    last_frame: time::PreciseTime,
+}
+
+impl Default for StatsHud {
+    fn default() -> StatsHud {
+        StatsHud::new()
+    }
+}
+
+impl StatsHud {
+
+    pub fn new() -> StatsHud {
+        StatsHud{
+            visible: false,
+            fps: 0,
+            frame_ms: 0.0,
+            frames: 0,
+            window_start: time::PreciseTime::now(),
+            last_frame: time::PreciseTime::now(),
+        }
+    }
+
+    pub fn frame(&mut self) {
+        let now = time::PreciseTime::now();
+        let dt_ms = self.last_frame.to(now).num_microseconds().unwrap_or(0) as f32 / 1000.0;
+        self.last_frame = now;
+        // smoothed so the number stays readable
+        self.frame_ms = self.frame_ms * 0.9 + dt_ms * 0.1;
+
+        self.frames += 1;
+        if self.window_start.to(now).num_milliseconds() >= 1000 {
+            self.fps = self.frames;
+            self.frames = 0;
+            self.window_start = now;
+        }
+    }
+
+    pub fn draw(&self, overlay: &mut Overlay, stats: &RenderStats) {
+        let text = format!(
+            "fps        {}\nframe      {:.2} ms\ndraw calls {}\ntriangles  {}\nculled     {}/{}",
+            self.fps, self.frame_ms, stats.draw_calls, stats.triangles, stats.culled, stats.submitted);
+        let (w, h) = overlay.font.measure(&text, 1.0);
+        overlay.quad(4.0, 4.0, w + 8.0, h + 8.0, [0.0, 0.0, 0.0, 0.6]);
+        overlay.text(8.0, 8.0, &text, 1.0, [1.0, 1.0, 1.0, 1.0]);
+    }
+}
@@ -0,0 +1,282 @@
+use cgmath::{Matrix4,Vector3,Vector4,InnerSpace,SquareMatrix,Matrix};
+use crate::my_game_engine::my_game_logic::my_renderer::{ModelRst,StaticMesh,AnimatedMesh,MyArmatureSkinVertex};
+use crate::my_game_engine::my_game_logic::my_bounds::Aabb;
+
+// world space, dir is normalized so distances along it are in world units
+#[derive(Copy, Clone, Debug)]
+pub struct Ray {
+    pub origin: Vector3<f32>,
+    pub dir: Vector3<f32>,
+}
+
+impl Ray {
+    pub fn at(&self, t: f32) -> Vector3<f32> {
+        self.origin + self.dir * t
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Picked {
+    // indices into the model lists the pick was run on
+    Static(usize),
+    Animated(usize),
+}
+
+#[derive(Copy, Clone, Debug)]
+pub struct PickHit {
+    pub picked: Picked,
+    pub distance: f32,
+    pub point: Vector3<f32>,
+}
+
+// the cpu side copy of a mesh the ray is tested against, in model space
+pub trait Pickable {
+    fn aabb(&self) -> &Aabb;
+    fn positions(&self) -> Vec<Vector3<f32>>;
+    fn indices(&self) -> &[u16];
+}
+
+impl Pickable for StaticMesh {
+    fn aabb(&self) -> &Aabb {
+        &self.bounds.aabb
+    }
+
+    fn positions(&self) -> Vec<Vector3<f32>> {
+        self.cpu_vertices.iter().map(|v| Vector3::from(v.position)).collect()
+    }
+
+    fn indices(&self) -> &[u16] {
+        &self.cpu_indices
+    }
+}
+
+impl Pickable for AnimatedMesh {
+    fn aabb(&self) -> &Aabb {
+        &self.bounds.aabb
+    }
+
+    fn positions(&self) -> Vec<Vector3<f32>> {
+        skinned_positions(&self.cpu_vertices, &self.current_pose)
+    }
+
+    fn indices(&self) -> &[u16] {
+        &self.cpu_indices
+    }
+}
+
+// the closest model under the ray. the bounds are tested first, only models whose box is hit
+// closer than the best hit so far are tested triangle by triangle
+pub fn pick<S: Pickable, A: Pickable>(ray: &Ray, models: &[(ModelRst, S)], animated_models: &[(ModelRst, A)]) -> Option<PickHit> {
+    let mut candidates: Vec<(f32, Picked, Matrix4<f32>)> = Vec::new();
+    for (i, model) in models.iter().enumerate() {
+        let transform = model.0.matrix();
+        if let Some(t) = model.1.aabb().transformed(&transform).ray_distance(ray.origin, ray.dir) {
+            candidates.push((t, Picked::Static(i), transform));
+        }
+    }
+    for (i, model) in animated_models.iter().enumerate() {
+        let transform = model.0.matrix();
+        if let Some(t) = model.1.aabb().transformed(&transform).ray_distance(ray.origin, ray.dir) {
+            candidates.push((t, Picked::Animated(i), transform));
+        }
+    }
+    candidates.sort_by(|a, b| a.0.partial_cmp(&b.0).unwrap_or(std::cmp::Ordering::Equal));
+
+    let mut best: Option<PickHit> = None;
+    for (box_distance, picked, transform) in candidates {
+        if let Some(ref hit) = best {
+            if box_distance > hit.distance {
+                break;
+            }
+        }
+        let distance = match picked {
+            Picked::Static(i) => intersect_mesh(ray, &models[i].1, &transform),
+            Picked::Animated(i) => intersect_mesh(ray, &animated_models[i].1, &transform),
+        };
+        if let Some(distance) = distance {
+            if best.map_or(true, |hit| distance < hit.distance) {
+                best = Some(PickHit { picked, distance, point: ray.at(distance) });
+            }
+        }
+    }
+    best
+}
+
+// the ray is moved into model space instead of every vertex into world space. dir is not
+// normalized again, so t along it stays the world distance
+fn local_ray(ray: &Ray, transform: &Matrix4<f32>) -> Option<Ray> {
+    let inverse = transform.invert()?;
+    Some(Ray{
+        origin: (inverse * ray.origin.extend(1.0)).truncate(),
+        dir: (inverse * ray.dir.extend(0.0)).truncate(),
+    })
+}
+
+fn intersect_mesh<M: Pickable>(ray: &Ray, mesh: &M, transform: &Matrix4<f32>) -> Option<f32> {
+    let local = local_ray(ray, transform)?;
+    intersect_triangles(&local, &mesh.positions(), mesh.indices())
+}
+
+// skinned with a pose the way mesh_vs.glsl does it. the pose is stored transposed for the row
+// vector multiplication in the shader
+fn skinned_positions(vertices: &[MyArmatureSkinVertex], pose: &[[[f32; 4]; 4]]) -> Vec<Vector3<f32>> {
+    let skins: Vec<Matrix4<f32>> = pose.iter().map(|m| Matrix4::from(*m).transpose()).collect();
+    vertices.iter().map(|v| {
+        let p = Vector4::new(v.position[0], v.position[1], v.position[2], 1.0);
+        let mut skinned = Vector4::new(0.0, 0.0, 0.0, 0.0);
+        for i in 0..(v.joint_c as usize).min(4) {
+            if v.weights[i] <= 0.0 {
+                break;
+            }
+            skinned += skins[v.joint_mi[i] as usize] * p * v.weights[i];
+        }
+        skinned.truncate()
+    }).collect()
+}
+
+fn intersect_triangles(ray: &Ray, positions: &[Vector3<f32>], indices: &[u16]) -> Option<f32> {
+    let mut best: Option<f32> = None;
+    for tri in indices.chunks(3).filter(|t| t.len() == 3) {
+        let (a, b, c) = (positions[tri[0] as usize], positions[tri[1] as usize], positions[tri[2] as usize]);
+        if let Some(t) = intersect_triangle(ray, a, b, c) {
+            if best.map_or(true, |best| t < best) {
+                best = Some(t);
+            }
+        }
+    }
+    best
+}
+
+// moeller trumbore, both faces count
+fn intersect_triangle(ray: &Ray, a: Vector3<f32>, b: Vector3<f32>, c: Vector3<f32>) -> Option<f32> {
+    let e1 = b - a;
+    let e2 = c - a;
+    let p = ray.dir.cross(e2);
+    let det = e1.dot(p);
+    if det.abs() < 1e-8 {
+        return None;
+    }
+    let inv_det = 1.0 / det;
+    let s = ray.origin - a;
+    let u = s.dot(p) * inv_det;
+    if !(0.0..=1.0).contains(&u) {
+        return None;
+    }
+    let q = s.cross(e1);
+    let v = ray.dir.dot(q) * inv_det;
+    if v < 0.0 || u + v > 1.0 {
+        return None;
+    }
+    let t = e2.dot(q) * inv_det;
+    if t > 0.0 { Some(t) } else { None }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use cgmath::SquareMatrix;
+
+    // a 2 x 2 square around the origin facing z, the box can be made deeper than the square
+    struct Square {
+        aabb: Aabb,
+    }
+
+    impl Square {
+        fn new(depth: f32) -> Square {
+            Square{ aabb: Aabb::from_points(vec![[-1.0, -1.0, -depth], [1.0, 1.0, depth]]) }
+        }
+    }
+
+    impl Pickable for Square {
+        fn aabb(&self) -> &Aabb {
+            &self.aabb
+        }
+
+        fn positions(&self) -> Vec<Vector3<f32>> {
+            vec![Vector3::new(-1.0, -1.0, 0.0), Vector3::new(1.0, -1.0, 0.0), Vector3::new(1.0, 1.0, 0.0), Vector3::new(-1.0, 1.0, 0.0)]
+        }
+
+        fn indices(&self) -> &[u16] {
+            &[0, 1, 2, 0, 2, 3]
+        }
+    }
+
+    fn at(x: f32, z: f32) -> ModelRst {
+        ModelRst{
+            rotation: Matrix4::identity(),
+            scale: Matrix4::identity(),
+            translation: Matrix4::from_translation(Vector3::new(x, 0.0, z)),
+        }
+    }
+
+    fn forward() -> Ray {
+        Ray{ origin: Vector3::new(0.0, 0.0, 0.0), dir: Vector3::new(0.0, 0.0, -1.0) }
+    }
+
+    const NONE: [(ModelRst, Square); 0] = [];
+
+    #[test]
+    fn triangles_are_hit_from_both_sides_and_only_ahead() {
+        let (a, b, c) = (Vector3::new(-1.0, -1.0, -2.0), Vector3::new(1.0, -1.0, -2.0), Vector3::new(0.0, 1.0, -2.0));
+        assert_eq!(intersect_triangle(&forward(), a, b, c), Some(2.0));
+        assert_eq!(intersect_triangle(&forward(), a, c, b), Some(2.0));
+        let beside = Ray{ origin: Vector3::new(0.9, 0.9, 0.0), ..forward() };
+        assert_eq!(intersect_triangle(&beside, a, b, c), None);
+        let behind = Ray{ dir: Vector3::new(0.0, 0.0, 1.0), ..forward() };
+        assert_eq!(intersect_triangle(&behind, a, b, c), None);
+        let parallel = Ray{ dir: Vector3::new(1.0, 0.0, 0.0), origin: Vector3::new(-5.0, 0.0, -2.0) };
+        assert_eq!(intersect_triangle(&parallel, a, b, c), None);
+    }
+
+    #[test]
+    fn the_closest_model_is_picked() {
+        // the far square has a deep box that the ray enters first, the near one still wins
+        let models = [(at(0.0, -10.0), Square::new(5.0)), (at(0.0, -8.0), Square::new(0.0))];
+        let hit = pick(&forward(), &models, &NONE).unwrap();
+        assert_eq!(hit.picked, Picked::Static(1));
+        assert!((hit.distance - 8.0).abs() < 1e-5);
+        assert!((hit.point - Vector3::new(0.0, 0.0, -8.0)).magnitude() < 1e-5);
+
+        let animated = [(at(0.0, -6.0), Square::new(0.0))];
+        assert_eq!(pick(&forward(), &models, &animated).unwrap().picked, Picked::Animated(0));
+    }
+
+    #[test]
+    fn scaled_models_keep_world_distances() {
+        let mut model = at(0.0, -4.0);
+        model.scale = Matrix4::from_scale(3.0);
+        let hit = pick(&Ray{ origin: Vector3::new(2.5, 0.0, 0.0), ..forward() }, &[(model, Square::new(0.0))], &NONE).unwrap();
+        assert!((hit.distance - 4.0).abs() < 1e-5);
+    }
+
+    #[test]
+    fn the_bounds_are_tested_before_the_triangles() {
+        // the triangles are under the ray, the box is not
+        let mut off = Square::new(0.0);
+        off.aabb = Aabb::from_points(vec![[5.0, 5.0, 0.0], [6.0, 6.0, 0.0]]);
+        assert!(pick(&forward(), &[(at(0.0, -5.0), off)], &NONE).is_none());
+    }
+
+    #[test]
+    fn a_ray_past_every_model_picks_nothing() {
+        let models = [(at(3.0, -5.0), Square::new(0.0)), (at(-3.0, -5.0), Square::new(0.0))];
+        assert!(pick(&forward(), &models, &NONE).is_none());
+        assert!(pick(&Ray{ dir: Vector3::new(0.0, 0.0, 1.0), ..forward() }, &models, &NONE).is_none());
+    }
+
+    #[test]
+    fn vertices_are_skinned_with_the_weighted_joints() {
+        let vertex = |weights: [f32; 4], joint_c: u8| MyArmatureSkinVertex{
+            position: [1.0, 0.0, 0.0], normal: [0.0, 0.0, 1.0], texture: [0.0, 0.0], weights, joint_mi: [0, 1, 0, 0], joint_c,
+        };
+        let identity: [[f32; 4]; 4] = Matrix4::identity().into();
+        // stored transposed, like calculate_current_pose does
+        let moved: [[f32; 4]; 4] = Matrix4::from_translation(Vector3::new(0.0, 2.0, 0.0)).transpose().into();
+        let pose = [identity, moved];
+        let positions = skinned_positions(&[vertex([1.0, 0.0, 0.0, 0.0], 1), vertex([0.5, 0.5, 0.0, 0.0], 2), vertex([0.0, 1.0, 0.0, 0.0], 2)], &pose);
+        assert_eq!(positions[0], Vector3::new(1.0, 0.0, 0.0));
+        assert_eq!(positions[1], Vector3::new(1.0, 1.0, 0.0));
+        // a zero weight ends the list
+        assert_eq!(positions[2], Vector3::new(0.0, 0.0, 0.0));
+    }
+}
@@ -0,0 +1,268 @@
+use glium::Surface;
+use glium::backend::Facade;
+use glium::framebuffer::SimpleFrameBuffer;
+use glium::texture::{Texture2d,DepthTexture2d,UncompressedFloatFormat,DepthFormat,MipmapsOption};
+use glium::uniforms::{Sampler,SamplerWrapFunction,MinifySamplerFilter,MagnifySamplerFilter};
+use std::rc::Rc;
+use crate::my_game_engine::my_game_logic::my_shaders::{ShaderProgram,GlslTarget};
+
+#[derive(Copy, Clone)]
+pub struct PostVertex {
+    position: [f32; 2],
+    tex_coords: [f32; 2],
+}
+#[allow(deprecated)]
+const _: () = { implement_vertex!(PostVertex, position, tex_coords); };
+
+pub enum PostEffect {
+    Bloom { threshold: f32, intensity: f32 },
+    Tonemap { exposure: f32 },
+    ColorGrading { lut: Texture2d },
+    Vignette { strength: f32, radius: f32 },
+    Gamma { gamma: f32 },
+    Fxaa,
+}
+
+impl PostEffect {
+    pub fn name(&self) -> &'static str {
+        match self {
+            PostEffect::Bloom{..} => "bloom",
+            PostEffect::Tonemap{..} => "tonemap",
+            PostEffect::ColorGrading{..} => "color_grading",
+            PostEffect::Vignette{..} => "vignette",
+            PostEffect::Gamma{..} => "gamma",
+            PostEffect::Fxaa => "fxaa",
+        }
+    }
+}
+
+pub struct PostPass {
+    pub effect: PostEffect,
+    pub enabled: bool,
+}
+
+// everything the scene and the chain render into, recreated when the frame size changes
+pub struct RenderTargets {
+    pub dimensions: (u32, u32),
+    pub color: Texture2d,
+    pub depth: DepthTexture2d,
+    ping: Texture2d,
+    pong: Texture2d,
+    bloom_a: Texture2d,
+    bloom_b: Texture2d,
+    pub oit_accum: Texture2d,
+    pub oit_weight: Texture2d,
+}
+
+impl RenderTargets {
+    fn new<F: Facade>(display: &F, dimensions: (u32, u32)) -> RenderTargets {
+        let (w, h) = dimensions;
+        let hdr = |w, h| Texture2d::empty_with_format(display, UncompressedFloatFormat::F16F16F16F16, MipmapsOption::NoMipmap, w, h).unwrap();
+
+        RenderTargets{
+            dimensions,
+            color: hdr(w, h),
+            // float, reversed z needs it to be more precise than fixed point
+            depth: DepthTexture2d::empty_with_format(display, DepthFormat::F32, MipmapsOption::NoMipmap, w, h).unwrap(),
+            ping: hdr(w, h),
+            pong: hdr(w, h),
+            bloom_a: hdr((w / 2).max(1), (h / 2).max(1)),
+            bloom_b: hdr((w / 2).max(1), (h / 2).max(1)),
+            oit_accum: hdr(w, h),
+            oit_weight: hdr(w, h),
+        }
+    }
+}
+
+pub struct PostProcessor {
+    pub passes: Vec<PostPass>,
+    targets: Option<Rc<RenderTargets>>,
+    quad: glium::VertexBuffer<PostVertex>,
+    copy_program: ShaderProgram,
+    bright_program: ShaderProgram,
+    blur_program: ShaderProgram,
+    bloom_program: ShaderProgram,
+    tonemap_program: ShaderProgram,
+    lut_program: ShaderProgram,
+    vignette_program: ShaderProgram,
+    gamma_program: ShaderProgram,
+    fxaa_program: ShaderProgram,
+    oit_composite_program: ShaderProgram,
+}
+
+impl PostProcessor {
+
+    pub fn new<F: Facade>(display: &F, glsl: &GlslTarget) -> PostProcessor {
+
+        let quad = glium::VertexBuffer::new(display, &[
+            PostVertex { position: [-1.0, -1.0], tex_coords: [0.0, 0.0] },
+            PostVertex { position: [ 1.0, -1.0], tex_coords: [1.0, 0.0] },
+            PostVertex { position: [-1.0,  1.0], tex_coords: [0.0, 1.0] },
+            PostVertex { position: [ 1.0,  1.0], tex_coords: [1.0, 1.0] },
+        ]).unwrap();
+
+        let passes = vec![
+            PostPass { effect: PostEffect::Bloom { threshold: 1.0, intensity: 0.6 }, enabled: false },
+            PostPass { effect: PostEffect::Tonemap { exposure: 1.0 }, enabled: true },
+            PostPass { effect: PostEffect::Vignette { strength: 0.5, radius: 0.35 }, enabled: false },
+            PostPass { effect: PostEffect::Gamma { gamma: 2.2 }, enabled: true },
+            PostPass { effect: PostEffect::Fxaa, enabled: true },
+        ];
+
+        PostProcessor{
+            passes,
+            targets: None,
+            quad,
+            copy_program: PostProcessor::load_program(display, glsl, "./res/shader/post/copy_fs.glsl"),
+            bright_program: PostProcessor::load_program(display, glsl, "./res/shader/post/bright_fs.glsl"),
+            blur_program: PostProcessor::load_program(display, glsl, "./res/shader/post/blur_fs.glsl"),
+            bloom_program: PostProcessor::load_program(display, glsl, "./res/shader/post/bloom_fs.glsl"),
+            tonemap_program: PostProcessor::load_program(display, glsl, "./res/shader/post/tonemap_fs.glsl"),
+            lut_program: PostProcessor::load_program(display, glsl, "./res/shader/post/lut_fs.glsl"),
+            vignette_program: PostProcessor::load_program(display, glsl, "./res/shader/post/vignette_fs.glsl"),
+            gamma_program: PostProcessor::load_program(display, glsl, "./res/shader/post/gamma_fs.glsl"),
+            fxaa_program: PostProcessor::load_program(display, glsl, "./res/shader/post/fxaa_fs.glsl"),
+            oit_composite_program: PostProcessor::load_program(display, glsl, "./res/shader/post/oit_composite_fs.glsl"),
+        }
+    }
+
+    // the chain does its own gamma, so glium must not convert to srgb on output
+    fn load_program<F: Facade>(display: &F, glsl: &GlslTarget, fragment_path: &str) -> ShaderProgram {
+        ShaderProgram::load_with_output(display, glsl, "./res/shader/post/post_vs.glsl", fragment_path, true)
+    }
+
+    pub fn reload_shaders<F: Facade>(&mut self, display: &F, changed: &[std::path::PathBuf]) {
+        let programs = vec![
+            &mut self.copy_program, &mut self.bright_program, &mut self.blur_program, &mut self.bloom_program,
+            &mut self.tonemap_program, &mut self.lut_program, &mut self.vignette_program, &mut self.gamma_program,
+            &mut self.fxaa_program, &mut self.oit_composite_program,
+        ];
+        for program in programs {
+            program.reload_if_changed(display, changed);
+        }
+    }
+
+    pub fn set_enabled(&mut self, name: &str, enabled: bool) {
+        for pass in self.passes.iter_mut().filter(|p| p.effect.name() == name) {
+            pass.enabled = enabled;
+        }
+    }
+
+    pub fn toggle(&mut self, index: usize) {
+        if let Some(pass) = self.passes.get_mut(index) {
+            pass.enabled = !pass.enabled;
+        }
+    }
+
+    // inserts the lut before gamma, it expects tonemapped linear colors
+    pub fn set_color_grading(&mut self, lut: Texture2d) {
+        self.passes.retain(|p| p.effect.name() != "color_grading");
+        let index = self.passes.iter().position(|p| p.effect.name() == "gamma").unwrap_or(self.passes.len());
+        self.passes.insert(index, PostPass { effect: PostEffect::ColorGrading { lut }, enabled: true });
+    }
+
+    pub fn targets<F: Facade>(&mut self, display: &F, dimensions: (u32, u32)) -> Rc<RenderTargets> {
+        let recreate = match self.targets {
+            Some(ref t) => t.dimensions != dimensions,
+            None => true,
+        };
+        if recreate {
+            self.targets = Some(Rc::new(RenderTargets::new(display, dimensions)));
+        }
+        self.targets.as_ref().unwrap().clone()
+    }
+
+    pub fn process<F: Facade, S: Surface>(&self, display: &F, targets: &RenderTargets, out: &mut S) {
+        let enabled: Vec<&PostPass> = self.passes.iter().filter(|p| p.enabled).collect();
+
+        if enabled.is_empty() {
+            self.fullscreen(out, &self.copy_program, &uniform! { source: PostProcessor::sampled(&targets.color) });
+            return;
+        }
+
+        for (k, pass) in enabled.iter().enumerate() {
+            let source = if k == 0 { &targets.color } else if k % 2 == 1 { &targets.ping } else { &targets.pong };
+
+            if k + 1 == enabled.len() {
+                self.apply(display, &pass.effect, source, targets, out);
+            } else {
+                let dest = if k % 2 == 0 { &targets.ping } else { &targets.pong };
+                let mut fb = SimpleFrameBuffer::new(display, dest).unwrap();
+                self.apply(display, &pass.effect, source, targets, &mut fb);
+            }
+        }
+    }
+
+    // blends the resolved transparent layer over the opaque scene
+    pub fn composite_oit<S: Surface>(&self, out: &mut S, targets: &RenderTargets) {
+        out.draw(
+            &self.quad,
+            glium::index::NoIndices(glium::index::PrimitiveType::TriangleStrip),
+            &self.oit_composite_program,
+            &uniform! { accum: PostProcessor::sampled(&targets.oit_accum), weight: PostProcessor::sampled(&targets.oit_weight) },
+            &glium::DrawParameters {
+                blend: glium::Blend::alpha_blending(),
+                ..Default::default()
+            },
+        ).unwrap();
+    }
+
+    fn apply<F: Facade, S: Surface>(&self, display: &F, effect: &PostEffect, source: &Texture2d, targets: &RenderTargets, out: &mut S) {
+        let src = PostProcessor::sampled(source);
+        let (w, h) = targets.dimensions;
+
+        match effect {
+            PostEffect::Bloom { threshold, intensity } => {
+                let (bw, bh) = targets.bloom_a.dimensions();
+                {
+                    let mut fb = SimpleFrameBuffer::new(display, &targets.bloom_a).unwrap();
+                    self.fullscreen(&mut fb, &self.bright_program, &uniform! { source: src, threshold: *threshold });
+                }
+                {
+                    let mut fb = SimpleFrameBuffer::new(display, &targets.bloom_b).unwrap();
+                    self.fullscreen(&mut fb, &self.blur_program, &uniform! {
+                        source: PostProcessor::sampled(&targets.bloom_a), direction: [1.0 / bw as f32, 0.0] });
+                }
+                {
+                    let mut fb = SimpleFrameBuffer::new(display, &targets.bloom_a).unwrap();
+                    self.fullscreen(&mut fb, &self.blur_program, &uniform! {
+                        source: PostProcessor::sampled(&targets.bloom_b), direction: [0.0, 1.0 / bh as f32] });
+                }
+                self.fullscreen(out, &self.bloom_program, &uniform! {
+                    source: src, bloom: PostProcessor::sampled(&targets.bloom_a), intensity: *intensity });
+            },
+            PostEffect::Tonemap { exposure } => {
+                self.fullscreen(out, &self.tonemap_program, &uniform! { source: src, exposure: *exposure });
+            },
+            PostEffect::ColorGrading { lut } => {
+                self.fullscreen(out, &self.lut_program, &uniform! { source: src, lut: PostProcessor::sampled(lut) });
+            },
+            PostEffect::Vignette { strength, radius } => {
+                self.fullscreen(out, &self.vignette_program, &uniform! { source: src, strength: *strength, radius: *radius });
+            },
+            PostEffect::Gamma { gamma } => {
+                self.fullscreen(out, &self.gamma_program, &uniform! { source: src, gamma: *gamma });
+            },
+            PostEffect::Fxaa => {
+                self.fullscreen(out, &self.fxaa_program, &uniform! { source: src, texel_size: [1.0 / w as f32, 1.0 / h as f32] });
+            },
+        }
+    }
+
+    fn sampled(texture: &Texture2d) -> Sampler<'_, Texture2d> {
+        Sampler::new(texture)
+            .wrap_function(SamplerWrapFunction::Clamp)
+            .minify_filter(MinifySamplerFilter::Linear)
+            .magnify_filter(MagnifySamplerFilter::Linear)
+    }
+
+    fn fullscreen<S: Surface, U: glium::uniforms::Uniforms>(&self, out: &mut S, program: &glium::Program, uniforms: &U) {
+        out.draw(
+            &self.quad,
+            glium::index::NoIndices(glium::index::PrimitiveType::TriangleStrip),
+            program,
+            uniforms,
+            &Default::default(),
+        ).unwrap();
+    }
+}
@@ -0,0 +1,33 @@
+// xorshift64*, small and giving the same numbers on every platform, which is what a replay needs.
+// everything random in the game should come from CCGame::rng so a seed reproduces a session
+pub struct Rng {
+    state: u64,
+}
+
+impl Rng {
+
+    pub fn new(seed: u64) -> Rng {
+        // a zero state would only ever give zeros
+        Rng{ state: if seed == 0 { 0x9E37_79B9_7F4A_7C15 } else { seed } }
+    }
+
+    pub fn next_u64(&mut self) -> u64 {
+        self.state ^= self.state >> 12;
+        self.state ^= self.state << 25;
+        self.state ^= self.state >> 27;
+        self.state.wrapping_mul(0x2545_F491_4F6C_DD1D)
+    }
+
+    // in 0..1, from the top 24 bits so every value is exact
+    pub fn next_f32(&mut self) -> f32 {
+        (self.next_u64() >> 40) as f32 / (1u64 << 24) as f32
+    }
+
+    pub fn range(&mut self, min: f32, max: f32) -> f32 {
+        min + (max - min) * self.next_f32()
+    }
+}
+
+pub fn seed_from_time() -> u64 {
+    time::precise_time_ns()
+}
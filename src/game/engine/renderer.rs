@@ -1,94 +1,244 @@
 
-use cgmath::{Matrix4,InnerSpace,Rad,Vector2,Vector3};
+use cgmath::{Matrix4,InnerSpace,Rad,Vector3,Vector4,Point3,Rotation3,EuclideanSpace,SquareMatrix};
 use cgmath::Quaternion;
+use std::rc::Rc;
+use crate::my_game_engine::my_game_logic::my_bounds::{Bounds,Frustum};
+use crate::my_game_engine::my_game_logic::my_postprocess::{PostProcessor,RenderTargets};
+use crate::my_game_engine::my_game_logic::my_capture;
+use crate::my_game_engine::my_game_logic::my_skybox::{Sky,SkyRenderer};
+use crate::my_game_engine::my_game_logic::my_debug_draw::{self,DebugDraw};
+use crate::my_game_engine::my_game_logic::my_overlay::{Overlay,StatsHud};
+use crate::my_game_engine::my_game_logic::my_picking::Ray;
+use crate::my_game_engine::my_game_logic::my_shaders::{ShaderProgram,ShaderWatcher,ShaderCache,ShaderPermutation,GlslTarget};
+
+// joints per skeleton, the size of every pose and palette. injected into the shaders as MAX_JOINTS
+pub const MAX_JOINTS: usize = 64;
+
+// pitch stops just short of straight up or down, the view would flip there
+pub const MAX_PITCH: f32 = std::f32::consts::PI * 0.5 - 0.01;
+
+// vertical, in radians
+pub const DEFAULT_FOV: f32 = std::f32::consts::PI / 3.0;
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Projection {
+    // vertical fov in radians, far is ignored when infinite
+    Perspective { fov: f32, near: f32, far: f32, infinite: bool },
+    // size is the visible height in world units
+    Orthographic { size: f32, near: f32, far: f32 },
+}
+
+impl Projection {
+    pub fn default_perspective() -> Projection {
+        Projection::Perspective{ fov: DEFAULT_FOV, near: 0.1, far: 1024.0, infinite: false }
+    }
+
+    pub fn matrix(&self, aspect: f32) -> Matrix4<f32> {
+        match *self {
+            Projection::Perspective { fov, near, infinite: true, .. } => {
+                // the limit of the gl perspective matrix for far -> infinity
+                let f = 1.0 / (fov * 0.5).tan();
+                Matrix4::new(
+                    f / aspect, 0.0, 0.0, 0.0,
+                    0.0, f, 0.0, 0.0,
+                    0.0, 0.0, -1.0, -1.0,
+                    0.0, 0.0, -2.0 * near, 0.0)
+            },
+            Projection::Perspective { fov, near, far, .. } => cgmath::perspective(Rad(fov), aspect, near, far),
+            Projection::Orthographic { size, near, far } => {
+                let h = size * 0.5;
+                cgmath::ortho(-h * aspect, h * aspect, -h, h, near, far)
+            },
+        }
+    }
+
+    // the same view volume with near at depth 1 and far at 0, for a 0..1 clip range. the float
+    // depth is then most precise where the perspective divide spreads the scene the least
+    pub fn reversed_z_matrix(&self, aspect: f32) -> Matrix4<f32> {
+        let mut m = self.matrix(aspect);
+        let (a, b) = match *self {
+            Projection::Perspective { near, infinite: true, .. } => (0.0, near),
+            Projection::Perspective { near, far, .. } => (near / (far - near), near * far / (far - near)),
+            Projection::Orthographic { near, far, .. } => (1.0 / (far - near), far / (far - near)),
+        };
+        // the z row, x and y stay as they are
+        m.z.z = a;
+        m.w.z = b;
+        m
+    }
 
+    // perspective <-> orthographic. the orthographic view is as high as the perspective one at
+    // distance, back to perspective gets the default fov
+    pub fn toggled(&self, distance: f32) -> Projection {
+        match *self {
+            Projection::Perspective { fov, near, far, .. } => Projection::Orthographic{ size: 2.0 * distance * (fov * 0.5).tan(), near, far },
+            Projection::Orthographic { near, far, .. } => Projection::Perspective{ fov: DEFAULT_FOV, near, far, infinite: false },
+        }
+    }
+}
+
+// yaw turns around the world y axis, pitch around the camera x axis, roll around the view direction.
+// yaw 0 looks down -z like an opengl camera, orientation is rebuilt from the three angles on every change
 pub struct Cam {
     pub pos: cgmath::Point3<f32>,
-    pub look_dir: cgmath::Vector3<f32>,
-    pub ha: f32,
-    pub va: f32,
-    pub perspective: Matrix4<f32>,
-    pub speed: f32
+    pub yaw: f32,
+    pub pitch: f32,
+    pub roll: f32,
+    pub orientation: Quaternion<f32>,
+    pub projection: Projection,
+    // width / height of the viewport in physical pixels
+    pub aspect: f32,
+    // near maps to depth 1 and far to 0, the scene is then cleared to 0 and drawn with IfMore
+    pub reversed_z: bool,
+    // projection matrix, rebuilt from the fields above by update_projection
+    pub perspective: Matrix4<f32>
 }
 
 impl Cam{
-    pub fn forward(&mut self, dt:&f32){
-        self.pos += self.look_dir * self.speed * *dt;
-    }
-    pub fn backward(&mut self, dt:&f32){
-        self.pos -= self.look_dir * self.speed * *dt;
+    pub fn new(pos: Point3<f32>, look_dir: Vector3<f32>, projection: Projection) -> Cam {
+        let mut cam = Cam{
+            pos,
+            yaw: 0.0,
+            pitch: 0.0,
+            roll: 0.0,
+            orientation: Quaternion::from_sv(1.0, Vector3::new(0.0, 0.0, 0.0)),
+            projection,
+            aspect: 4.0 / 3.0,
+            reversed_z: false,
+            perspective: Matrix4::from_scale(1.0)
+        };
+        cam.look_dir(look_dir);
+        cam.update_projection();
+        cam
     }
 
-    pub fn move_angle(&mut self, dt:&f32, a:&f32){
-        let ml:Matrix4<f32> = Matrix4::from_angle_y(Rad{ 0: (*a)});
-        let mut ld = self.look_dir.extend(1.0);
-        ld = ml * ld;
-        if *a>std::f32::consts::PI*0.5 && *a<std::f32::consts::PI*1.5 {
-            ld.y*=-1.0;
+    pub fn set_projection(&mut self, projection: Projection){
+        self.projection = projection;
+        self.update_projection();
+    }
+    // physical pixels, a minimized window reports 0 and keeps the last aspect
+    pub fn set_viewport(&mut self, width: f64, height: f64){
+        if width > 0.0 && height > 0.0 {
+            self.aspect = (width / height) as f32;
+            self.update_projection();
         }
-        self.pos+=ld.truncate().normalize()* self.speed **dt;
     }
-    pub fn left(&mut self, dt:&f32){
-        let ml:Matrix4<f32> = Matrix4::from_angle_y(Rad{ 0: (std::f32::consts::PI*1.5)});
-        let mut ld = self.look_dir.extend(1.0);
-        ld = ml * ld;
-        ld.y = 0.0;
-        self.pos+=ld.truncate().normalize()* self.speed **dt;
+    pub fn set_reversed_z(&mut self, reversed_z: bool){
+        self.reversed_z = reversed_z;
+        self.update_projection();
     }
-    pub fn right(&mut self, dt:&f32){
-        let ml:Matrix4<f32> = Matrix4::from_angle_y(Rad{ 0: (std::f32::consts::PI*0.5)});
-        let mut ld = self.look_dir.extend(1.0);
-        ld = ml * ld;
-        ld.y = 0.0;
-        self.pos+=ld.truncate().normalize()* self.speed **dt;
+    pub fn update_projection(&mut self){
+        self.perspective = if self.reversed_z {
+            self.projection.reversed_z_matrix(self.aspect)
+        } else {
+            self.projection.matrix(self.aspect)
+        };
     }
-    pub fn up(&mut self, dt:&f32){
-        self.pos += cgmath::Vector3{x:0.0,y:1.0,z:0.0} * self.speed * *dt;
+
+    // what the depth buffer is cleared to and tested with for the current depth direction
+    pub fn clear_depth(&self) -> f32 {
+        if self.reversed_z { 0.0 } else { 1.0 }
     }
-    pub fn down(&mut self, dt:&f32){
-        self.pos -= cgmath::Vector3{x:0.0,y:1.0,z:0.0} * self.speed * *dt;
+    pub fn depth_test(&self, or_equal: bool) -> glium::draw_parameters::DepthTest {
+        use glium::draw_parameters::DepthTest;
+        match (self.reversed_z, or_equal) {
+            (false, false) => DepthTest::IfLess,
+            (false, true) => DepthTest::IfLessOrEqual,
+            (true, false) => DepthTest::IfMore,
+            (true, true) => DepthTest::IfMoreOrEqual,
+        }
+    }
+    // with the -1..1 depth of gl whichever way the depth buffer goes, for culling, picking and
+    // the frustum lines
+    pub fn view_projection(&self) -> Matrix4<f32> {
+        self.projection.matrix(self.aspect) * self.view()
     }
-    pub fn update_angle(&mut self){
-
-        let xz = Vector2{x:self.look_dir.x,y:self.look_dir.z}.normalize();
-        let yz = Vector2{x:self.look_dir.y,y:self.look_dir.z}.normalize();
 
-        self.ha = yz.y.cos();
-        self.va = xz.y.cos();
+    // unit vectors of the camera in world space
+    pub fn forward_v(&self) -> Vector3<f32> {
+        self.orientation * Vector3::new(0.0, 0.0, -1.0)
+    }
+    pub fn right_v(&self) -> Vector3<f32> {
+        self.orientation * Vector3::new(1.0, 0.0, 0.0)
+    }
+    pub fn up_v(&self) -> Vector3<f32> {
+        self.orientation * Vector3::new(0.0, 1.0, 0.0)
+    }
 
+    // the world direction of a movement in camera terms, x to the right, y up and z forward.
+    // strafing stays level whatever the pitch and roll, up is the world up. as long as the input,
+    // forward and up overlap when looking up or down, so their sum is normalized first
+    pub fn move_dir(&self, input: Vector3<f32>) -> Vector3<f32> {
+        let dir = self.level_right() * input.x + Vector3::unit_y() * input.y + self.forward_v() * input.z;
+        if dir.magnitude2() <= 0.0 {
+            return dir;
+        }
+        dir.normalize() * input.magnitude()
     }
-    pub fn rotate(&mut self,ha:f32,va:f32){
 
-        if self.va+va<std::f32::consts::PI/2.0{
-            self.va = std::f32::consts::PI/2.0;
-        }else  if self.va+va>(3.0*std::f32::consts::PI)/2.0 {
-            self.va = (3.0*std::f32::consts::PI)/2.0 - 0.01;
-        }else {self.va+=va;}
+    fn level_right(&self) -> Vector3<f32> {
+        Vector3::new(self.yaw.cos(), 0.0, -self.yaw.sin())
+    }
 
-        self.ha+=ha;
-        if self.ha >= std::f32::consts::PI*2.0 {
-            self.ha-=std::f32::consts::PI*2.0
-        }else if self.ha <= std::f32::consts::PI*2.0 {
-            self.ha+=std::f32::consts::PI*2.0
+    // yaw wraps around, pitch is clamped to MAX_PITCH
+    pub fn rotate(&mut self, yaw:f32, pitch:f32){
+        let two_pi = std::f32::consts::PI * 2.0;
+        self.yaw = (self.yaw + yaw) % two_pi;
+        if self.yaw < 0.0 {
+            self.yaw += two_pi;
         }
-
-        self.look_dir = Vector3{
-            x: (self.va.cos()*self.ha.sin()),
-            y: (self.va.sin()),
-            z: (self.va.cos()*self.ha.cos())
+        self.pitch = (self.pitch + pitch).clamp(-MAX_PITCH, MAX_PITCH);
+        self.update_orientation();
+    }
+    pub fn set_roll(&mut self, roll: f32){
+        self.roll = roll;
+        self.update_orientation();
+    }
+    // keeps the roll
+    pub fn look_dir(&mut self, dir: Vector3<f32>){
+        if dir.magnitude2() <= 0.0 {
+            return;
         }
+        let dir = dir.normalize();
+        self.yaw = 0.0;
+        self.pitch = 0.0;
+        self.rotate((-dir.x).atan2(-dir.z), dir.y.asin());
+    }
+    fn update_orientation(&mut self){
+        self.orientation = Quaternion::from_angle_y(Rad(self.yaw))
+            * Quaternion::from_angle_x(Rad(self.pitch))
+            * Quaternion::from_angle_z(Rad(self.roll));
     }
-    pub fn look_at(&mut self,rst: &ModelRst){
 
-        let t = rst.translation.w;
-        let v = self.look_dir;
-        self.look_dir = Vector3{
-            x: (t.x - v.x),
-            y: (t.y - v.y),
-            z: (t.z - v.z)
+    pub fn view(&self) -> Matrix4<f32> {
+        Matrix4::from(self.orientation.conjugate()) * Matrix4::from_translation(-self.pos.to_vec())
+    }
+    pub fn frustum(&self) -> Frustum {
+        Frustum::from_matrix(self.view_projection())
+    }
+    // x, y in pixels from the top left corner of a width x height viewport. the ray starts on the
+    // near plane, so it also works for orthographic projections
+    pub fn screen_ray(&self, x: f32, y: f32, width: f32, height: f32) -> Option<Ray> {
+        let inverse = self.view_projection().invert()?;
+        let ndc_x = x / width * 2.0 - 1.0;
+        let ndc_y = 1.0 - y / height * 2.0;
+        let unproject = |z: f32| {
+            let p = inverse * Vector4::new(ndc_x, ndc_y, z, 1.0);
+            p.truncate() / p.w
         };
-
-        //self.update_angle();
+        // the middle of the depth range instead of the far plane, which can be at infinity
+        let origin = unproject(-1.0);
+        let dir = unproject(0.0) - origin;
+        if dir.magnitude2() <= 0.0 {
+            return None;
+        }
+        Some(Ray{ origin, dir: dir.normalize() })
+    }
+    pub fn look_at_point(&mut self, target: Point3<f32>){
+        self.look_dir(target - self.pos);
+    }
+    pub fn look_at(&mut self,rst: &ModelRst){
+        self.look_at_point(rst.position());
     }
 }
 
@@ -98,7 +248,9 @@ pub struct MyVertex {
     pub normal: [f32; 3],
     pub texture: [f32; 2],
 }
-glium::implement_vertex!(MyVertex, position, normal, texture);
+// glium 0.23's macros expand to mem::uninitialized and try!, the allows keep to the expansions
+#[allow(deprecated)]
+const _: () = { glium::implement_vertex!(MyVertex, position, normal, texture); };
 
 #[derive(Copy, Clone)]
 pub struct MyArmatureSkinVertex {
@@ -109,7 +261,8 @@ pub struct MyArmatureSkinVertex {
     pub joint_mi: [u8; 4],
     pub joint_c: u8
 }
-implement_vertex!(MyArmatureSkinVertex, position, normal, texture, weights, joint_mi, joint_c);
+#[allow(deprecated)]
+const _: () = { implement_vertex!(MyArmatureSkinVertex, position, normal, texture, weights, joint_mi, joint_c); };
 
 pub struct VertexWeights {
     pub joint_i: [u8;4],
@@ -117,6 +270,7 @@ pub struct VertexWeights {
     pub joint_c: usize
 }
 
+#[derive(Clone)]
 pub struct MyJoint {
     pub pose_m: Vec<Matrix4<f32>>,
     pub translations: Vec<cgmath::Vector4<f32>>,
@@ -128,6 +282,34 @@ pub struct MyJoint {
     pub parent_i: i8
 }
 
+impl MyJoint {
+    // the skinning matrix a of the way from key frame key_frame to the next one
+    pub fn skin_matrix(&self, key_frame: usize, a: f32) -> Matrix4<f32> {
+        let q1 = self.rotations[key_frame];
+        let q2 = self.rotations[key_frame+1];
+        let q3 = q1.slerp(q2,a);
+
+        let t1 = self.translations[key_frame].truncate();
+        let t2 = self.translations[key_frame+1].truncate();
+        let mut q5 = Quaternion::from_arc(t1,t2,None);
+        q5.s*=a;
+        let mut t3 = q5*t1;
+
+        let len1 = self.lengths[key_frame];
+        let len2 = self.lengths[key_frame+1];
+        let len3 = len2*a + len1*(1.0-a);
+
+
+        t3 = t3.normalize();
+
+        t3*=len3;
+
+        let mut mat:Matrix4<f32> = q3.into();
+        mat.w = t3.extend(1.0);
+        mat*self.inv_bind_pos
+    }
+}
+
 
 pub struct ModelRst {
     pub rotation:Matrix4<f32>,
@@ -135,27 +317,122 @@ pub struct ModelRst {
     pub translation:Matrix4<f32>,
 }
 
+impl ModelRst {
+    pub fn position(&self) -> Point3<f32> {
+        Point3::from_vec(self.translation.w.truncate())
+    }
+
+    pub fn matrix(&self) -> Matrix4<f32> {
+        self.translation * self.rotation * self.scale
+    }
+}
+
 pub struct StaticMesh{
-    pub vertices: glium::VertexBuffer<MyVertex>,
-    pub indices: glium::IndexBuffer<u16>,
+    pub vertices: Rc<glium::VertexBuffer<MyVertex>>,
+    pub indices: Rc<glium::IndexBuffer<u16>>,
+    pub bounds: Bounds,
+    // kept on the cpu for picking and the debug normals
+    pub cpu_vertices: Rc<Vec<MyVertex>>,
+    pub cpu_indices: Rc<Vec<u16>>,
+}
+
+impl StaticMesh {
+    pub fn instance(&self) -> StaticMesh {
+        StaticMesh{
+            vertices: self.vertices.clone(),
+            indices: self.indices.clone(),
+            bounds: self.bounds,
+            cpu_vertices: self.cpu_vertices.clone(),
+            cpu_indices: self.cpu_indices.clone(),
+        }
+    }
 }
 
 pub struct AnimatedMesh{
-    pub vertices: glium::VertexBuffer<MyArmatureSkinVertex>,
-    pub indices: glium::IndexBuffer<u16>,
+    pub vertices: Rc<glium::VertexBuffer<MyArmatureSkinVertex>>,
+    pub indices: Rc<glium::IndexBuffer<u16>>,
+    // kept on the cpu for picking, skinned there with current_pose
+    pub cpu_vertices: Rc<Vec<MyArmatureSkinVertex>>,
+    pub cpu_indices: Rc<Vec<u16>>,
     pub skeleton: Vec<MyJoint>,
-    pub current_pose: [[[f32;4];4];64],
+    // conservative, covers the whole clip
+    pub bounds: Bounds,
+    pub current_pose: [[[f32;4];4];MAX_JOINTS],
     pub current_time_sec: f32,
-    pub running: bool
+    pub running: bool,
+    pub joint_buffer: glium::uniforms::UniformBuffer<JointTransforms>,
+    pub pose_dirty: bool,
+    // changes with every new pose and is never shared between meshes, see next_pose_id
+    pub pose_id: usize
+}
+
+// a new id for every pose of every mesh, an instanced palette slot keeps the ids it holds
+// and only writes the instances whose pose changed since
+pub fn next_pose_id() -> usize {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    static NEXT: AtomicUsize = AtomicUsize::new(0);
+    NEXT.fetch_add(1, Ordering::Relaxed)
+}
+
+// the joint palettes of one instanced batch, MAX_JOINTS per instance
+struct JointPalette {
+    buffer: glium::uniforms::UniformBuffer<JointTransforms>,
+    capacity: usize,
+    // the pose_id written for each instance
+    poses: Vec<usize>,
 }
 
 pub struct JointTransforms{
     transform_m: [[[f32;4];4]]
 }
-implement_buffer_content!(JointTransforms);
-implement_uniform_block!(JointTransforms,transform_m);
+#[allow(deprecated)]
+const _: () = {
+    implement_buffer_content!(JointTransforms);
+    implement_uniform_block!(JointTransforms,transform_m);
+};
+
+#[derive(Copy, Clone)]
+pub struct InstanceAttr {
+    pub instance_model: [[f32; 4]; 4],
+}
+#[allow(deprecated)]
+const _: () = { implement_vertex!(InstanceAttr, instance_model); };
 
 impl AnimatedMesh {
+    pub fn new_joint_buffer<F: glium::backend::Facade>(display:&F) -> glium::uniforms::UniformBuffer<JointTransforms> {
+        glium::uniforms::UniformBuffer::empty_unsized(display, 4*4*4*MAX_JOINTS).unwrap()
+    }
+
+    pub fn instance<F: glium::backend::Facade>(&self, display:&F) -> AnimatedMesh {
+        AnimatedMesh{
+            vertices: self.vertices.clone(),
+            indices: self.indices.clone(),
+            cpu_vertices: self.cpu_vertices.clone(),
+            cpu_indices: self.cpu_indices.clone(),
+            skeleton: self.skeleton.clone(),
+            bounds: self.bounds,
+            current_pose: self.current_pose,
+            current_time_sec: self.current_time_sec,
+            running: self.running,
+            joint_buffer: AnimatedMesh::new_joint_buffer(display),
+            pose_dirty: true,
+            pose_id: next_pose_id()
+        }
+    }
+
+    // writes current_pose into the persistent joint buffer, only if it changed since the last upload
+    pub fn update_joint_buffer(&mut self) {
+        if !self.pose_dirty {
+            return;
+        }
+        {
+            let mut mapping = self.joint_buffer.map();
+            for (val, pose) in mapping.transform_m.iter_mut().zip(self.current_pose.iter()) {
+                *val = *pose;
+            }
+        }
+        self.pose_dirty = false;
+    }
     pub fn advance_time(&mut self,time_in_sec:&f32){
         let max_time = self.skeleton[0].time_stamps.last().unwrap();
         let current_time = &self.current_time_sec;
@@ -164,17 +441,14 @@ impl AnimatedMesh {
         }else { self.current_time_sec+=time_in_sec }
     }
     pub fn calculate_current_pose(&mut self) {
-        use cgmath::SquareMatrix;
         
         let c: Matrix4<f32> = Matrix4::identity();
         let c:[[f32;4];4] = c.into();
-        let mut current_transforms= [c;64];
+        let mut current_transforms= [c;MAX_JOINTS];
 
         let skeleton = &self.skeleton;
 
-        let mut y:usize = 0;
-
-        for joint in skeleton {
+        for (y, joint) in skeleton.iter().enumerate() {
             let mut prev_kf =0;
             let mut a= 0.0;
             let max_time = joint.time_stamps.last().unwrap();
@@ -191,100 +465,384 @@ impl AnimatedMesh {
                 }
             }
 
-            let q1 = joint.rotations[prev_kf];
-            let q2 = joint.rotations[prev_kf+1];
-            let q3 = q1.slerp(q2,a);
+            let mut mat = joint.skin_matrix(prev_kf, a);
+            mat.transpose_self();
+            current_transforms[y] = mat.into();
+        }
+        if self.current_pose.iter().zip(current_transforms.iter()).any(|(a, b)| a != b) {
+            self.current_pose = current_transforms;
+            self.pose_dirty = true;
+            self.pose_id = next_pose_id();
+        }
+    }
+}
 
-            let t1 = joint.translations[prev_kf].truncate();
-            let t2 = joint.translations[prev_kf+1].truncate();
-            let mut q5 = Quaternion::from_arc(t1,t2,None);
-            q5.s=q5.s*a;
-            let mut t3 = q5*t1;
+#[derive(Copy, Clone)]
+pub enum Material<'a> {
+    Flat,
+    Textured(&'a glium::texture::SrgbTexture2d),
+    // color and a tangent space normal map, lit by a fixed light from above
+    NormalMapped(&'a glium::texture::SrgbTexture2d, &'a glium::texture::Texture2d),
+}
 
-            let len1 = joint.lengths[prev_kf];
-            let len2 = joint.lengths[prev_kf+1];
-            let len3 = len2*a + len1*(1.0-a);
+impl<'a> Material<'a> {
+    // textures are owned by the game, so their address is a stable id for the frame
+    fn sort_id(&self) -> (usize, usize) {
+        match self {
+            Material::Flat => (0, 0),
+            Material::Textured(t) => (*t as *const glium::texture::SrgbTexture2d as usize, 0),
+            Material::NormalMapped(t, n) => (*t as *const glium::texture::SrgbTexture2d as usize, *n as *const glium::texture::Texture2d as usize),
+        }
+    }
+}
 
+#[derive(Copy, Clone)]
+pub enum Drawable<'a> {
+    Static(&'a StaticMesh),
+    Animated(&'a AnimatedMesh),
+}
 
-            t3 = t3.normalize();
+impl<'a> Drawable<'a> {
+    // instances share their vertex buffer, so it identifies the geometry
+    fn batch_id(&self) -> usize {
+        match self {
+            Drawable::Static(mesh) => &*mesh.vertices as *const glium::VertexBuffer<MyVertex> as usize,
+            Drawable::Animated(mesh) => &*mesh.vertices as *const glium::VertexBuffer<MyArmatureSkinVertex> as usize,
+        }
+    }
 
-            t3*=len3;
+    fn triangles(&self) -> usize {
+        match *self {
+            Drawable::Static(mesh) => mesh.indices.len() / 3,
+            Drawable::Animated(mesh) => mesh.indices.len() / 3,
+        }
+    }
 
-            let mut mat:Matrix4<f32> = q3.into();
-            mat.w = t3.extend(1.0);
-            mat =   mat*joint.inv_bind_pos;
-            mat.transpose_self();
-            current_transforms[y] = mat.into();
-            y=y+1;
+    pub fn bounds(&self) -> &'a Bounds {
+        match *self {
+            Drawable::Static(mesh) => &mesh.bounds,
+            Drawable::Animated(mesh) => &mesh.bounds,
         }
-        self.current_pose = current_transforms ;
     }
 }
 
-pub struct Renderer {
-    static_render_program: glium::Program,
-    animated_render_program: glium::Program,
-    static_textured_render_program: glium::Program,
+// the defines a mesh program is compiled with, see mesh_vs.glsl and mesh_fs.glsl
+#[derive(Copy, Clone, PartialEq, Eq, Hash, Debug)]
+pub struct MeshVariant {
+    pub skinned: bool,
+    pub instanced: bool,
+    pub textured: bool,
+    pub normal_mapped: bool,
+    pub oit: bool,
 }
 
-impl Renderer {
+impl MeshVariant {
+    fn of(item: &DrawItem, instanced: bool, oit: bool) -> MeshVariant {
+        MeshVariant{
+            skinned: matches!(item.mesh, Drawable::Animated(_)),
+            instanced,
+            textured: !matches!(item.material, Material::Flat),
+            normal_mapped: matches!(item.material, Material::NormalMapped(..)),
+            oit,
+        }
+    }
 
-    pub fn new(display: &mut glium::Display) -> Renderer{
+    fn direct(skinned: bool, textured: bool) -> MeshVariant {
+        MeshVariant{ skinned, instanced: false, textured, normal_mapped: false, oit: false }
+    }
+}
 
+impl ShaderPermutation for MeshVariant {
+    fn defines(&self) -> Vec<(String, String)> {
+        let mut defines = vec![("MAX_JOINTS".to_string(), MAX_JOINTS.to_string())];
+        let flags = [(self.skinned, "SKINNED"), (self.instanced, "INSTANCED"), (self.textured, "TEXTURED"), (self.normal_mapped, "HAS_NORMAL_MAP"), (self.oit, "OIT")];
+        for &(enabled, name) in flags.iter() {
+            if enabled {
+                defines.push((name.to_string(), "1".to_string()));
+            }
+        }
+        defines
+    }
 
-        let vertex_shader = std::fs::read_to_string("./res/shader/vs.glsl").unwrap();
-        let fragment_shader = std::fs::read_to_string("./res/shader/fs.glsl").unwrap();
+    // skinning, instancing and oit change the inputs and outputs, the textures are only left unused
+    fn base(&self) -> MeshVariant {
+        MeshVariant{ textured: false, normal_mapped: false, ..*self }
+    }
+}
 
-        let vertex_shader_src: &str = vertex_shader.as_ref();
-        let fragment_shader_src: &str = fragment_shader.as_ref();
+#[derive(Copy, Clone, PartialEq, PartialOrd)]
+enum ProgramKind {
+    Static,
+    StaticTextured,
+    StaticNormalMapped,
+    Animated,
+    AnimatedTextured,
+    AnimatedNormalMapped,
+}
 
-        let program =
-            glium::Program::from_source(display, vertex_shader_src, fragment_shader_src, None)
-                .unwrap();
+#[derive(Copy, Clone, PartialEq)]
+pub enum AlphaMode {
+    Opaque,
+    // discards fragments below the cutoff, drawn with the opaque items
+    Cutout(f32),
+    // blended with the given opacity, sorted back to front or resolved with oit
+    Blend(f32),
+}
 
-        let vertex_shadert = std::fs::read_to_string("./res/shader/dynvs.glsl").unwrap();
-        let fragment_shadert = std::fs::read_to_string("./res/shader/fts.glsl").unwrap();
+impl AlphaMode {
+    pub fn is_blended(&self) -> bool {
+        matches!(self, AlphaMode::Blend(_))
+    }
 
-        let vertex_shadert_src: &str = vertex_shadert.as_ref();
-        let fragment_shadert_src: &str = fragment_shadert.as_ref();
+    // opacity, alpha_cutoff
+    fn uniforms(&self) -> (f32, f32) {
+        match *self {
+            AlphaMode::Opaque => (1.0, 0.0),
+            AlphaMode::Cutout(cutoff) => (1.0, cutoff),
+            AlphaMode::Blend(opacity) => (opacity, 0.0),
+        }
+    }
+}
 
-        let dprogram =
-            glium::Program::from_source(display, vertex_shadert_src, fragment_shadert_src, None)
-                .unwrap();
+pub struct DrawItem<'a> {
+    pub mesh: Drawable<'a>,
+    pub material: Material<'a>,
+    pub transform: Matrix4<f32>,
+    pub layer: u8,
+    pub alpha: AlphaMode,
+}
 
-        let vertex_shadert = std::fs::read_to_string("./res/shader/vts.glsl").unwrap();
-        let fragment_shadert = std::fs::read_to_string("./res/shader/fts.glsl").unwrap();
+impl<'a> DrawItem<'a> {
+    fn program_kind(&self) -> ProgramKind {
+        match (self.mesh, self.material) {
+            (Drawable::Static(_), Material::Flat) => ProgramKind::Static,
+            (Drawable::Static(_), Material::Textured(_)) => ProgramKind::StaticTextured,
+            (Drawable::Static(_), Material::NormalMapped(..)) => ProgramKind::StaticNormalMapped,
+            (Drawable::Animated(_), Material::Flat) => ProgramKind::Animated,
+            (Drawable::Animated(_), Material::Textured(_)) => ProgramKind::AnimatedTextured,
+            (Drawable::Animated(_), Material::NormalMapped(..)) => ProgramKind::AnimatedNormalMapped,
+        }
+    }
 
-        let vertex_shadert_src: &str = vertex_shadert.as_ref();
-        let fragment_shadert_src: &str = fragment_shadert.as_ref();
+    fn batches_with(&self, other: &DrawItem) -> bool {
+        !self.alpha.is_blended() && self.alpha == other.alpha
+            && self.layer == other.layer
+            && self.program_kind() == other.program_kind()
+            && self.material.sort_id() == other.material.sort_id()
+            && self.mesh.batch_id() == other.mesh.batch_id()
+    }
+}
 
-        let tprogram =
-            glium::Program::from_source(display, vertex_shadert_src, fragment_shadert_src, None)
-                .unwrap();
+pub struct RenderQueue<'a> {
+    items: Vec<DrawItem<'a>>,
+    sky: Option<&'a Sky>,
+}
+
+impl<'a> Default for RenderQueue<'a> {
+    fn default() -> RenderQueue<'a> {
+        RenderQueue::new()
+    }
+}
+
+impl<'a> RenderQueue<'a> {
+    pub fn new() -> RenderQueue<'a> {
+        RenderQueue{
+            items: Vec::new(),
+            sky: None,
+        }
+    }
+
+    pub fn submit(&mut self, item: DrawItem<'a>) {
+        self.items.push(item);
+    }
+
+    pub fn submit_static(&mut self, model: &'a (ModelRst, StaticMesh), material: Material<'a>) {
+        self.submit_static_with_alpha(model, material, AlphaMode::Opaque);
+    }
+
+    pub fn submit_animated(&mut self, model: &'a (ModelRst, AnimatedMesh), material: Material<'a>) {
+        self.submit_animated_with_alpha(model, material, AlphaMode::Opaque);
+    }
+
+    // blended items are drawn after the sky, with oit if the renderer has it turned on
+    pub fn submit_static_with_alpha(&mut self, model: &'a (ModelRst, StaticMesh), material: Material<'a>, alpha: AlphaMode) {
+        self.submit(DrawItem{
+            mesh: Drawable::Static(&model.1),
+            material,
+            transform: model.0.matrix(),
+            layer: 0,
+            alpha,
+        });
+    }
+
+    pub fn submit_animated_with_alpha(&mut self, model: &'a (ModelRst, AnimatedMesh), material: Material<'a>, alpha: AlphaMode) {
+        self.submit(DrawItem{
+            mesh: Drawable::Animated(&model.1),
+            material,
+            transform: model.0.matrix(),
+            layer: 0,
+            alpha,
+        });
+    }
+
+    // drawn between the opaque and the transparent items
+    pub fn set_sky(&mut self, sky: &'a Sky) {
+        self.sky = Some(sky);
+    }
+
+    pub fn len(&self) -> usize {
+        self.items.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.items.is_empty()
+    }
+
+    pub fn clear(&mut self) {
+        self.items.clear();
+        self.sky = None;
+    }
+
+    // opaque: layer, program, material, mesh, front to back; transparent after that, back to front
+    fn sort(&mut self, cam_pos: cgmath::Point3<f32>) {
+        use cgmath::MetricSpace;
+        use std::cmp::Ordering;
+
+        let depth = |item: &DrawItem| Point3::from_vec(item.transform.w.truncate()).distance2(cam_pos);
+
+        self.items.sort_by(|a, b| {
+            a.alpha.is_blended().cmp(&b.alpha.is_blended())
+                .then(a.layer.cmp(&b.layer))
+                .then_with(|| {
+                    if a.alpha.is_blended() {
+                        depth(b).partial_cmp(&depth(a)).unwrap_or(Ordering::Equal)
+                    } else {
+                        a.program_kind().partial_cmp(&b.program_kind()).unwrap_or(Ordering::Equal)
+                            .then(a.material.sort_id().cmp(&b.material.sort_id()))
+                            .then(a.mesh.batch_id().cmp(&b.mesh.batch_id()))
+                            .then(depth(a).partial_cmp(&depth(b)).unwrap_or(Ordering::Equal))
+                    }
+                })
+        });
+    }
+}
+
+#[derive(Copy, Clone, Default, Debug)]
+pub struct RenderStats {
+    pub submitted: usize,
+    pub culled: usize,
+    pub draw_calls: usize,
+    pub triangles: usize,
+}
+
+pub struct Renderer {
+    pub culling: bool,
+    pub stats: RenderStats,
+    pub post: PostProcessor,
+    // weighted blended order independent transparency instead of sorted blending
+    pub oit: bool,
+    pub debug: DebugDraw,
+    pub overlay: Overlay,
+    pub hud: StatsHud,
+    current_targets: Option<Rc<RenderTargets>>,
+    sky: SkyRenderer,
+    // set before render_frame to read the post processed frame back into captured
+    pub capture_requested: bool,
+    pub captured: Option<image::RgbaImage>,
+    joint_palettes: Vec<JointPalette>,
+    next_joint_palette: usize,
+    mesh_shaders: ShaderCache<MeshVariant>,
+    shader_watcher: Option<ShaderWatcher>,
+    // the dialect every program is compiled as, decides if skinned meshes can be instanced
+    pub glsl: GlslTarget,
+    clip_control: Option<ClipControl>,
+}
+
+type ClipControl = extern "system" fn(u32, u32);
+const GL_LOWER_LEFT: u32 = 0x8CA1;
+const GL_NEGATIVE_ONE_TO_ONE: u32 = 0x935E;
+const GL_ZERO_TO_ONE: u32 = 0x935F;
+
+// glClipControl, core since gl 4.5
+fn load_clip_control<F: glium::backend::Facade>(display: &F, get_proc_address: &dyn Fn(&str) -> *const std::os::raw::c_void) -> Option<ClipControl> {
+    use glium::{Version,Api};
+    if *display.get_context().get_opengl_version() < Version(Api::Gl, 4, 5) {
+        return None;
+    }
+    let clip_control = get_proc_address("glClipControl");
+    if clip_control.is_null() {
+        return None;
+    }
+    Some(unsafe { std::mem::transmute::<*const std::os::raw::c_void, ClipControl>(clip_control) })
+}
+
+impl Renderer {
+
+    // get_proc_address of the window or context, for what glium can not tell about it
+    pub fn new<F: glium::backend::Facade>(display: &F, get_proc_address: &dyn Fn(&str) -> *const std::os::raw::c_void) -> Renderer{
+
+        let glsl = GlslTarget::detect(display, get_proc_address).unwrap_or_else(|e| panic!("{}", e));
+        println!("compiling shaders as {}", glsl.describe());
+        let clip_control = load_clip_control(display, get_proc_address);
+
+        // the queue compiles its variants on first use, the direct draw_* functions have no facade for that
+        let mut mesh_shaders = ShaderCache::new(&glsl, "./res/shader/mesh_vs.glsl", "./res/shader/mesh_fs.glsl");
+        for &(skinned, textured) in [(false, false), (false, true), (true, false), (true, true)].iter() {
+            mesh_shaders.prepare(display, MeshVariant::direct(skinned, textured));
+        }
 
         Renderer{
-            static_render_program: program,
-            animated_render_program: dprogram,
-            static_textured_render_program: tprogram,
+            culling: true,
+            stats: RenderStats::default(),
+            post: PostProcessor::new(display, &glsl),
+            oit: false,
+            debug: DebugDraw::new(display, &glsl),
+            overlay: Overlay::new(display, &glsl),
+            hud: StatsHud::new(),
+            current_targets: None,
+            sky: SkyRenderer::new(display, &glsl),
+            capture_requested: false,
+            captured: None,
+            joint_palettes: Vec::new(),
+            next_joint_palette: 0,
+            mesh_shaders,
+            shader_watcher: ShaderWatcher::new("./res/shader"),
+            glsl,
+            clip_control,
+        }
+
+    }
+
+    pub fn load_program<F: glium::backend::Facade>(display: &F, glsl: &GlslTarget, vertex_path: &str, fragment_path: &str) -> ShaderProgram {
+        ShaderProgram::load(display, glsl, vertex_path, fragment_path)
+    }
+
+    // rebuilds every program whose files changed on disk since the last frame
+    pub fn reload_shaders<F: glium::backend::Facade>(&mut self, display: &F) {
+        let changed = match self.shader_watcher {
+            Some(ref watcher) => watcher.changed(),
+            None => return,
+        };
+        if changed.is_empty() {
+            return;
         }
 
+        self.mesh_shaders.reload_if_changed(display, &changed);
+        self.post.reload_shaders(display, &changed);
+        self.sky.reload_shaders(display, &changed);
+        self.debug.reload_shaders(display, &changed);
+        self.overlay.reload_shaders(display, &changed);
     }
 
     pub fn draw_static_mesh(&mut self,target_frame:&mut glium::Frame, cam :&Cam, model: &(ModelRst, StaticMesh)){
 
-        use cgmath::{conv, Matrix4};
+        use cgmath::conv;
         use glium::Surface;
-        target_frame.clear_color_and_depth((0.0, 0.0, 1.0, 1.0), 1.0);
-        let up_v = cgmath::Vector3 {
-            x: 0.0,
-            y: 1.0,
-            z: 0.0,
-        };
-        let view: Matrix4<f32> = Matrix4::look_at_dir(cam.pos, cam.look_dir, up_v);
+        let view = cam.view();
 
         let params = glium::DrawParameters {
             depth: glium::Depth {
-                test: glium::draw_parameters::DepthTest::IfLess,
+                test: cam.depth_test(false),
                 write: true,
                 ..Default::default()
             },
@@ -294,16 +852,16 @@ impl Renderer {
             let trs = &model.0;
             let trs_m = trs.translation * trs.rotation * trs.scale;
 
-            let v_b = &model.1.vertices;
-            let i_b = &model.1.indices;
+            let v_b = &*model.1.vertices;
+            let i_b = &*model.1.indices;
 
             target_frame
                 .draw(
                     v_b,
                     i_b,
-                    &self.static_render_program,
+                    self.mesh_shaders.get(MeshVariant::direct(false, false)),
                     &uniform! { model: conv::array4x4(trs_m), view: conv::array4x4(view),
-                    perspective: conv::array4x4(cam.perspective) },
+                    perspective: conv::array4x4(cam.perspective), opacity: 1.0f32 },
                     &params,
                 )
                 .unwrap();
@@ -313,18 +871,13 @@ impl Renderer {
 
     pub fn draw_textured_static_mesh(&mut self,target_frame:&mut glium::Frame, cam :&Cam, model: &(ModelRst, StaticMesh), texture: &glium::texture::SrgbTexture2d){
 
-        use cgmath::{conv, Matrix4};
+        use cgmath::conv;
         use glium::Surface;
-        let up_v = cgmath::Vector3 {
-            x: 0.0,
-            y: 1.0,
-            z: 0.0,
-        };
-        let view: Matrix4<f32> = Matrix4::look_at_dir(cam.pos, cam.look_dir, up_v);
+        let view = cam.view();
 
         let params = glium::DrawParameters {
             depth: glium::Depth {
-                test: glium::draw_parameters::DepthTest::IfLess,
+                test: cam.depth_test(false),
                 write: true,
                 ..Default::default()
             },
@@ -335,35 +888,61 @@ impl Renderer {
         let trs = &model.0;
         let trs_m = trs.translation * trs.rotation * trs.scale;
 
-        let v_b = &model.1.vertices;
-        let i_b = &model.1.indices;
+        let v_b = &*model.1.vertices;
+        let i_b = &*model.1.indices;
 
         target_frame
             .draw(
                 v_b,
                 i_b,
-                &self.static_textured_render_program,
+                self.mesh_shaders.get(MeshVariant::direct(false, true)),
                 &uniform! { model: conv::array4x4(trs_m), view: conv::array4x4(view) ,myTextureSampler: texture,
-                perspective: conv::array4x4(cam.perspective) },
+                perspective: conv::array4x4(cam.perspective), opacity: 1.0f32 },
                 &params,
             )
             .unwrap();
     }
 
-    pub fn draw_textured_animated_mesh(&mut self,target_frame:&mut glium::Frame,display:&glium::Display, cam :&Cam, model: &(ModelRst, AnimatedMesh), texture: &glium::texture::SrgbTexture2d){
+    pub fn draw_animated_mesh(&mut self,target_frame:&mut glium::Frame, cam :&Cam, model: &(ModelRst, AnimatedMesh)){
 
-        use cgmath::{conv, Matrix4};
+        use cgmath::conv;
         use glium::Surface;
-        let up_v = cgmath::Vector3 {
-            x: 0.0,
-            y: 1.0,
-            z: 0.0,
+        let view = cam.view();
+
+        let params = glium::DrawParameters {
+            depth: glium::Depth {
+                test: cam.depth_test(false),
+                write: true,
+                ..Default::default()
+            },
+            ..Default::default()
         };
-        let view: Matrix4<f32> = Matrix4::look_at_dir(cam.pos, cam.look_dir, up_v);
+
+        let trs = &model.0;
+        let trs_m = trs.translation * trs.rotation * trs.scale;
+
+        let buffer = &model.1.joint_buffer;
+
+        target_frame
+            .draw(
+                &*model.1.vertices,
+                &*model.1.indices,
+                self.mesh_shaders.get(MeshVariant::direct(true, false)),
+                &uniform! { model: conv::array4x4(trs_m), view: conv::array4x4(view), perspective: conv::array4x4(cam.perspective), MyBlock: buffer, opacity: 1.0f32 },
+                &params,
+            )
+            .unwrap();
+    }
+
+    pub fn draw_textured_animated_mesh(&mut self,target_frame:&mut glium::Frame, cam :&Cam, model: &(ModelRst, AnimatedMesh), texture: &glium::texture::SrgbTexture2d){
+
+        use cgmath::conv;
+        use glium::Surface;
+        let view = cam.view();
 
         let params = glium::DrawParameters {
             depth: glium::Depth {
-                test: glium::draw_parameters::DepthTest::IfLess,
+                test: cam.depth_test(false),
                 write: true,
                 ..Default::default()
             },
@@ -374,31 +953,614 @@ impl Renderer {
         let trs = &model.0;
         let trs_m = trs.translation * trs.rotation * trs.scale;
 
-        let v_b = &model.1.vertices;
-        let i_b = &model.1.indices;
+        let v_b = &*model.1.vertices;
+        let i_b = &*model.1.indices;
 
-        let mut buffer: glium::uniforms::UniformBuffer<JointTransforms> =
-            glium::uniforms::UniformBuffer::empty_unsized(display, 4*4*4*64).unwrap();
-
-        {
-            let mut i = 0;
-            let mut mapping = buffer.map();
-            for val in mapping.transform_m.iter_mut() {
-                *val = model.1.current_pose[i];
-                i+=1;
-            }
-        }
+        let buffer = &model.1.joint_buffer;
 
 
         target_frame
             .draw(
                 v_b,
                 i_b,
-                &self.animated_render_program,
-                &uniform! { model: conv::array4x4(trs_m), view: conv::array4x4(view), perspective: conv::array4x4(cam.perspective), MyBlock: &buffer, myTextureSampler: texture,
-                perspective: conv::array4x4(cam.perspective) },
+                self.mesh_shaders.get(MeshVariant::direct(true, true)),
+                &uniform! { model: conv::array4x4(trs_m), view: conv::array4x4(view), perspective: conv::array4x4(cam.perspective), MyBlock: buffer, myTextureSampler: texture,
+                perspective: conv::array4x4(cam.perspective), opacity: 1.0f32 },
                 &params,
             )
             .unwrap();
     }
-}
\ No newline at end of file
+
+    // draws the scene into the offscreen hdr targets, then runs the post chain and the overlay into the frame
+    pub fn render_frame<F, S, D>(&mut self,display:&F,target_frame:&mut S,draw_scene: D)
+        where F: glium::backend::Facade, S: glium::Surface, D: FnOnce(&mut Renderer, &mut glium::framebuffer::SimpleFrameBuffer) {
+
+        self.reload_shaders(display);
+        let targets = self.post.targets(display, target_frame.get_dimensions());
+        self.current_targets = Some(targets.clone());
+        {
+            let mut scene = glium::framebuffer::SimpleFrameBuffer::with_depth_buffer(display, &targets.color, &targets.depth).unwrap();
+            draw_scene(self, &mut scene);
+        }
+        self.current_targets = None;
+        self.hud.frame();
+        if self.hud.visible {
+            self.hud.draw(&mut self.overlay, &self.stats);
+        }
+        if self.capture_requested {
+            use glium::texture::{Texture2d,UncompressedFloatFormat,MipmapsOption};
+            use glium::Surface;
+
+            // the finished frame goes into a texture that can be read back, then is copied to the frame
+            let (width, height) = targets.dimensions;
+            let capture = Texture2d::empty_with_format(display, UncompressedFloatFormat::U8U8U8U8, MipmapsOption::NoMipmap, width, height).unwrap();
+            {
+                let mut fb = glium::framebuffer::SimpleFrameBuffer::new(display, &capture).unwrap();
+                self.post.process(display, &targets, &mut fb);
+                self.overlay.draw(display, &mut fb);
+                fb.fill(target_frame, glium::uniforms::MagnifySamplerFilter::Nearest);
+            }
+            self.captured = Some(my_capture::texture_to_image(&capture));
+            self.capture_requested = false;
+        }else {
+            self.post.process(display, &targets, target_frame);
+            self.overlay.draw(display, target_frame);
+        }
+        self.overlay.clear();
+    }
+
+    // 0..1 depth after the divide instead of -1..1, where reversed z keeps its precision. without
+    // glClipControl a reversed scene still draws right, the depth just lands in 0.5..1
+    fn set_zero_to_one_depth<F: glium::backend::Facade>(&self, display: &F, zero_to_one: bool) {
+        if let Some(clip_control) = self.clip_control {
+            let depth = if zero_to_one { GL_ZERO_TO_ONE } else { GL_NEGATIVE_ONE_TO_ONE };
+            unsafe {
+                display.get_context().exec_in_context(|| clip_control(GL_LOWER_LEFT, depth));
+            }
+        }
+    }
+
+    pub fn draw_queue<F: glium::backend::Facade, S: glium::Surface>(&mut self,target_frame:&mut S,display:&F, cam :&Cam, queue: &mut RenderQueue){
+
+        use cgmath::conv;
+
+        self.set_zero_to_one_depth(display, cam.reversed_z);
+
+        let view = conv::array4x4(cam.view());
+        let perspective = conv::array4x4(cam.perspective);
+        let opaque_params = glium::DrawParameters {
+            depth: glium::Depth {
+                test: cam.depth_test(false),
+                write: true,
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        let transparent_params = glium::DrawParameters {
+            depth: glium::Depth {
+                test: cam.depth_test(false),
+                write: false,
+                ..Default::default()
+            },
+            blend: glium::Blend::alpha_blending(),
+            ..Default::default()
+        };
+
+        let submitted = queue.items.len();
+        if self.culling {
+            let frustum = cam.frustum();
+            queue.items.retain(|item| frustum.intersects(&item.mesh.bounds().transformed(&item.transform)));
+        }
+        self.stats = RenderStats{
+            submitted,
+            culled: submitted - queue.items.len(),
+            draw_calls: 0,
+            triangles: 0,
+        };
+
+        queue.sort(cam.pos);
+        self.next_joint_palette = 0;
+
+        let first_transparent = queue.items.iter().position(|item| item.alpha.is_blended()).unwrap_or(queue.items.len());
+        self.draw_batches(target_frame, display, view, perspective, &queue.items[..first_transparent], &opaque_params);
+        if let Some(sky) = queue.sky {
+            let triangles = self.sky.draw(target_frame, view, perspective, sky, cam.reversed_z);
+            if triangles > 0 {
+                self.stats.draw_calls += 1;
+                self.stats.triangles += triangles;
+            }
+        }
+
+        let transparent = &queue.items[first_transparent..];
+        if self.oit && self.current_targets.is_some() && !transparent.is_empty() {
+            self.draw_oit(target_frame, display, view, perspective, transparent, &transparent_params);
+        }else {
+            self.draw_batches(target_frame, display, view, perspective, transparent, &transparent_params);
+        }
+
+        for item in queue.items.iter() {
+            if self.debug.show_bounds {
+                self.debug.transformed_aabb(&item.mesh.bounds().aabb, &item.transform, my_debug_draw::YELLOW, 0.0);
+            }
+            match item.mesh {
+                Drawable::Static(mesh) if self.debug.show_normals => {
+                    self.debug.normals(mesh, &item.transform, 0.1, my_debug_draw::CYAN, 0.0);
+                },
+                Drawable::Animated(mesh) if self.debug.show_skeletons => {
+                    self.debug.skeleton(mesh, &item.transform, my_debug_draw::WHITE, 0.0);
+                },
+                _ => (),
+            }
+        }
+        self.debug.draw(display, target_frame, view, perspective, cam.depth_test(true));
+        self.set_zero_to_one_depth(display, false);
+        queue.clear();
+    }
+
+    fn draw_batches<F: glium::backend::Facade, S: glium::Surface>(&mut self,target_frame:&mut S,display:&F, view: [[f32;4];4], perspective: [[f32;4];4], items: &[DrawItem], params: &glium::DrawParameters){
+        let mut i = 0;
+        while i < items.len() {
+            let mut j = i + 1;
+            while j < items.len() && self.can_instance(&items[i]) && items[i].batches_with(&items[j]) {
+                j += 1;
+            }
+
+            let instanced = j - i > 1;
+            self.mesh_shaders.prepare(display, MeshVariant::of(&items[i], instanced, false));
+            if instanced {
+                self.draw_instanced(target_frame, display, view, perspective, &items[i..j], params);
+            } else {
+                self.draw_item(target_frame, view, perspective, &items[i], params, false);
+            }
+            self.stats.draw_calls += 1;
+            self.stats.triangles += items[i].mesh.triangles() * (j - i);
+            i = j;
+        }
+    }
+
+    // the palettes of all instances only fit a storage buffer, a uniform block holds a single one
+    fn can_instance(&self, item: &DrawItem) -> bool {
+        match item.mesh {
+            Drawable::Animated(_) => self.glsl.storage_buffers,
+            Drawable::Static(_) => true,
+        }
+    }
+
+    fn draw_item<S: glium::Surface>(&self,target_frame:&mut S, view: [[f32;4];4], perspective: [[f32;4];4], item: &DrawItem, params: &glium::DrawParameters, oit: bool){
+
+        use cgmath::conv;
+
+        let model = conv::array4x4(item.transform);
+        let (opacity, alpha_cutoff) = item.alpha.uniforms();
+        let program = self.mesh_shaders.get(MeshVariant::of(item, false, oit));
+
+        match (item.mesh, item.material) {
+            (Drawable::Static(mesh), Material::Flat) => {
+                target_frame
+                    .draw(
+                        &*mesh.vertices,
+                        &*mesh.indices,
+                        program,
+                        &uniform! { model: model, view: view, perspective: perspective, opacity: opacity, alpha_cutoff: alpha_cutoff },
+                        params,
+                    )
+                    .unwrap();
+            },
+            (Drawable::Static(mesh), Material::Textured(texture)) => {
+                target_frame
+                    .draw(
+                        &*mesh.vertices,
+                        &*mesh.indices,
+                        program,
+                        &uniform! { model: model, view: view, myTextureSampler: texture, perspective: perspective, opacity: opacity, alpha_cutoff: alpha_cutoff },
+                        params,
+                    )
+                    .unwrap();
+            },
+            (Drawable::Static(mesh), Material::NormalMapped(texture, normals)) => {
+                target_frame
+                    .draw(
+                        &*mesh.vertices,
+                        &*mesh.indices,
+                        program,
+                        &uniform! { model: model, view: view, myTextureSampler: texture, normalMapSampler: normals, perspective: perspective, opacity: opacity, alpha_cutoff: alpha_cutoff },
+                        params,
+                    )
+                    .unwrap();
+            },
+            (Drawable::Animated(mesh), Material::Textured(texture)) => {
+                let buffer = &mesh.joint_buffer;
+                target_frame
+                    .draw(
+                        &*mesh.vertices,
+                        &*mesh.indices,
+                        program,
+                        &uniform! { model: model, view: view, perspective: perspective, MyBlock: buffer, myTextureSampler: texture, opacity: opacity, alpha_cutoff: alpha_cutoff },
+                        params,
+                    )
+                    .unwrap();
+            },
+            (Drawable::Animated(mesh), Material::NormalMapped(texture, normals)) => {
+                let buffer = &mesh.joint_buffer;
+                target_frame
+                    .draw(
+                        &*mesh.vertices,
+                        &*mesh.indices,
+                        program,
+                        &uniform! { model: model, view: view, perspective: perspective, MyBlock: buffer, myTextureSampler: texture, normalMapSampler: normals, opacity: opacity, alpha_cutoff: alpha_cutoff },
+                        params,
+                    )
+                    .unwrap();
+            },
+            (Drawable::Animated(mesh), Material::Flat) => {
+                let buffer = &mesh.joint_buffer;
+                target_frame
+                    .draw(
+                        &*mesh.vertices,
+                        &*mesh.indices,
+                        program,
+                        &uniform! { model: model, view: view, perspective: perspective, MyBlock: buffer, opacity: opacity, alpha_cutoff: alpha_cutoff },
+                        params,
+                    )
+                    .unwrap();
+            },
+        }
+    }
+
+    // accumulates all transparent items into the oit targets of the frame in any order, then resolves
+    // them over the scene. the depth state is the one of the sorted transparent items
+    fn draw_oit<F: glium::backend::Facade, S: glium::Surface>(&mut self,target_frame:&mut S,display:&F, view: [[f32;4];4], perspective: [[f32;4];4], items: &[DrawItem], transparent_params: &glium::DrawParameters){
+
+        use glium::{Blend,BlendingFunction,LinearBlendingFactor};
+        use glium::Surface;
+
+        let targets = self.current_targets.clone().unwrap();
+        let params = glium::DrawParameters {
+            blend: Blend {
+                color: BlendingFunction::Addition {
+                    source: LinearBlendingFactor::One,
+                    destination: LinearBlendingFactor::One,
+                },
+                alpha: BlendingFunction::Addition {
+                    source: LinearBlendingFactor::Zero,
+                    destination: LinearBlendingFactor::OneMinusSourceAlpha,
+                },
+                constant_value: (0.0, 0.0, 0.0, 0.0),
+            },
+            ..transparent_params.clone()
+        };
+
+        {
+            let outputs = [("accum", &targets.oit_accum), ("weight", &targets.oit_weight)];
+            let mut oit_frame = glium::framebuffer::MultiOutputFrameBuffer::with_depth_buffer(display, outputs.iter().cloned(), &targets.depth).unwrap();
+            oit_frame.clear_color(0.0, 0.0, 0.0, 1.0);
+            for item in items {
+                self.mesh_shaders.prepare(display, MeshVariant::of(item, false, true));
+                self.draw_item(&mut oit_frame, view, perspective, item, &params, true);
+                self.stats.draw_calls += 1;
+                self.stats.triangles += item.mesh.triangles();
+            }
+        }
+        self.post.composite_oit(target_frame, &targets);
+    }
+
+    fn draw_instanced<F: glium::backend::Facade, S: glium::Surface>(&mut self,target_frame:&mut S,display:&F, view: [[f32;4];4], perspective: [[f32;4];4], batch: &[DrawItem], params: &glium::DrawParameters){
+
+        use cgmath::conv;
+
+        let instances: Vec<InstanceAttr> = batch.iter()
+            .map(|item| InstanceAttr { instance_model: conv::array4x4(item.transform) })
+            .collect();
+        let instance_buffer = glium::VertexBuffer::dynamic(display, &instances).unwrap();
+        let (opacity, alpha_cutoff) = batch[0].alpha.uniforms();
+        let program = self.mesh_shaders.get(MeshVariant::of(&batch[0], true, false));
+
+        match (batch[0].mesh, batch[0].material) {
+            (Drawable::Static(mesh), Material::Flat) => {
+                target_frame
+                    .draw(
+                        (&*mesh.vertices, instance_buffer.per_instance().unwrap()),
+                        &*mesh.indices,
+                        program,
+                        &uniform! { view: view, perspective: perspective, opacity: opacity, alpha_cutoff: alpha_cutoff },
+                        params,
+                    )
+                    .unwrap();
+            },
+            (Drawable::Static(mesh), Material::Textured(texture)) => {
+                target_frame
+                    .draw(
+                        (&*mesh.vertices, instance_buffer.per_instance().unwrap()),
+                        &*mesh.indices,
+                        program,
+                        &uniform! { view: view, myTextureSampler: texture, perspective: perspective, opacity: opacity, alpha_cutoff: alpha_cutoff },
+                        params,
+                    )
+                    .unwrap();
+            },
+            (Drawable::Static(mesh), Material::NormalMapped(texture, normals)) => {
+                target_frame
+                    .draw(
+                        (&*mesh.vertices, instance_buffer.per_instance().unwrap()),
+                        &*mesh.indices,
+                        program,
+                        &uniform! { view: view, myTextureSampler: texture, normalMapSampler: normals, perspective: perspective, opacity: opacity, alpha_cutoff: alpha_cutoff },
+                        params,
+                    )
+                    .unwrap();
+            },
+            (Drawable::Animated(mesh), material) => {
+                // one MAX_JOINTS palette per instance, indexed by gl_InstanceID in the shader.
+                // every batch in a frame gets its own slot, slots are kept and reused across frames
+                // and only the instances whose pose changed since the last frame are written
+                let slot = self.next_joint_palette;
+                self.next_joint_palette += 1;
+                if slot == self.joint_palettes.len() || self.joint_palettes[slot].capacity < batch.len() {
+                    let capacity = batch.len().next_power_of_two();
+                    let palette = JointPalette{
+                        buffer: glium::uniforms::UniformBuffer::empty_unsized(display, 4*4*4*MAX_JOINTS*capacity).unwrap(),
+                        capacity,
+                        poses: Vec::new(),
+                    };
+                    if slot == self.joint_palettes.len() {
+                        self.joint_palettes.push(palette);
+                    } else {
+                        self.joint_palettes[slot] = palette;
+                    }
+                }
+                let palette = &mut self.joint_palettes[slot];
+                let poses: Vec<usize> = batch.iter().map(|item| match item.mesh {
+                    Drawable::Animated(instance) => instance.pose_id,
+                    Drawable::Static(_) => 0,
+                }).collect();
+                if palette.poses != poses {
+                    let mut mapping = palette.buffer.map();
+                    for (k, item) in batch.iter().enumerate() {
+                        if palette.poses.get(k) == Some(&poses[k]) {
+                            continue;
+                        }
+                        if let Drawable::Animated(instance) = item.mesh {
+                            mapping.transform_m[k*MAX_JOINTS..(k+1)*MAX_JOINTS].copy_from_slice(&instance.current_pose);
+                        }
+                    }
+                    palette.poses = poses;
+                }
+                let palette = &self.joint_palettes[slot].buffer;
+
+                match material {
+                    Material::Textured(texture) => {
+                        target_frame
+                            .draw(
+                                (&*mesh.vertices, instance_buffer.per_instance().unwrap()),
+                                &*mesh.indices,
+                                program,
+                                &uniform! { view: view, perspective: perspective, MyBlock: palette, myTextureSampler: texture, opacity: opacity, alpha_cutoff: alpha_cutoff },
+                                params,
+                            )
+                            .unwrap();
+                    },
+                    Material::NormalMapped(texture, normals) => {
+                        target_frame
+                            .draw(
+                                (&*mesh.vertices, instance_buffer.per_instance().unwrap()),
+                                &*mesh.indices,
+                                program,
+                                &uniform! { view: view, perspective: perspective, MyBlock: palette, myTextureSampler: texture, normalMapSampler: normals, opacity: opacity, alpha_cutoff: alpha_cutoff },
+                                params,
+                            )
+                            .unwrap();
+                    },
+                    Material::Flat => {
+                        target_frame
+                            .draw(
+                                (&*mesh.vertices, instance_buffer.per_instance().unwrap()),
+                                &*mesh.indices,
+                                program,
+                                &uniform! { view: view, perspective: perspective, MyBlock: palette, opacity: opacity, alpha_cutoff: alpha_cutoff },
+                                params,
+                            )
+                            .unwrap();
+                    },
+                }
+            },
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const YAWS: [f32; 5] = [0.0, 0.7, 1.5707964, 3.0, 5.5];
+    const PITCHES: [f32; 5] = [0.0, 0.4, -0.4, 1.2, -1.2];
+
+    fn cam(yaw: f32, pitch: f32) -> Cam {
+        let mut cam = Cam::new(Point3::new(0.0, 0.0, 0.0), Vector3::new(0.0, 0.0, -1.0), Projection::default_perspective());
+        cam.rotate(yaw, pitch);
+        cam
+    }
+
+    fn assert_close(a: Vector3<f32>, b: Vector3<f32>) {
+        assert!((a - b).magnitude() < 1e-5, "{:?} != {:?}", a, b);
+    }
+
+    #[test]
+    fn forward_looks_down_negative_z() {
+        assert_close(cam(0.0, 0.0).forward_v(), Vector3::new(0.0, 0.0, -1.0));
+        assert_close(cam(0.0, 0.0).move_dir(Vector3::new(1.0, 0.0, 0.0)), Vector3::new(1.0, 0.0, 0.0));
+    }
+
+    #[test]
+    fn move_dir_follows_yaw_and_pitch() {
+        for &yaw in YAWS.iter() {
+            for &pitch in PITCHES.iter() {
+                let cam = cam(yaw, pitch);
+                let forward = cam.forward_v();
+                let right = cam.move_dir(Vector3::new(1.0, 0.0, 0.0));
+
+                assert_close(cam.move_dir(Vector3::new(0.0, 0.0, 1.0)), forward);
+                assert_close(cam.move_dir(Vector3::new(0.0, 0.0, -1.0)), -forward);
+                // strafing stays level and at a right angle to the view
+                assert!(right.y.abs() < 1e-6);
+                assert!((right.magnitude() - 1.0).abs() < 1e-5);
+                assert!(right.dot(forward).abs() < 1e-5);
+                assert_close(cam.move_dir(Vector3::new(-1.0, 0.0, 0.0)), -right);
+
+                let d = std::f32::consts::FRAC_1_SQRT_2;
+                for &(x, z) in [(d, d), (-d, d), (d, -d), (-d, -d)].iter() {
+                    let diagonal = cam.move_dir(Vector3::new(x, 0.0, z));
+                    assert_close(diagonal, right * x + forward * z);
+                    assert!((diagonal.magnitude() - 1.0).abs() < 1e-5);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn move_dir_keeps_the_input_length() {
+        let cam = cam(0.7, 1.2);
+        // forward and up overlap while looking up, the sum must not get longer than the input
+        let up_forward = Vector3::new(0.0, 1.0, 1.0).normalize() * 0.5;
+        assert!((cam.move_dir(up_forward).magnitude() - 0.5).abs() < 1e-5);
+        assert_close(cam.move_dir(Vector3::new(0.0, 0.0, 0.0)), Vector3::new(0.0, 0.0, 0.0));
+    }
+
+    #[test]
+    fn yaw_wraps_around() {
+        let two_pi = std::f32::consts::PI * 2.0;
+        let mut cam = cam(0.0, 0.0);
+        cam.rotate(two_pi + 0.5, 0.0);
+        assert!((cam.yaw - 0.5).abs() < 1e-5);
+        cam.rotate(-1.0, 0.0);
+        assert!((cam.yaw - (two_pi - 0.5)).abs() < 1e-5);
+        for _ in 0..100 {
+            cam.rotate(0.3, 0.0);
+            assert!(cam.yaw >= 0.0 && cam.yaw < two_pi);
+        }
+    }
+
+    #[test]
+    fn pitch_stops_at_max_pitch() {
+        let mut cam = cam(0.0, 0.0);
+        cam.rotate(0.0, 10.0);
+        assert_eq!(cam.pitch, MAX_PITCH);
+        cam.rotate(0.0, 0.5);
+        assert_eq!(cam.pitch, MAX_PITCH);
+        cam.rotate(0.0, -20.0);
+        assert_eq!(cam.pitch, -MAX_PITCH);
+        // looking straight up ends at the clamp too
+        cam.look_dir(Vector3::new(0.0, 1.0, 0.0));
+        assert_eq!(cam.pitch, MAX_PITCH);
+    }
+
+    #[test]
+    fn look_dir_round_trips_through_forward() {
+        let dirs = [
+            Vector3::new(0.0, 0.0, -1.0), Vector3::new(0.0, 0.0, 1.0), Vector3::new(1.0, 0.0, 0.0),
+            Vector3::new(-3.0, 1.0, 2.0), Vector3::new(0.5, -2.0, -0.5), Vector3::new(2.0, 0.3, -7.0),
+        ];
+        for dir in dirs.iter() {
+            let mut cam = cam(1.0, 0.3);
+            cam.look_dir(*dir);
+            assert_close(cam.forward_v(), dir.normalize());
+        }
+    }
+
+    // a point at the toggle distance stays where it was on screen
+    #[test]
+    fn orthographic_matches_the_perspective_at_the_distance() {
+        let mut cam = cam(0.0, 0.0);
+        let point = Vector4::new(1.0, 0.5, -5.0, 1.0);
+        let project = |cam: &Cam| {
+            let p = cam.perspective * cam.view() * point;
+            (p.x / p.w, p.y / p.w)
+        };
+        let before = project(&cam);
+        cam.set_projection(cam.projection.toggled(5.0));
+        match cam.projection {
+            Projection::Orthographic { .. } => (),
+            other => panic!("{:?} is not orthographic", other),
+        }
+        let after = project(&cam);
+        assert!((before.0 - after.0).abs() < 1e-5 && (before.1 - after.1).abs() < 1e-5, "{:?} != {:?}", before, after);
+
+        cam.set_projection(cam.projection.toggled(5.0));
+        assert_eq!(cam.projection, Projection::default_perspective());
+    }
+
+    // the pixel a world point lands on in a width x height viewport
+    fn to_screen(cam: &Cam, p: Vector3<f32>, width: f32, height: f32) -> (f32, f32) {
+        let p = cam.perspective * cam.view() * p.extend(1.0);
+        ((p.x / p.w + 1.0) * 0.5 * width, (1.0 - p.y / p.w) * 0.5 * height)
+    }
+
+    #[test]
+    fn screen_rays_start_on_the_near_plane_and_go_through_the_pixel() {
+        let projections = [
+            Projection::default_perspective(),
+            Projection::Perspective{ fov: 1.2, near: 0.5, far: 0.0, infinite: true },
+            Projection::Orthographic{ size: 6.0, near: 0.1, far: 100.0 },
+        ];
+        for projection in projections.iter() {
+            let mut cam = Cam::new(Point3::new(1.0, 2.0, 3.0), Vector3::new(1.0, -0.5, -1.0), *projection);
+            cam.set_viewport(800.0, 600.0);
+            let near = match *projection { Projection::Perspective { near, .. } | Projection::Orthographic { near, .. } => near };
+
+            let center = cam.screen_ray(400.0, 300.0, 800.0, 600.0).unwrap();
+            assert_close(center.dir, cam.forward_v());
+            assert_close(center.origin, cam.pos.to_vec() + cam.forward_v() * near);
+
+            for &(x, y) in [(0.0, 0.0), (800.0, 600.0), (123.0, 456.0)].iter() {
+                let ray = cam.screen_ray(x, y, 800.0, 600.0).unwrap();
+                assert!((ray.dir.magnitude() - 1.0).abs() < 1e-5);
+                assert!(((ray.origin - cam.pos.to_vec()).dot(cam.forward_v()) - near).abs() < 1e-3, "{:?}", ray);
+                for &t in [0.0, 1.0, 20.0].iter() {
+                    let (px, py) = to_screen(&cam, ray.at(t), 800.0, 600.0);
+                    assert!((px - x).abs() < 0.05 && (py - y).abs() < 0.05, "{:?} at {} lands on {} {}", projection, t, px, py);
+                }
+                match *projection {
+                    Projection::Orthographic { .. } => assert_close(ray.dir, cam.forward_v()),
+                    _ => assert_close(ray.dir, (ray.origin - cam.pos.to_vec()).normalize()),
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn reversed_z_maps_near_to_one_and_far_to_zero() {
+        let projections = [
+            Projection::Perspective{ fov: 1.0, near: 0.1, far: 100.0, infinite: false },
+            Projection::Perspective{ fov: 1.0, near: 0.1, far: 100.0, infinite: true },
+            Projection::Orthographic{ size: 4.0, near: 0.1, far: 100.0 },
+        ];
+        for projection in projections.iter() {
+            let m = projection.reversed_z_matrix(1.5);
+            let depth = |z: f32| {
+                let p = m * Vector4::new(0.5, -0.3, z, 1.0);
+                p.z / p.w
+            };
+            assert!((depth(-0.1) - 1.0).abs() < 1e-5, "{:?} puts near at {}", projection, depth(-0.1));
+            match *projection {
+                Projection::Perspective { infinite: true, .. } => assert!(depth(-1e6) < 1e-6 && depth(-1e6) > 0.0),
+                _ => assert!(depth(-100.0).abs() < 1e-5, "{:?} puts far at {}", projection, depth(-100.0)),
+            }
+            // closer is deeper all the way, x and y are those of the usual matrix
+            assert!(depth(-1.0) > depth(-2.0) && depth(-2.0) > depth(-50.0));
+            let usual = projection.matrix(1.5) * Vector4::new(0.5, -0.3, -7.0, 1.0);
+            let reversed = m * Vector4::new(0.5, -0.3, -7.0, 1.0);
+            assert_eq!((usual.x, usual.y, usual.w), (reversed.x, reversed.y, reversed.w));
+
+            // culling and picking see the same volume
+            let mut cam = Cam::new(Point3::new(0.0, 1.0, 2.0), Vector3::new(0.0, 0.0, -1.0), *projection);
+            let frustum = cam.frustum().planes;
+            let ray = cam.screen_ray(10.0, 20.0, 300.0, 200.0).unwrap();
+            cam.set_reversed_z(true);
+            assert_eq!(cam.perspective, projection.reversed_z_matrix(cam.aspect));
+            assert_eq!(cam.frustum().planes, frustum);
+            let reversed_ray = cam.screen_ray(10.0, 20.0, 300.0, 200.0).unwrap();
+            assert_close(reversed_ray.origin, ray.origin);
+            assert_close(reversed_ray.dir, ray.dir);
+            assert_eq!(cam.clear_depth(), 0.0);
+        }
+    }
+}
@@ -0,0 +1,292 @@
+use crate::my_game_engine::my_game_logic::my_input::{InputMap,InputEvent};
+
+// the input of a session by the fixed update it was handled in, together with the seed of the rng.
+// the cursor, the mouse buttons and typed text are in it, so ui clicks and console commands
+// replay too. resizes are not
+pub struct InputRecording {
+    pub seed: u64,
+    pub events: Vec<(u64, InputEvent)>,
+}
+
+impl InputRecording {
+
+    pub fn new(seed: u64) -> InputRecording {
+        InputRecording{
+            seed,
+            events: Vec::new(),
+        }
+    }
+
+    // seed <n> in the first line, then one <tick> <event> per line, see InputEvent::parse
+    pub fn load(path: &str) -> Result<InputRecording, String> {
+        let text = std::fs::read_to_string(path).map_err(|e| format!("{}: {}", path, e))?;
+        let mut lines = text.lines().enumerate();
+        let seed = match lines.next().map(|(_, l)| l.split_whitespace().collect::<Vec<&str>>()) {
+            Some(ref words) if words.len() == 2 && words[0] == "seed" => words[1].parse::<u64>().map_err(|_| format!("{}:1: bad seed", path))?,
+            _ => return Err(format!("{}:1: expected seed <n>", path)),
+        };
+        let mut recording = InputRecording::new(seed);
+
+        for (i, line) in lines {
+            let words: Vec<&str> = line.split_whitespace().collect();
+            if words.is_empty() {
+                continue;
+            }
+            let tick = words[0].parse::<u64>().map_err(|_| format!("{}:{}: bad tick {}", path, i + 1, words[0]))?;
+            let event = InputEvent::parse(&words[1..]).ok_or_else(|| format!("{}:{}: can not read {}", path, i + 1, line))?;
+            recording.events.push((tick, event));
+        }
+        Ok(recording)
+    }
+
+    pub fn save(&self, path: &str) -> std::io::Result<()> {
+        let mut lines = vec![format!("seed {}", self.seed)];
+        for (tick, event) in self.events.iter() {
+            lines.push(format!("{} {}", tick, event.describe()));
+        }
+        lines.push(String::new());
+        std::fs::write(path, lines.join("\n"))
+    }
+
+    pub fn last_tick(&self) -> u64 {
+        self.events.last().map_or(0, |e| e.0)
+    }
+}
+
+pub enum InputSession {
+    Live,
+    // written to path when finished
+    Recording { recording: InputRecording, path: String },
+    // next is the first event not handed to the map yet
+    Replaying { recording: InputRecording, next: usize },
+}
+
+impl InputSession {
+
+    pub fn is_live(&self) -> bool {
+        matches!(self, InputSession::Live)
+    }
+
+    // call before the update of every tick. a recording takes what the map got since the last
+    // tick, a replay gives the map what it got in this tick back then
+    pub fn begin_tick(&mut self, tick: u64, input: &mut InputMap) {
+        let mut finished = false;
+        match self {
+            InputSession::Live => (),
+            InputSession::Recording { recording, .. } => {
+                for event in input.take_captured() {
+                    recording.events.push((tick, event));
+                }
+            },
+            InputSession::Replaying { recording, next } => {
+                while *next < recording.events.len() && recording.events[*next].0 <= tick {
+                    input.apply(recording.events[*next].1.clone());
+                    *next += 1;
+                }
+                finished = *next >= recording.events.len();
+            },
+        }
+        // the devices take over where the replay ends
+        if finished {
+            println!("replay finished at tick {}", tick);
+            input.live = true;
+            *self = InputSession::Live;
+        }
+    }
+
+    // saves a recording, call once the game ends
+    pub fn finish(&mut self) {
+        if let InputSession::Recording { recording, path } = std::mem::replace(self, InputSession::Live) {
+            match recording.save(&path) {
+                Ok(()) => println!("recorded {} input events to {}", recording.events.len(), path),
+                Err(e) => println!("could not save the input recording: {}", e),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use glutin::{ElementState,VirtualKeyCode,MouseButton};
+    use crate::my_game_engine::my_game_logic::CCGame;
+    use crate::my_game_engine::my_game_logic::my_input::{PadAxis,PadButton};
+    use crate::my_game_engine::my_game_logic::my_renderer::Projection;
+    use crate::my_game_engine::my_game_logic::my_text_input::TextEvent;
+
+    const TICKS: u64 = 100;
+
+    fn key(key: VirtualKeyCode, scancode: u32, state: ElementState) -> glutin::WindowEvent {
+        glutin::WindowEvent::KeyboardInput {
+            device_id: unsafe { glutin::DeviceId::dummy() },
+            input: glutin::KeyboardInput { scancode, state, virtual_keycode: Some(key), modifiers: Default::default() },
+        }
+    }
+
+    // what a player does before the update of a tick, through the entry points of the devices
+    fn play(game: &mut CCGame, tick: u64) {
+        let device_id = unsafe { glutin::DeviceId::dummy() };
+        match tick {
+            3 => game.input.handle_pad_axis(PadAxis::LeftY, 0.8),
+            10 => game.input.handle_device_event(&glutin::DeviceEvent::MouseMotion { delta: (40.0, -12.0) }),
+            20 => game.input.handle_pad_button(PadButton::LeftThumb, ElementState::Pressed),
+            30 => game.input.handle_pad_axis(PadAxis::LeftX, -0.6),
+            45 => {
+                game.input.handle_pad_axis(PadAxis::LeftY, 0.0);
+                game.input.handle_pad_axis(PadAxis::LeftX, 0.0);
+            },
+            50 => game.input.handle_window_event(&key(VirtualKeyCode::F, 33, ElementState::Pressed)),
+            51 => game.input.handle_window_event(&key(VirtualKeyCode::F, 33, ElementState::Released)),
+            // above the debug panel, its windows would take the click
+            60 => game.input.handle_window_event(&glutin::WindowEvent::CursorMoved {
+                device_id,
+                position: glutin::dpi::LogicalPosition::new(200.0, 100.0),
+                modifiers: Default::default(),
+            }),
+            64 => game.input.handle_window_event(&glutin::WindowEvent::MouseInput {
+                device_id,
+                state: ElementState::Pressed,
+                button: MouseButton::Left,
+                modifiers: Default::default(),
+            }),
+            // the console opens on grave, which also types a char like a real keyboard does
+            70 => {
+                game.input.handle_window_event(&key(VirtualKeyCode::Grave, 41, ElementState::Pressed));
+                game.input.handle_text(TextEvent::Char('`'));
+            },
+            71 => game.input.handle_window_event(&key(VirtualKeyCode::Grave, 41, ElementState::Released)),
+            75 => {
+                for c in "fov 90".chars() {
+                    game.input.handle_text(TextEvent::Char(c));
+                }
+                game.input.handle_text(TextEvent::Enter);
+            },
+            _ => (),
+        }
+    }
+
+    #[test]
+    fn replay_reaches_the_recorded_state() {
+        let path = std::env::temp_dir().join(format!("cc_replay_{}.txt", std::process::id()));
+        let path = path.to_str().unwrap();
+
+        let mut recorded = CCGame::new();
+        recorded.record_input(path, 7);
+        for tick in 0..TICKS {
+            play(&mut recorded, tick);
+            recorded.update(&10.0);
+        }
+        recorded.session.finish();
+
+        let mut replayed = CCGame::new();
+        replayed.replay_input(path).unwrap();
+        for _ in 0..TICKS {
+            replayed.update(&10.0);
+        }
+        std::fs::remove_file(path).unwrap();
+
+        assert!(recorded.cam.pos != cgmath::Point3::new(0.0, 0.0, 0.0));
+        assert_eq!(recorded.cam.pos, replayed.cam.pos);
+        assert_eq!(recorded.cam.yaw, replayed.cam.yaw);
+        assert_eq!(recorded.cam.pitch, replayed.cam.pitch);
+        assert!(!replayed.ego_mode);
+        assert_eq!(recorded.pick_at, Some(glutin::dpi::LogicalPosition::new(200.0, 100.0)));
+        assert_eq!(recorded.pick_at, replayed.pick_at);
+        assert_eq!(recorded.rng.next_u64(), replayed.rng.next_u64());
+        match replayed.cam.projection {
+            Projection::Perspective { fov, .. } => assert!((fov - 90f32.to_radians()).abs() < 1e-6),
+            _ => panic!("the projection changed"),
+        }
+        assert_eq!(recorded.cam.projection, replayed.cam.projection);
+        assert!(replayed.console.open && replayed.console.line.is_empty());
+    }
+
+    fn mouse(game: &mut CCGame, state: ElementState) {
+        game.input.handle_window_event(&glutin::WindowEvent::MouseInput {
+            device_id: unsafe { glutin::DeviceId::dummy() },
+            state,
+            button: MouseButton::Left,
+            modifiers: Default::default(),
+        });
+    }
+
+    fn move_cursor(game: &mut CCGame, x: f64, y: f64) {
+        game.input.handle_window_event(&glutin::WindowEvent::CursorMoved {
+            device_id: unsafe { glutin::DeviceId::dummy() },
+            position: glutin::dpi::LogicalPosition::new(x, y),
+            modifiers: Default::default(),
+        });
+    }
+
+    // frees the cursor, types into the name field of the capture window, clicks its screenshot
+    // button within one tick and unticks culling in the renderer window
+    fn use_the_panel(game: &mut CCGame, tick: u64) {
+        match tick {
+            2 => game.input.handle_window_event(&key(VirtualKeyCode::F, 33, ElementState::Pressed)),
+            3 => game.input.handle_window_event(&key(VirtualKeyCode::F, 33, ElementState::Released)),
+            5 => move_cursor(game, 600.0, 178.0),
+            6 => mouse(game, ElementState::Pressed),
+            7 => mouse(game, ElementState::Released),
+            9 => {
+                for c in "shots".chars() {
+                    game.input.handle_text(TextEvent::Char(c));
+                }
+            },
+            10 => game.input.handle_text(TextEvent::Backspace),
+            11 => game.input.handle_text(TextEvent::Enter),
+            13 => move_cursor(game, 600.0, 202.0),
+            14 => {
+                mouse(game, ElementState::Pressed);
+                mouse(game, ElementState::Released);
+            },
+            16 => move_cursor(game, 100.0, 202.0),
+            17 => mouse(game, ElementState::Pressed),
+            18 => mouse(game, ElementState::Released),
+            _ => (),
+        }
+    }
+
+    // the ui runs on the tick, how many frames are drawn in between must not change what it does
+    #[test]
+    fn ui_edits_replay_at_any_frame_rate() {
+        use crate::my_game_engine::render_offscreen;
+        use crate::my_game_engine::my_game_logic::my_egl;
+        use crate::my_game_engine::my_game_logic::my_renderer::Renderer;
+
+        let Some(context) = my_egl::test_context((64, 64)) else { return };
+        let path = std::env::temp_dir().join(format!("cc_replay_ui_{}.txt", std::process::id()));
+        let path = path.to_str().unwrap();
+        let ticks = 25;
+        // a frame after every frame_every ticks and one at the end
+        let run = |game: &mut CCGame, frame_every: u64, play: &dyn Fn(&mut CCGame, u64)| {
+            let mut renderer = Renderer::new(&context, &my_egl::get_proc_address);
+            let culling = renderer.culling;
+            for tick in 0..ticks {
+                play(game, tick);
+                game.update(&10.0);
+                if tick % frame_every == 0 || tick == ticks - 1 {
+                    render_offscreen(&context, &mut renderer, (64, 64), |renderer, scene| game.render(renderer, scene, &context));
+                }
+            }
+            renderer.culling != culling
+        };
+
+        let mut recorded = CCGame::new();
+        recorded.record_input(path, 7);
+        let recorded_culling = run(&mut recorded, 1, &use_the_panel);
+        recorded.session.finish();
+        assert_eq!(recorded.screenshot_name, "shot");
+        assert!(recorded.screenshot_requested);
+        assert!(recorded_culling);
+
+        for frame_every in [4, ticks] {
+            let mut replayed = CCGame::new();
+            replayed.replay_input(path).unwrap();
+            let culling = run(&mut replayed, frame_every, &|_, _| ());
+            assert_eq!(replayed.screenshot_name, "shot", "at a frame every {} ticks", frame_every);
+            assert!(replayed.screenshot_requested, "at a frame every {} ticks", frame_every);
+            assert!(culling, "at a frame every {} ticks", frame_every);
+            assert!(!replayed.ui.has_focus());
+        }
+        std::fs::remove_file(path).unwrap();
+    }
+}
@@ -0,0 +1,527 @@
+use std::collections::HashMap;
+use std::path::{Path,PathBuf};
+use std::sync::mpsc;
+use std::time::Duration;
+use glium::backend::Facade;
+use notify::{Watcher,RecursiveMode,DebouncedEvent};
+
+// GL_MAX_VERTEX_SHADER_STORAGE_BLOCKS, skinning reads the palette in the vertex shader
+const MAX_VERTEX_SHADER_STORAGE_BLOCKS: u32 = 0x90D6;
+
+// the glsl dialect the sources are compiled as, picked from the context version once by the renderer.
+// shaders are written against 330/300 es and only use storage buffers behind HAS_STORAGE_BUFFERS,
+// a 3.2 context gets 150 where they still build
+#[derive(Clone, Debug, PartialEq)]
+pub struct GlslTarget {
+    // replaces the #version line of every root file
+    pub version: String,
+    pub storage_buffers: bool,
+    pub es: bool,
+}
+
+impl GlslTarget {
+
+    // get_proc_address is the one of the window or context, glium has no way to ask for the
+    // storage block limit. fails on contexts the shaders can not be built for
+    pub fn detect<F: Facade>(display: &F, get_proc_address: &dyn Fn(&str) -> *const std::os::raw::c_void) -> Result<GlslTarget, String> {
+        use glium::{Api,Version};
+
+        let context = display.get_context();
+        match *context.get_opengl_version() {
+            // storage buffers are core since 4.3, but may still be missing in the vertex stage
+            Version(Api::Gl, major, minor) if (major, minor) >= (4, 3) => {
+                let Version(_, glsl_major, glsl_minor) = context.get_supported_glsl_version();
+                let storage_buffers = vertex_storage_blocks(display, get_proc_address) > 0;
+                Ok(GlslTarget{ version: format!("#version {}{}0", glsl_major, glsl_minor), storage_buffers, es: false })
+            },
+            Version(Api::Gl, major, minor) if (major, minor) >= (3, 3) => Ok(GlslTarget{ version: "#version 330 core".to_string(), storage_buffers: false, es: false }),
+            Version(Api::Gl, 3, 2) => Ok(GlslTarget{ version: "#version 150 core".to_string(), storage_buffers: false, es: false }),
+            // es 3.1 only requires them in compute shaders, most mobile drivers have none in the vertex stage
+            Version(Api::GlEs, major, minor) if (major, minor) >= (3, 1) => {
+                let storage_buffers = vertex_storage_blocks(display, get_proc_address) > 0;
+                Ok(GlslTarget{ version: "#version 310 es".to_string(), storage_buffers, es: true })
+            },
+            Version(Api::GlEs, 3, _) => Ok(GlslTarget{ version: "#version 300 es".to_string(), storage_buffers: false, es: true }),
+            Version(api, major, minor) => Err(format!("{:?} {}.{} is not supported, the shaders need at least opengl 3.2 or opengl es 3.0", api, major, minor)),
+        }
+    }
+
+    pub fn describe(&self) -> String {
+        format!("{}{}", self.version, if self.storage_buffers { "" } else { ", without storage buffers" })
+    }
+
+    // goes right after the version line, before the defines of the variant
+    fn prelude(&self) -> String {
+        let mut prelude = String::new();
+        if self.storage_buffers {
+            prelude.push_str("#define HAS_STORAGE_BUFFERS 1\n");
+        }
+        if self.es {
+            prelude.push_str("precision highp float;\nprecision highp int;\n");
+        }
+        prelude
+    }
+}
+
+// 0 if glGetIntegerv can not be found
+fn vertex_storage_blocks<F: Facade>(display: &F, get_proc_address: &dyn Fn(&str) -> *const std::os::raw::c_void) -> i32 {
+    type GetIntegerv = extern "system" fn(u32, *mut i32);
+    let get_integerv = get_proc_address("glGetIntegerv");
+    if get_integerv.is_null() {
+        return 0;
+    }
+    let get_integerv: GetIntegerv = unsafe { std::mem::transmute(get_integerv) };
+    let mut blocks = 0;
+    unsafe {
+        display.get_context().exec_in_context(|| get_integerv(MAX_VERTEX_SHADER_STORAGE_BLOCKS, &mut blocks));
+    }
+    blocks
+}
+
+// a program that remembers where it came from so it can be rebuilt when its files change.
+// derefs to the glium program, so it can be passed to draw calls directly
+pub struct ShaderProgram {
+    program: glium::Program,
+    vertex_path: String,
+    fragment_path: String,
+    defines: Vec<(String, String)>,
+    outputs_srgb: bool,
+    target: GlslTarget,
+    // both stages and everything they include
+    files: Vec<PathBuf>,
+}
+
+impl ShaderProgram {
+
+    pub fn load<F: Facade>(display: &F, target: &GlslTarget, vertex_path: &str, fragment_path: &str) -> ShaderProgram {
+        ShaderProgram::load_variant(display, target, vertex_path, fragment_path, Vec::new(), false)
+    }
+
+    pub fn load_with_output<F: Facade>(display: &F, target: &GlslTarget, vertex_path: &str, fragment_path: &str, outputs_srgb: bool) -> ShaderProgram {
+        ShaderProgram::load_variant(display, target, vertex_path, fragment_path, Vec::new(), outputs_srgb)
+    }
+
+    // without a previous program to fall back to a broken shader is fatal
+    pub fn load_variant<F: Facade>(display: &F, target: &GlslTarget, vertex_path: &str, fragment_path: &str, defines: Vec<(String, String)>, outputs_srgb: bool) -> ShaderProgram {
+        ShaderProgram::try_load_variant(display, target, vertex_path, fragment_path, defines, outputs_srgb)
+            .unwrap_or_else(|log| panic!("could not build {} + {}\n{}", vertex_path, fragment_path, log))
+    }
+
+    pub fn try_load_variant<F: Facade>(display: &F, target: &GlslTarget, vertex_path: &str, fragment_path: &str, defines: Vec<(String, String)>, outputs_srgb: bool) -> Result<ShaderProgram, String> {
+        let (files, program) = ShaderProgram::compile(display, target, vertex_path, fragment_path, &defines, outputs_srgb);
+        program.map(|program| ShaderProgram{
+            program,
+            vertex_path: vertex_path.to_string(),
+            fragment_path: fragment_path.to_string(),
+            defines,
+            outputs_srgb,
+            target: target.clone(),
+            files,
+        })
+    }
+
+    // the file list is returned even if the build fails, so fixing an include triggers the next reload
+    fn compile<F: Facade>(display: &F, target: &GlslTarget, vertex_path: &str, fragment_path: &str, defines: &[(String, String)], outputs_srgb: bool) -> (Vec<PathBuf>, Result<glium::Program, String>) {
+        // both stages share the source string numbering, the log then says which file failed
+        let mut sources: Vec<String> = Vec::new();
+        let vertex_shader = preprocess(vertex_path, target, defines, &mut sources);
+        let fragment_shader = preprocess(fragment_path, target, defines, &mut sources);
+        let files = sources.iter().filter_map(|f| std::fs::canonicalize(f).ok()).collect();
+
+        let (vertex_shader, fragment_shader) = match (vertex_shader, fragment_shader) {
+            (Ok(v), Ok(f)) => (v, f),
+            (Err(e), _) | (_, Err(e)) => return (files, Err(e)),
+        };
+
+        let program = glium::Program::new(display, glium::program::ProgramCreationInput::SourceCode {
+            vertex_shader: &vertex_shader,
+            tessellation_control_shader: None,
+            tessellation_evaluation_shader: None,
+            geometry_shader: None,
+            fragment_shader: &fragment_shader,
+            transform_feedback_varyings: None,
+            outputs_srgb,
+            uses_point_size: false,
+        }).map_err(|e| locate_errors(&format!("{}", e), &sources));
+        (files, program)
+    }
+
+    pub fn uses(&self, changed: &[PathBuf]) -> bool {
+        changed.iter().any(|c| self.files.contains(c))
+    }
+
+    // keeps the current program if the new sources don't build
+    pub fn reload<F: Facade>(&mut self, display: &F) -> bool {
+        let (files, program) = ShaderProgram::compile(display, &self.target, &self.vertex_path, &self.fragment_path, &self.defines, self.outputs_srgb);
+        if !files.is_empty() {
+            self.files = files;
+        }
+        match program {
+            Ok(program) => {
+                self.program = program;
+                println!("reloaded {} + {}{}", self.vertex_path, self.fragment_path, self.describe_defines());
+                true
+            },
+            Err(log) => {
+                println!("could not reload {} + {}{}, keeping the last good program\n{}", self.vertex_path, self.fragment_path, self.describe_defines(), log);
+                false
+            },
+        }
+    }
+
+    pub fn reload_if_changed<F: Facade>(&mut self, display: &F, changed: &[PathBuf]) {
+        if self.uses(changed) {
+            self.reload(display);
+        }
+    }
+
+    fn describe_defines(&self) -> String {
+        describe_defines(&self.defines)
+    }
+}
+
+fn describe_defines(defines: &[(String, String)]) -> String {
+    if defines.is_empty() {
+        return String::new();
+    }
+    let names: Vec<&str> = defines.iter().map(|(k, _)| k.as_str()).collect();
+    format!(" [{}]", names.join(" "))
+}
+
+impl std::ops::Deref for ShaderProgram {
+    type Target = glium::Program;
+
+    fn deref(&self) -> &glium::Program {
+        &self.program
+    }
+}
+
+// a set of variants of one vertex/fragment pair, selected by defines
+pub trait ShaderPermutation: Copy + Eq + std::hash::Hash {
+    fn defines(&self) -> Vec<(String, String)>;
+    // drawn with instead when the variant does not build. only optional features may be left
+    // out, the inputs and outputs have to stay the same
+    fn base(&self) -> Self;
+}
+
+// compiles a variant the first time it is asked for, so only the ones in use are ever built
+pub struct ShaderCache<K: ShaderPermutation> {
+    vertex_path: String,
+    fragment_path: String,
+    target: GlslTarget,
+    programs: HashMap<K, ShaderProgram>,
+    // variants that did not build and the one drawn in their place, retried when a file changes
+    fallbacks: HashMap<K, K>,
+}
+
+impl<K: ShaderPermutation> ShaderCache<K> {
+
+    pub fn new(target: &GlslTarget, vertex_path: &str, fragment_path: &str) -> ShaderCache<K> {
+        ShaderCache{
+            vertex_path: vertex_path.to_string(),
+            fragment_path: fragment_path.to_string(),
+            target: target.clone(),
+            programs: HashMap::new(),
+            fallbacks: HashMap::new(),
+        }
+    }
+
+    // a variant that does not build falls back to its base, a base that does not build is fatal
+    pub fn prepare<F: Facade>(&mut self, display: &F, key: K) {
+        if self.programs.contains_key(&key) || self.fallbacks.contains_key(&key) {
+            return;
+        }
+        let base = key.base();
+        if base == key {
+            let program = ShaderProgram::load_variant(display, &self.target, &self.vertex_path, &self.fragment_path, key.defines(), false);
+            self.programs.insert(key, program);
+            return;
+        }
+        match ShaderProgram::try_load_variant(display, &self.target, &self.vertex_path, &self.fragment_path, key.defines(), false) {
+            Ok(program) => {
+                self.programs.insert(key, program);
+            },
+            Err(log) => {
+                println!("could not build {} + {}{}, drawing with{} instead\n{}", self.vertex_path, self.fragment_path,
+                    describe_defines(&key.defines()), describe_defines(&base.defines()), log);
+                self.prepare(display, base);
+                self.fallbacks.insert(key, base);
+            },
+        }
+    }
+
+    // the variant has to be prepared before
+    pub fn get(&self, key: K) -> &ShaderProgram {
+        &self.programs[self.fallbacks.get(&key).unwrap_or(&key)]
+    }
+
+    pub fn len(&self) -> usize {
+        self.programs.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.programs.is_empty()
+    }
+
+    pub fn reload_if_changed<F: Facade>(&mut self, display: &F, changed: &[PathBuf]) {
+        for program in self.programs.values_mut() {
+            program.reload_if_changed(display, changed);
+        }
+        if changed.is_empty() {
+            return;
+        }
+        let failed: Vec<K> = self.fallbacks.keys().cloned().collect();
+        for key in failed {
+            if let Ok(program) = ShaderProgram::try_load_variant(display, &self.target, &self.vertex_path, &self.fragment_path, key.defines(), false) {
+                println!("built {} + {}{}", self.vertex_path, self.fragment_path, describe_defines(&key.defines()));
+                self.programs.insert(key, program);
+                self.fallbacks.remove(&key);
+            }
+        }
+    }
+}
+
+// resolves #include "file" relative to the including file. every include is wrapped in a guard, so a
+// file is compiled once per stage even if the first include of it is in a branch the defines leave out.
+// the #version of the root file is replaced with the one of the target, the prelude and the defines go
+// right after it since it has to stay the first statement. every file gets its own source string number
+// through #line, sources collects the paths in that order
+fn preprocess(path: &str, target: &GlslTarget, defines: &[(String, String)], sources: &mut Vec<String>) -> Result<String, String> {
+    let mut out = String::new();
+    let mut guards: Vec<PathBuf> = Vec::new();
+    let mut stack: Vec<PathBuf> = Vec::new();
+    expand(Path::new(path), target, defines, sources, &mut guards, &mut stack, &mut out)?;
+    Ok(out)
+}
+
+fn expand(path: &Path, target: &GlslTarget, defines: &[(String, String)], sources: &mut Vec<String>, guards: &mut Vec<PathBuf>, stack: &mut Vec<PathBuf>, out: &mut String) -> Result<(), String> {
+    let text = std::fs::read_to_string(path).map_err(|e| format!("{}: {}", path.display(), e))?;
+    let number = sources.len();
+    sources.push(path.to_string_lossy().into_owned());
+    let canonical = std::fs::canonicalize(path).unwrap_or(path.to_path_buf());
+    stack.push(canonical);
+
+    let root = stack.len() == 1;
+    let mut versioned = !root;
+    if !root {
+        out.push_str(&format!("#line 1 {}\n", number));
+    }
+    if root && !text.lines().any(|l| l.trim_start().starts_with("#version")) {
+        push_prelude(target, defines, out);
+        out.push_str(&format!("#line 1 {}\n", number));
+        versioned = true;
+    }
+
+    for (i, line) in text.lines().enumerate() {
+        let trimmed = line.trim_start();
+        if let Some(name) = trimmed.strip_prefix("#include") {
+            let name = name.trim().trim_matches('"');
+            let include = path.parent().unwrap_or(Path::new(".")).join(name);
+            let canonical = std::fs::canonicalize(&include).map_err(|e| format!("{}:{}: {}: {}", path.display(), i + 1, include.display(), e))?;
+            if stack.contains(&canonical) {
+                return Err(format!("{}:{}: {} includes itself", path.display(), i + 1, include.display()));
+            }
+            let guard = match guards.iter().position(|g| *g == canonical) {
+                Some(guard) => guard,
+                None => {
+                    guards.push(canonical);
+                    guards.len() - 1
+                },
+            };
+            out.push_str(&format!("#ifndef INCLUDED_{0}\n#define INCLUDED_{0}\n", guard));
+            expand(&include, target, defines, sources, guards, stack, out)?;
+            out.push_str("#endif\n");
+            out.push_str(&format!("#line {} {}\n", i + 2, number));
+            continue;
+        }
+
+        if !versioned && trimmed.starts_with("#version") {
+            push_prelude(target, defines, out);
+            out.push_str(&format!("#line {} {}\n", i + 2, number));
+            versioned = true;
+            continue;
+        }
+        out.push_str(line);
+        out.push('\n');
+    }
+    stack.pop();
+    Ok(())
+}
+
+fn push_prelude(target: &GlslTarget, defines: &[(String, String)], out: &mut String) {
+    out.push_str(&target.version);
+    out.push('\n');
+    out.push_str(&target.prelude());
+    for (name, value) in defines {
+        out.push_str(&format!("#define {} {}\n", name, value));
+    }
+}
+
+// drivers print locations as 0:12(5) (mesa), 0(12) (nvidia) or 0:12 (others), the first number
+// is the source string. those are replaced with file:line
+fn locate_errors(log: &str, files: &[String]) -> String {
+    log.lines().map(|line| locate_line(line, files)).collect::<Vec<String>>().join("\n")
+}
+
+fn locate_line(line: &str, files: &[String]) -> String {
+    let bytes = line.as_bytes();
+    let digits = |from: usize| bytes[from..].iter().take_while(|b| b.is_ascii_digit()).count();
+
+    let mut i = 0;
+    while i < bytes.len() {
+        if bytes[i].is_ascii_digit() && (i == 0 || !bytes[i - 1].is_ascii_alphanumeric()) {
+            let s_len = digits(i);
+            let sep = i + s_len;
+            if sep + 1 < bytes.len() && (bytes[sep] == b':' || bytes[sep] == b'(') {
+                let l_len = digits(sep + 1);
+                if l_len > 0 {
+                    let source: usize = line[i..sep].parse().unwrap_or(usize::MAX);
+                    if let Some(file) = files.get(source) {
+                        let mut end = sep + 1 + l_len;
+                        if bytes[sep] == b'(' && end < bytes.len() && bytes[end] == b')' {
+                            end += 1;
+                        }
+                        return format!("{}{}:{}{}", &line[..i], file, &line[sep + 1..sep + 1 + l_len], &line[end..]);
+                    }
+                }
+            }
+            i = sep;
+        } else {
+            i += 1;
+        }
+    }
+    line.to_string()
+}
+
+// reports the files written under a directory since the last call
+pub struct ShaderWatcher {
+    _watcher: notify::RecommendedWatcher,
+    rx: mpsc::Receiver<DebouncedEvent>,
+}
+
+impl ShaderWatcher {
+
+    pub fn new(directory: &str) -> Option<ShaderWatcher> {
+        let (tx, rx) = mpsc::channel();
+        let watcher = notify::watcher(tx, Duration::from_millis(200)).and_then(|mut w| {
+            let directory = std::fs::canonicalize(directory)?;
+            w.watch(directory, RecursiveMode::Recursive)?;
+            Ok(w)
+        });
+        match watcher {
+            Ok(watcher) => Some(ShaderWatcher { _watcher: watcher, rx }),
+            Err(e) => {
+                println!("shader hot reload disabled, could not watch {}: {}", directory, e);
+                None
+            },
+        }
+    }
+
+    pub fn changed(&self) -> Vec<PathBuf> {
+        let mut changed: Vec<PathBuf> = Vec::new();
+        while let Ok(event) = self.rx.try_recv() {
+            match event {
+                // editors often save by writing a new file and renaming it over the old one
+                DebouncedEvent::Write(path) | DebouncedEvent::Create(path) | DebouncedEvent::Rename(_, path) if !changed.contains(&path) => {
+                    changed.push(path);
+                },
+                _ => (),
+            }
+        }
+        changed
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::my_game_engine::my_game_logic::my_egl;
+    use crate::my_game_engine::my_game_logic::my_renderer::MeshVariant;
+
+    fn temp_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("cc_shaders_{}_{}", name, std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    fn target() -> GlslTarget {
+        GlslTarget{ version: "#version 330 core".to_string(), storage_buffers: false, es: false }
+    }
+
+    // the first include is compiled out, the second one still has to bring the function in
+    #[test]
+    fn includes_in_a_disabled_branch_are_not_lost() {
+        let dir = temp_dir("ifdef");
+        std::fs::write(dir.join("helper.glsl"), "float helper() { return 0.5; }\n").unwrap();
+        std::fs::write(dir.join("vs.glsl"), "#version 330 core\n#ifdef NOT_DEFINED\n#include \"helper.glsl\"\n#endif\n#include \"helper.glsl\"\n#include \"helper.glsl\"\nin vec3 position;\nvoid main() { gl_Position = vec4(position * helper(), 1.0); }\n").unwrap();
+        std::fs::write(dir.join("fs.glsl"), "#version 330 core\nout vec4 color;\nvoid main() { color = vec4(1.0); }\n").unwrap();
+
+        let Some(context) = my_egl::test_context((16, 16)) else { return };
+        let vertex_path = dir.join("vs.glsl").to_string_lossy().into_owned();
+        let fragment_path = dir.join("fs.glsl").to_string_lossy().into_owned();
+        let (_, program) = ShaderProgram::compile(&context, &target(), &vertex_path, &fragment_path, &[], false);
+        std::fs::remove_dir_all(&dir).unwrap();
+        program.unwrap();
+    }
+
+    #[derive(Copy, Clone, PartialEq, Eq, Hash)]
+    struct Broken(bool);
+
+    impl ShaderPermutation for Broken {
+        // a define named like the color output breaks the fragment shader
+        fn defines(&self) -> Vec<(String, String)> {
+            if self.0 { vec![("color".to_string(), "1.0".to_string())] } else { Vec::new() }
+        }
+
+        fn base(&self) -> Broken {
+            Broken(false)
+        }
+    }
+
+    #[test]
+    fn a_broken_variant_falls_back_to_the_base() {
+        let Some(context) = my_egl::test_context((16, 16)) else { return };
+        let mut cache: ShaderCache<Broken> = ShaderCache::new(&target(), "./res/shader/debug_vs.glsl", "./res/shader/debug_fs.glsl");
+        cache.prepare(&context, Broken(true));
+        assert!(std::ptr::eq(cache.get(Broken(true)), cache.get(Broken(false))));
+        assert_eq!(cache.len(), 1);
+    }
+
+    // what a 3.2 context gets, every program has to build there too
+    #[test]
+    fn every_shader_builds_as_150() {
+        let Some(context) = my_egl::test_context((16, 16)) else { return };
+        let target = GlslTarget{ version: "#version 150 core".to_string(), storage_buffers: false, es: false };
+
+        let mut pairs: Vec<(String, String)> = ["debug", "overlay"].iter()
+            .map(|name| (format!("./res/shader/{}_vs.glsl", name), format!("./res/shader/{}_fs.glsl", name)))
+            .collect();
+        for sky in ["gradient", "cube", "equirect"].iter() {
+            pairs.push(("./res/shader/sky_vs.glsl".to_string(), format!("./res/shader/sky_{}_fs.glsl", sky)));
+        }
+        for entry in std::fs::read_dir("./res/shader/post").unwrap() {
+            let path = entry.unwrap().path().to_string_lossy().into_owned();
+            if path.ends_with("_fs.glsl") {
+                pairs.push(("./res/shader/post/post_vs.glsl".to_string(), path));
+            }
+        }
+        for (vertex_path, fragment_path) in pairs.iter() {
+            ShaderProgram::load(&context, &target, vertex_path, fragment_path);
+        }
+
+        // instanced skinning needs storage buffers, the renderer draws those one by one here
+        for &skinned in [false, true].iter() {
+            for &instanced in [false, true].iter() {
+                for &(textured, normal_mapped) in [(false, false), (true, false), (true, true)].iter() {
+                    for &oit in [false, true].iter() {
+                        if skinned && instanced {
+                            continue;
+                        }
+                        let variant = MeshVariant{ skinned, instanced, textured, normal_mapped, oit };
+                        ShaderProgram::load_variant(&context, &target, "./res/shader/mesh_vs.glsl", "./res/shader/mesh_fs.glsl", variant.defines(), false);
+                    }
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,113 @@
+use glium::backend::Facade;
+use crate::my_game_engine::my_game_logic::my_renderer::Renderer;
+use crate::my_game_engine::my_game_logic::my_shaders::{ShaderProgram,GlslTarget};
+
+#[derive(Copy, Clone)]
+pub struct SkyVertex {
+    position: [f32; 3],
+}
+#[allow(deprecated)]
+const _: () = { implement_vertex!(SkyVertex, position); };
+
+pub enum Sky {
+    // nothing drawn, only the clear color shows
+    Color,
+    Gradient { zenith: [f32; 3], horizon: [f32; 3], ground: [f32; 3] },
+    Cubemap(glium::texture::SrgbCubemap),
+    Equirectangular(glium::texture::Texture2d),
+}
+
+impl Sky {
+    pub fn default_gradient() -> Sky {
+        Sky::Gradient {
+            zenith: [0.15, 0.35, 0.75],
+            horizon: [0.7, 0.8, 0.95],
+            ground: [0.25, 0.22, 0.2],
+        }
+    }
+}
+
+pub struct SkyRenderer {
+    cube: glium::VertexBuffer<SkyVertex>,
+    gradient_program: ShaderProgram,
+    cubemap_program: ShaderProgram,
+    equirect_program: ShaderProgram,
+}
+
+impl SkyRenderer {
+
+    pub fn new<F: Facade>(display: &F, glsl: &GlslTarget) -> SkyRenderer {
+        let corners = [
+            [-1.0, -1.0, -1.0], [ 1.0, -1.0, -1.0], [ 1.0,  1.0, -1.0], [-1.0,  1.0, -1.0],
+            [-1.0, -1.0,  1.0], [ 1.0, -1.0,  1.0], [ 1.0,  1.0,  1.0], [-1.0,  1.0,  1.0],
+        ];
+        let faces: [[usize; 4]; 6] = [
+            [0, 1, 2, 3], [5, 4, 7, 6], [4, 0, 3, 7],
+            [1, 5, 6, 2], [3, 2, 6, 7], [4, 5, 1, 0],
+        ];
+        let mut vertices = Vec::with_capacity(36);
+        for f in faces.iter() {
+            for &i in [f[0], f[1], f[2], f[0], f[2], f[3]].iter() {
+                vertices.push(SkyVertex { position: corners[i] });
+            }
+        }
+
+        SkyRenderer{
+            cube: glium::VertexBuffer::new(display, &vertices).unwrap(),
+            gradient_program: Renderer::load_program(display, glsl, "./res/shader/sky_vs.glsl", "./res/shader/sky_gradient_fs.glsl"),
+            cubemap_program: Renderer::load_program(display, glsl, "./res/shader/sky_vs.glsl", "./res/shader/sky_cube_fs.glsl"),
+            equirect_program: Renderer::load_program(display, glsl, "./res/shader/sky_vs.glsl", "./res/shader/sky_equirect_fs.glsl"),
+        }
+    }
+
+    pub fn reload_shaders<F: Facade>(&mut self, display: &F, changed: &[std::path::PathBuf]) {
+        for program in [&mut self.gradient_program, &mut self.cubemap_program, &mut self.equirect_program] {
+            program.reload_if_changed(display, changed);
+        }
+    }
+
+    // drawn after the opaque geometry, only where nothing was drawn yet. returns the triangles drawn,
+    // none for a plain color
+    pub fn draw<S: glium::Surface>(&self, target_frame: &mut S, view: [[f32;4];4], perspective: [[f32;4];4], sky: &Sky, reversed_z: bool) -> usize {
+        use glium::uniforms::{Sampler,SamplerWrapFunction,MinifySamplerFilter,MagnifySamplerFilter};
+
+        let params = glium::DrawParameters {
+            depth: glium::Depth {
+                test: if reversed_z { glium::draw_parameters::DepthTest::IfMoreOrEqual } else { glium::draw_parameters::DepthTest::IfLessOrEqual },
+                write: false,
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        let indices = glium::index::NoIndices(glium::index::PrimitiveType::TrianglesList);
+        // where the far plane ends up after the divide
+        let far_ndc: f32 = if reversed_z { 0.0 } else { 1.0 };
+
+        match sky {
+            Sky::Color => return 0,
+            Sky::Gradient { zenith, horizon, ground } => {
+                target_frame.draw(&self.cube, indices, &self.gradient_program,
+                    &uniform! { view: view, perspective: perspective, far_ndc: far_ndc, zenith: *zenith, horizon: *horizon, ground: *ground },
+                    &params).unwrap();
+            },
+            Sky::Cubemap(cubemap) => {
+                let environment = Sampler::new(cubemap)
+                    .wrap_function(SamplerWrapFunction::Clamp)
+                    .minify_filter(MinifySamplerFilter::Linear)
+                    .magnify_filter(MagnifySamplerFilter::Linear);
+                target_frame.draw(&self.cube, indices, &self.cubemap_program,
+                    &uniform! { view: view, perspective: perspective, far_ndc: far_ndc, environment: environment },
+                    &params).unwrap();
+            },
+            Sky::Equirectangular(texture) => {
+                let environment = Sampler::new(texture)
+                    .minify_filter(MinifySamplerFilter::Linear)
+                    .magnify_filter(MagnifySamplerFilter::Linear);
+                target_frame.draw(&self.cube, indices, &self.equirect_program,
+                    &uniform! { view: view, perspective: perspective, far_ndc: far_ndc, environment: environment },
+                    &params).unwrap();
+            },
+        }
+        self.cube.len() / 3
+    }
+}
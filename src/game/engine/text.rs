@@ -0,0 +1,186 @@
+use std::collections::HashMap;
+use glium::backend::Facade;
+use glium::texture::{Texture2d,RawImage2d,ClientFormat,UncompressedFloatFormat,MipmapsOption};
+use rusttype::{Font,Scale,point};
+
+const ATLAS_SIZE: u32 = 512;
+// the top left corner of the atlas is solid, untextured quads sample it
+const WHITE_SIZE: u32 = 2;
+
+#[derive(Copy, Clone, Debug)]
+pub struct GlyphInfo {
+    // atlas rect in 0..1
+    pub uv_min: [f32; 2],
+    pub uv_max: [f32; 2],
+    // pixel offset of the rect from the pen position on the baseline
+    pub offset: [f32; 2],
+    pub size: [f32; 2],
+    pub advance: f32,
+}
+
+// glyphs are rasterized at a fixed pixel size into a single channel texture the first time they are
+// drawn, packed in rows. printable ascii is there from the start
+pub struct FontAtlas {
+    pub texture: Texture2d,
+    pub pixel_size: f32,
+    pub ascent: f32,
+    pub line_height: f32,
+    pub white_uv: [f32; 2],
+    font: Font<'static>,
+    glyphs: HashMap<char, GlyphInfo>,
+    // where the next glyph goes and the height of the row it is in
+    next: (u32, u32),
+    row_height: u32,
+    full: bool,
+}
+
+impl FontAtlas {
+
+    pub fn load<F: Facade>(display: &F, path: &str, pixel_size: f32) -> FontAtlas {
+        let data = std::fs::read(path).unwrap();
+        let font = Font::from_bytes(data).unwrap();
+        let v_metrics = font.v_metrics(Scale::uniform(pixel_size));
+
+        let mut pixels = vec![0u8; (ATLAS_SIZE * ATLAS_SIZE) as usize];
+        for py in 0..WHITE_SIZE {
+            for px in 0..WHITE_SIZE {
+                pixels[(py * ATLAS_SIZE + px) as usize] = 255;
+            }
+        }
+        // rows are uploaded top first, so v grows downwards in the atlas like the pixel rows
+        let image = RawImage2d{
+            data: std::borrow::Cow::Owned(pixels),
+            width: ATLAS_SIZE,
+            height: ATLAS_SIZE,
+            format: ClientFormat::U8,
+        };
+
+        let mut atlas = FontAtlas{
+            texture: Texture2d::with_format(display, image, UncompressedFloatFormat::U8, MipmapsOption::NoMipmap).unwrap(),
+            pixel_size,
+            ascent: v_metrics.ascent,
+            line_height: v_metrics.ascent - v_metrics.descent + v_metrics.line_gap,
+            white_uv: [0.5 / ATLAS_SIZE as f32, 0.5 / ATLAS_SIZE as f32],
+            font,
+            glyphs: HashMap::new(),
+            next: (WHITE_SIZE + 1, 0),
+            row_height: WHITE_SIZE,
+            full: false,
+        };
+        for c in (32u8..127).map(|c| c as char) {
+            atlas.glyph(c);
+        }
+        atlas
+    }
+
+    // characters the font does not have, or that no longer fit into the atlas, are drawn as '?'
+    pub fn glyph(&mut self, c: char) -> GlyphInfo {
+        if let Some(info) = self.glyphs.get(&c) {
+            return *info;
+        }
+        match self.rasterize(c) {
+            Some(info) => {
+                self.glyphs.insert(c, info);
+                info
+            },
+            None if c != '?' => self.glyph('?'),
+            None => GlyphInfo{ uv_min: [0.0, 0.0], uv_max: [0.0, 0.0], offset: [0.0, 0.0], size: [0.0, 0.0], advance: 0.0 },
+        }
+    }
+
+    // places the glyph behind the last one and writes it into the texture
+    fn rasterize(&mut self, c: char) -> Option<GlyphInfo> {
+        let glyph = self.font.glyph(c);
+        // id 0 is the font's missing glyph box
+        if glyph.id().0 == 0 {
+            return None;
+        }
+        let glyph = glyph.scaled(Scale::uniform(self.pixel_size)).positioned(point(0.0, 0.0));
+        let advance = glyph.unpositioned().h_metrics().advance_width;
+        let bb = match glyph.pixel_bounding_box() {
+            Some(bb) => bb,
+            None => return Some(GlyphInfo{ uv_min: [0.0, 0.0], uv_max: [0.0, 0.0], offset: [0.0, 0.0], size: [0.0, 0.0], advance }),
+        };
+        let (w, h) = (bb.width() as u32, bb.height() as u32);
+
+        let (mut x, mut y) = self.next;
+        if x + w + 1 > ATLAS_SIZE {
+            x = 0;
+            y += self.row_height + 1;
+            self.row_height = 0;
+        }
+        if y + h > ATLAS_SIZE {
+            if !self.full {
+                println!("the font atlas is full, new characters are drawn as '?'");
+                self.full = true;
+            }
+            return None;
+        }
+        self.next = (x + w + 1, y);
+        self.row_height = self.row_height.max(h);
+
+        let mut pixels = vec![0u8; (w * h) as usize];
+        glyph.draw(|px, py, v| pixels[(py * w + px) as usize] = (v * 255.0) as u8);
+        self.texture.write(glium::Rect{ left: x, bottom: y, width: w, height: h }, RawImage2d{
+            data: std::borrow::Cow::Owned(pixels),
+            width: w,
+            height: h,
+            format: ClientFormat::U8,
+        });
+
+        let size = ATLAS_SIZE as f32;
+        Some(GlyphInfo{
+            uv_min: [x as f32 / size, y as f32 / size],
+            uv_max: [(x + w) as f32 / size, (y + h) as f32 / size],
+            offset: [bb.min.x as f32, bb.min.y as f32],
+            size: [w as f32, h as f32],
+            advance,
+        })
+    }
+
+    // without rasterizing, the advance of a character the font does not have is the one of '?'
+    fn advance(&self, c: char) -> f32 {
+        let scale = Scale::uniform(self.pixel_size);
+        let glyph = self.font.glyph(c);
+        let glyph = if glyph.id().0 == 0 { self.font.glyph('?') } else { glyph };
+        glyph.scaled(scale).h_metrics().advance_width
+    }
+
+    // width of the longest line and total height in pixels at the given scale
+    pub fn measure(&self, text: &str, scale: f32) -> (f32, f32) {
+        let mut width: f32 = 0.0;
+        let mut lines = 0;
+        for line in text.lines() {
+            width = width.max(line.chars().map(|c| self.advance(c)).sum::<f32>());
+            lines += 1;
+        }
+        (width * scale, lines.max(1) as f32 * self.line_height * scale)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::my_game_engine::my_game_logic::my_egl;
+
+    #[test]
+    fn glyphs_outside_ascii_are_rasterized_when_used() {
+        let Some(context) = my_egl::test_context((16, 16)) else { return };
+        let mut atlas = FontAtlas::load(&context, "./res/fonts/DejaVuSansMono.ttf", 16.0);
+        let question = atlas.glyph('?');
+
+        for &c in ['é', 'Ж', 'λ'].iter() {
+            let g = atlas.glyph(c);
+            assert!(g.size[0] > 0.0 && g.size[1] > 0.0, "{} has no pixels", c);
+            assert!(g.uv_min != question.uv_min, "{} is drawn as '?'", c);
+            // cached after the first time
+            assert_eq!(atlas.glyph(c).uv_min, g.uv_min);
+
+            let pixels: Vec<Vec<(u8, u8, u8, u8)>> = atlas.texture.read();
+            let (x0, y0) = ((g.uv_min[0] * ATLAS_SIZE as f32) as usize, (g.uv_min[1] * ATLAS_SIZE as f32) as usize);
+            let covered = (y0..y0 + g.size[1] as usize).any(|y| (x0..x0 + g.size[0] as usize).any(|x| pixels[y][x].0 > 0));
+            assert!(covered, "{} was not written into the atlas", c);
+        }
+        assert_eq!(atlas.measure("é", 1.0).0, atlas.glyph('é').advance);
+    }
+}
@@ -0,0 +1,246 @@
+use glutin::{ElementState,VirtualKeyCode};
+use clipboard::{ClipboardContext,ClipboardProvider};
+
+#[derive(Copy, Clone, Debug, Default, PartialEq)]
+pub struct Modifiers {
+    pub shift: bool,
+    pub ctrl: bool,
+    pub alt: bool,
+    // the windows or command key
+    pub logo: bool,
+}
+
+impl From<glutin::ModifiersState> for Modifiers {
+    fn from(state: glutin::ModifiersState) -> Modifiers {
+        Modifiers{ shift: state.shift, ctrl: state.ctrl, alt: state.alt, logo: state.logo }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum TextEvent {
+    // typed, or committed by an input method. winit has no composition events, only the result arrives
+    Char(char),
+    Backspace,
+    Delete,
+    Enter,
+    Escape,
+    Tab,
+    Left,
+    Right,
+    Up,
+    Down,
+    Home,
+    End,
+    // the clipboard on ctrl+v, in one line
+    Paste(String),
+}
+
+const KEYS: &[(&str, TextEvent)] = &[
+    ("backspace", TextEvent::Backspace), ("delete", TextEvent::Delete), ("enter", TextEvent::Enter),
+    ("escape", TextEvent::Escape), ("tab", TextEvent::Tab), ("left", TextEvent::Left), ("right", TextEvent::Right),
+    ("up", TextEvent::Up), ("down", TextEvent::Down), ("home", TextEvent::Home), ("end", TextEvent::End),
+];
+
+impl TextEvent {
+    // char 97, paste 104 105, backspace. chars as code points so spaces and newlines survive
+    pub fn parse(words: &[&str]) -> Option<TextEvent> {
+        let chars = |words: &[&str]| words.iter().map(|w| w.parse::<u32>().ok().and_then(std::char::from_u32)).collect::<Option<String>>();
+        match words {
+            ["char", c] => chars(&[*c]).and_then(|s| s.chars().next()).map(TextEvent::Char),
+            ["paste", rest @ ..] => chars(rest).map(TextEvent::Paste),
+            [name] => KEYS.iter().find(|(n, _)| n == name).map(|(_, e)| e.clone()),
+            _ => None,
+        }
+    }
+
+    pub fn describe(&self) -> String {
+        match self {
+            TextEvent::Char(c) => format!("char {}", *c as u32),
+            TextEvent::Paste(text) => std::iter::once("paste".to_string()).chain(text.chars().map(|c| (c as u32).to_string())).collect::<Vec<String>>().join(" "),
+            key => KEYS.iter().find(|(_, e)| e == key).map(|(n, _)| n.to_string()).unwrap(),
+        }
+    }
+}
+
+// turns the window events into text for whatever has the keyboard focus. unlike the action
+// bindings key repeat counts here. the text events go through the input map to be recorded
+// and reach the console and the ui on the tick they came in
+pub struct TextInput {
+    pub modifiers: Modifiers,
+}
+
+impl Default for TextInput {
+    fn default() -> TextInput {
+        TextInput::new()
+    }
+}
+
+impl TextInput {
+
+    pub fn new() -> TextInput {
+        TextInput{
+            modifiers: Modifiers::default(),
+        }
+    }
+
+    pub fn handle_event(&mut self, event: &glutin::WindowEvent) -> Option<TextEvent> {
+        match event {
+            // control characters come with the keys below
+            glutin::WindowEvent::ReceivedCharacter(c) if !c.is_control() => {
+                return Some(TextEvent::Char(*c));
+            },
+            glutin::WindowEvent::KeyboardInput { input, .. } => {
+                self.modifiers = Modifiers::from(input.modifiers);
+                if input.state != ElementState::Pressed {
+                    return None;
+                }
+                return match input.virtual_keycode {
+                    Some(VirtualKeyCode::Back) => Some(TextEvent::Backspace),
+                    Some(VirtualKeyCode::Delete) => Some(TextEvent::Delete),
+                    Some(VirtualKeyCode::Return) | Some(VirtualKeyCode::NumpadEnter) => Some(TextEvent::Enter),
+                    Some(VirtualKeyCode::Escape) => Some(TextEvent::Escape),
+                    Some(VirtualKeyCode::Tab) => Some(TextEvent::Tab),
+                    Some(VirtualKeyCode::Left) => Some(TextEvent::Left),
+                    Some(VirtualKeyCode::Right) => Some(TextEvent::Right),
+                    Some(VirtualKeyCode::Up) => Some(TextEvent::Up),
+                    Some(VirtualKeyCode::Down) => Some(TextEvent::Down),
+                    Some(VirtualKeyCode::Home) => Some(TextEvent::Home),
+                    Some(VirtualKeyCode::End) => Some(TextEvent::End),
+                    Some(VirtualKeyCode::V) if self.modifiers.ctrl || self.modifiers.logo => paste(),
+                    _ => None,
+                };
+            },
+            glutin::WindowEvent::MouseInput { modifiers, .. } => self.modifiers = Modifiers::from(*modifiers),
+            glutin::WindowEvent::Focused(false) => self.modifiers = Modifiers::default(),
+            _ => (),
+        }
+        None
+    }
+}
+
+fn paste() -> Option<TextEvent> {
+    let mut clipboard: ClipboardContext = ClipboardProvider::new().ok()?;
+    let text = clipboard.get_contents().ok()?;
+    Some(TextEvent::Paste(single_line(&text)))
+}
+
+// line breaks and tabs become spaces, other control characters are dropped
+fn single_line(text: &str) -> String {
+    text.chars().map(|c| if c == '\n' || c == '\t' { ' ' } else { c }).filter(|c| !c.is_control()).collect()
+}
+
+// byte offset of the char at index i, the end for i past the last char
+fn byte_offset(text: &str, i: usize) -> usize {
+    text.char_indices().nth(i).map_or(text.len(), |(b, _)| b)
+}
+
+// edits a single line with the caret as a char index, true if the text changed.
+// enter, escape, tab, up and down are left to the caller
+pub fn edit(text: &mut String, caret: &mut usize, event: &TextEvent) -> bool {
+    let length = text.chars().count();
+    *caret = (*caret).min(length);
+    match event {
+        TextEvent::Char(c) => {
+            text.insert(byte_offset(text, *caret), *c);
+            *caret += 1;
+            true
+        },
+        TextEvent::Paste(pasted) => {
+            text.insert_str(byte_offset(text, *caret), pasted);
+            *caret += pasted.chars().count();
+            !pasted.is_empty()
+        },
+        TextEvent::Backspace if *caret > 0 => {
+            *caret -= 1;
+            text.remove(byte_offset(text, *caret));
+            true
+        },
+        TextEvent::Delete if *caret < length => {
+            text.remove(byte_offset(text, *caret));
+            true
+        },
+        TextEvent::Left => {
+            *caret = caret.saturating_sub(1);
+            false
+        },
+        TextEvent::Right => {
+            *caret = (*caret + 1).min(length);
+            false
+        },
+        TextEvent::Home => {
+            *caret = 0;
+            false
+        },
+        TextEvent::End => {
+            *caret = length;
+            false
+        },
+        _ => false,
+    }
+}
+
+// the text with the caret drawn in as _
+pub fn with_caret(text: &str, caret: usize) -> String {
+    let at = byte_offset(text, caret);
+    format!("{}_{}", &text[..at], &text[at..])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn type_in(text: &mut String, caret: &mut usize, events: &[TextEvent]) {
+        for event in events {
+            edit(text, caret, event);
+        }
+    }
+
+    #[test]
+    fn the_caret_counts_chars_not_bytes() {
+        let (mut text, mut caret) = (String::new(), 0);
+        type_in(&mut text, &mut caret, &[TextEvent::Char('é'), TextEvent::Char('日'), TextEvent::Char('x')]);
+        assert_eq!((text.as_str(), caret), ("é日x", 3));
+
+        type_in(&mut text, &mut caret, &[TextEvent::Left, TextEvent::Backspace]);
+        assert_eq!((text.as_str(), caret), ("éx", 1));
+        assert_eq!(with_caret(&text, caret), "é_x");
+
+        type_in(&mut text, &mut caret, &[TextEvent::Char('ß'), TextEvent::Home, TextEvent::Delete]);
+        assert_eq!((text.as_str(), caret), ("ßx", 0));
+        assert_eq!(with_caret(&text, 5), "ßx_");
+    }
+
+    #[test]
+    fn arrows_and_deletes_stop_at_the_ends() {
+        let (mut text, mut caret) = ("ab".to_string(), 0);
+        assert!(!edit(&mut text, &mut caret, &TextEvent::Left));
+        assert!(!edit(&mut text, &mut caret, &TextEvent::Backspace));
+        assert_eq!((text.as_str(), caret), ("ab", 0));
+
+        type_in(&mut text, &mut caret, &[TextEvent::Right, TextEvent::Right, TextEvent::Right]);
+        assert_eq!(caret, 2);
+        assert!(!edit(&mut text, &mut caret, &TextEvent::Delete));
+        assert_eq!((text.as_str(), caret), ("ab", 2));
+
+        // a caret left behind by a shorter text is moved back to its end first
+        let mut caret = 10;
+        assert!(edit(&mut text, &mut caret, &TextEvent::Backspace));
+        assert_eq!((text.as_str(), caret), ("a", 1));
+    }
+
+    #[test]
+    fn pasted_text_is_put_on_one_line() {
+        let pasted = single_line("one\ntwo\tthree\r\n");
+        assert_eq!(pasted, "one two three ");
+
+        let (mut text, mut caret) = ("[]".to_string(), 1);
+        assert!(edit(&mut text, &mut caret, &TextEvent::Paste(pasted.clone())));
+        assert_eq!((text.as_str(), caret), ("[one two three ]", 15));
+
+        // and survives being recorded
+        let event = TextEvent::Paste(pasted);
+        let described = event.describe();
+        let words: Vec<&str> = described.split(' ').collect();
+        assert_eq!(TextEvent::parse(&words), Some(event));
+    }
+}
@@ -0,0 +1,465 @@
+use std::collections::HashMap;
+use std::collections::hash_map::DefaultHasher;
+use std::hash::{Hash,Hasher};
+use crate::my_game_engine::my_game_logic::my_overlay::Overlay;
+use crate::my_game_engine::my_game_logic::my_text_input::{self,TextEvent};
+
+const PADDING: f32 = 6.0;
+const ROW_HEIGHT: f32 = 24.0;
+const TITLE_HEIGHT: f32 = 22.0;
+
+const WINDOW_COLOR: [f32; 4] = [0.1, 0.1, 0.12, 0.85];
+const TITLE_COLOR: [f32; 4] = [0.2, 0.25, 0.4, 1.0];
+const WIDGET_COLOR: [f32; 4] = [0.25, 0.25, 0.3, 1.0];
+const HOT_COLOR: [f32; 4] = [0.35, 0.35, 0.45, 1.0];
+const ACTIVE_COLOR: [f32; 4] = [0.45, 0.5, 0.7, 1.0];
+const TEXT_COLOR: [f32; 4] = [1.0, 1.0, 1.0, 1.0];
+
+#[derive(Copy, Clone, Debug)]
+struct Rect {
+    x: f32,
+    y: f32,
+    w: f32,
+    h: f32,
+}
+
+impl Rect {
+    fn contains(&self, p: (f32, f32)) -> bool {
+        p.0 >= self.x && p.0 < self.x + self.w && p.1 >= self.y && p.1 < self.y + self.h
+    }
+}
+
+// laid out on a tick and drawn with the next frame. text is placed then, that needs the font
+enum UiShape {
+    Quad(Rect, [f32; 4]),
+    // starts at x, centered in the height of the row
+    Text { x: f32, row: Rect, text: String },
+    Centered { row: Rect, text: String },
+    // the label, then the field in the rest of the row
+    Field { row: Rect, label: String, text: String, color: [f32; 4] },
+}
+
+// window state that has to survive between ticks
+struct UiWindow {
+    x: f32,
+    y: f32,
+    w: f32,
+    // height of the last tick, the background is laid out before the content is known
+    h: f32,
+}
+
+// the mouse of the current fixed update
+#[derive(Default)]
+struct UiInput {
+    // physical pixels from the top left corner
+    cursor: (f32, f32),
+    mouse_down: bool,
+    mouse_pressed: bool,
+    mouse_released: bool,
+}
+
+// immediate mode widgets, run on the fixed update so a replay makes the same edits. state
+// that outlives a tick is keyed by label. only interactive while enabled, the game disables it
+// while the cursor is grabbed
+pub struct Ui {
+    pub enabled: bool,
+    pub hidpi_factor: f64,
+    input: UiInput,
+    active: Option<u64>,
+    focused: Option<u64>,
+    // char index into the focused text field
+    caret: usize,
+    drag_offset: (f32, f32),
+    windows: HashMap<u64, UiWindow>,
+    shapes: Vec<UiShape>,
+}
+
+impl Default for Ui {
+    fn default() -> Ui {
+        Ui::new()
+    }
+}
+
+impl Ui {
+
+    pub fn new() -> Ui {
+        Ui{
+            enabled: false,
+            hidpi_factor: 1.0,
+            input: UiInput::default(),
+            active: None,
+            focused: None,
+            caret: 0,
+            drag_offset: (0.0, 0.0),
+            windows: HashMap::new(),
+            shapes: Vec::new(),
+        }
+    }
+
+    pub fn handle_event(&mut self, event: &glutin::WindowEvent) {
+        if let glutin::WindowEvent::HiDpiFactorChanged(factor) = event {
+            self.hidpi_factor = *factor;
+        }
+    }
+
+    // the left button and the cursor in physical pixels, fed from the input map on every
+    // fixed update before the widgets of the tick run
+    pub fn update_mouse(&mut self, cursor: (f32, f32), down: bool, pressed: bool, released: bool) {
+        self.input.cursor = cursor;
+        // the game has the cursor
+        if !self.enabled {
+            return;
+        }
+        self.input.mouse_down = down;
+        self.input.mouse_pressed = pressed;
+        self.input.mouse_released = released;
+    }
+
+    // a text field has keyboard focus, key bindings should not react
+    pub fn has_focus(&self) -> bool {
+        self.enabled && self.focused.is_some()
+    }
+
+    // the cursor is over a window or a widget is held, clicks should not reach the game
+    pub fn wants_mouse(&self) -> bool {
+        let cursor = self.input.cursor;
+        self.enabled && (self.active.is_some() || self.windows.values().any(|w| {
+            Rect { x: w.x, y: w.y, w: w.w, h: w.h }.contains(cursor)
+        }))
+    }
+
+    // text is what the focused text field types on this tick
+    pub fn begin<'a>(&'a mut self, text: &'a [TextEvent]) -> UiFrame<'a> {
+        if !self.enabled {
+            self.active = None;
+            self.focused = None;
+        }
+        self.shapes.clear();
+        UiFrame{
+            ui: self,
+            text,
+            window: None,
+            x: PADDING,
+            y: PADDING,
+            width: 0.0,
+        }
+    }
+
+    // the widgets of the last tick
+    pub fn draw(&self, overlay: &mut Overlay) {
+        let text_y = |overlay: &Overlay, row: &Rect| row.y + (row.h - overlay.font.line_height) * 0.5;
+        for shape in &self.shapes {
+            match shape {
+                UiShape::Quad(rect, color) => overlay.quad(rect.x, rect.y, rect.w, rect.h, *color),
+                UiShape::Text { x, row, text } => {
+                    let y = text_y(overlay, row);
+                    overlay.text(*x, y, text, 1.0, TEXT_COLOR);
+                },
+                UiShape::Centered { row, text } => {
+                    let (w, _) = overlay.font.measure(text, 1.0);
+                    let y = text_y(overlay, row);
+                    overlay.text(row.x + (row.w - w) * 0.5, y, text, 1.0, TEXT_COLOR);
+                },
+                UiShape::Field { row, label, text, color } => {
+                    let (lw, _) = overlay.font.measure(label, 1.0);
+                    let y = text_y(overlay, row);
+                    overlay.text(row.x, y, label, 1.0, TEXT_COLOR);
+                    let field = Rect { x: row.x + lw + PADDING, y: row.y, w: row.w - lw - PADDING, h: row.h };
+                    overlay.quad(field.x, field.y, field.w, field.h, *color);
+                    overlay.text(field.x + 4.0, y, text, 1.0, TEXT_COLOR);
+                },
+            }
+        }
+    }
+
+    fn id(scope: Option<u64>, label: &str) -> u64 {
+        let mut hasher = DefaultHasher::new();
+        scope.hash(&mut hasher);
+        label.hash(&mut hasher);
+        hasher.finish()
+    }
+}
+
+// widgets of one tick, the mouse edges of the tick are consumed when it is dropped
+pub struct UiFrame<'a> {
+    ui: &'a mut Ui,
+    text: &'a [TextEvent],
+    window: Option<u64>,
+    // layout cursor inside the current window
+    x: f32,
+    y: f32,
+    width: f32,
+}
+
+impl<'a> UiFrame<'a> {
+
+    fn hovered(&self, rect: &Rect) -> bool {
+        self.ui.enabled && rect.contains(self.ui.input.cursor)
+    }
+
+    // makes the widget active on a press over it, true while it stays active
+    fn interact(&mut self, id: u64, rect: &Rect) -> bool {
+        if self.hovered(rect) && self.ui.input.mouse_pressed {
+            self.ui.active = Some(id);
+        }
+        self.ui.active == Some(id)
+    }
+
+    fn clicked(&mut self, id: u64, rect: &Rect) -> bool {
+        self.interact(id, rect) && self.ui.input.mouse_released && self.hovered(rect)
+    }
+
+    fn widget_color(&self, id: u64, rect: &Rect) -> [f32; 4] {
+        if self.ui.active == Some(id) || self.ui.focused == Some(id) {
+            ACTIVE_COLOR
+        } else if self.hovered(rect) {
+            HOT_COLOR
+        } else {
+            WIDGET_COLOR
+        }
+    }
+
+    fn next_row(&mut self) -> Rect {
+        let rect = Rect { x: self.x, y: self.y, w: self.width, h: ROW_HEIGHT - 4.0 };
+        self.y += ROW_HEIGHT;
+        rect
+    }
+
+    fn quad(&mut self, rect: Rect, color: [f32; 4]) {
+        self.ui.shapes.push(UiShape::Quad(rect, color));
+    }
+
+    fn text(&mut self, x: f32, row: Rect, text: &str) {
+        self.ui.shapes.push(UiShape::Text { x, row, text: text.to_string() });
+    }
+
+    // a movable panel, the title bar drags it. widgets until end_window are laid out inside
+    pub fn begin_window(&mut self, title: &str, x: f32, y: f32, width: f32) {
+        let id = Ui::id(None, title);
+        let (wx, wy, wh) = {
+            let window = self.ui.windows.entry(id).or_insert(UiWindow { x, y, w: width, h: TITLE_HEIGHT });
+            window.w = width;
+            (window.x, window.y, window.h)
+        };
+
+        let title_bar = Rect { x: wx, y: wy, w: width, h: TITLE_HEIGHT };
+        if self.interact(id, &title_bar) {
+            if self.ui.input.mouse_pressed {
+                self.ui.drag_offset = (self.ui.input.cursor.0 - wx, self.ui.input.cursor.1 - wy);
+            }
+            let window = self.ui.windows.get_mut(&id).unwrap();
+            window.x = self.ui.input.cursor.0 - self.ui.drag_offset.0;
+            window.y = self.ui.input.cursor.1 - self.ui.drag_offset.1;
+        }
+        let (wx, wy) = {
+            let window = &self.ui.windows[&id];
+            (window.x, window.y)
+        };
+
+        let title_bar = Rect { x: wx, y: wy, w: width, h: TITLE_HEIGHT };
+        self.quad(Rect { x: wx, y: wy, w: width, h: wh }, WINDOW_COLOR);
+        self.quad(title_bar, TITLE_COLOR);
+        self.text(wx + PADDING, title_bar, title);
+
+        self.window = Some(id);
+        self.x = wx + PADDING;
+        self.y = wy + TITLE_HEIGHT + PADDING;
+        self.width = width - 2.0 * PADDING;
+    }
+
+    pub fn end_window(&mut self) {
+        if let Some(id) = self.window.take() {
+            let y = self.y;
+            if let Some(window) = self.ui.windows.get_mut(&id) {
+                window.h = y - window.y + PADDING;
+            }
+        }
+    }
+
+    pub fn label(&mut self, text: &str) {
+        let rect = self.next_row();
+        self.text(rect.x, rect, text);
+    }
+
+    pub fn button(&mut self, label: &str) -> bool {
+        let id = Ui::id(self.window, label);
+        let rect = self.next_row();
+        let clicked = self.clicked(id, &rect);
+
+        let color = self.widget_color(id, &rect);
+        self.quad(rect, color);
+        self.ui.shapes.push(UiShape::Centered { row: rect, text: label.to_string() });
+        clicked
+    }
+
+    // true if the value changed this tick
+    pub fn checkbox(&mut self, label: &str, value: &mut bool) -> bool {
+        let id = Ui::id(self.window, label);
+        let rect = self.next_row();
+        let changed = self.clicked(id, &rect);
+        if changed {
+            *value = !*value;
+        }
+
+        let bx = Rect { x: rect.x, y: rect.y, w: rect.h, h: rect.h };
+        let color = self.widget_color(id, &rect);
+        self.quad(bx, color);
+        if *value {
+            self.quad(Rect { x: bx.x + 4.0, y: bx.y + 4.0, w: bx.w - 8.0, h: bx.h - 8.0 }, TEXT_COLOR);
+        }
+        self.text(rect.x + rect.h + PADDING, rect, label);
+        changed
+    }
+
+    pub fn slider(&mut self, label: &str, value: &mut f32, min: f32, max: f32) -> bool {
+        let id = Ui::id(self.window, label);
+        let rect = self.next_row();
+        let mut changed = false;
+        // pressed as well, a press and release on the same tick still moves it
+        if self.interact(id, &rect) && (self.ui.input.mouse_down || self.ui.input.mouse_pressed) {
+            let t = ((self.ui.input.cursor.0 - rect.x) / rect.w).clamp(0.0, 1.0);
+            let new_value = min + t * (max - min);
+            changed = new_value != *value;
+            *value = new_value;
+        }
+
+        let color = self.widget_color(id, &rect);
+        self.quad(rect, WIDGET_COLOR);
+        let t = if max > min { ((*value - min) / (max - min)).clamp(0.0, 1.0) } else { 0.0 };
+        self.quad(Rect { w: rect.w * t, ..rect }, color);
+        self.text(rect.x + PADDING, rect, &format!("{} {:.2}", label, value));
+        changed
+    }
+
+    // takes the keyboard on click until enter, escape or a click elsewhere
+    pub fn text_field(&mut self, label: &str, text: &mut String) -> bool {
+        let id = Ui::id(self.window, label);
+        let rect = self.next_row();
+        if self.clicked(id, &rect) && self.ui.focused != Some(id) {
+            self.ui.focused = Some(id);
+            self.ui.caret = text.chars().count();
+        } else if self.ui.focused == Some(id) && self.ui.input.mouse_pressed && !self.hovered(&rect) {
+            self.ui.focused = None;
+        }
+
+        let mut changed = false;
+        if self.ui.focused == Some(id) {
+            for event in self.text {
+                match event {
+                    TextEvent::Enter | TextEvent::Escape => self.ui.focused = None,
+                    _ if self.ui.focused == Some(id) => changed |= my_text_input::edit(text, &mut self.ui.caret, event),
+                    _ => (),
+                }
+            }
+        }
+
+        let color = self.widget_color(id, &rect);
+        let shown = if self.ui.focused == Some(id) { my_text_input::with_caret(text, self.ui.caret) } else { text.clone() };
+        self.ui.shapes.push(UiShape::Field { row: rect, label: label.to_string(), text: shown, color });
+        changed
+    }
+}
+
+impl<'a> Drop for UiFrame<'a> {
+    fn drop(&mut self) {
+        self.end_window();
+        let input = &mut self.ui.input;
+        if input.mouse_released {
+            self.ui.active = None;
+        }
+        input.mouse_pressed = false;
+        input.mouse_released = false;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // a window at the origin, its first row spans 6..194 and 28..48, the second 52..72
+    fn tick<R>(ui: &mut Ui, cursor: (f32, f32), (down, pressed, released): (bool, bool, bool), text: &[TextEvent], widgets: impl FnOnce(&mut UiFrame) -> R) -> R {
+        ui.update_mouse(cursor, down, pressed, released);
+        let mut frame = ui.begin(text);
+        frame.begin_window("window", 0.0, 0.0, 200.0);
+        widgets(&mut frame)
+    }
+
+    const UP: (bool, bool, bool) = (false, false, false);
+    const PRESS: (bool, bool, bool) = (true, true, false);
+    const HELD: (bool, bool, bool) = (true, false, false);
+    const RELEASE: (bool, bool, bool) = (false, false, true);
+    const CLICK: (bool, bool, bool) = (false, true, true);
+
+    fn enabled() -> Ui {
+        let mut ui = Ui::new();
+        ui.enabled = true;
+        ui
+    }
+
+    #[test]
+    fn a_click_within_one_tick_presses_the_button() {
+        let mut ui = enabled();
+        assert!(tick(&mut ui, (100.0, 38.0), CLICK, &[], |f| f.button("ok")));
+        assert!(!tick(&mut ui, (100.0, 38.0), UP, &[], |f| f.button("ok")));
+        assert!(ui.active.is_none());
+    }
+
+    #[test]
+    fn releasing_outside_the_button_does_not_click() {
+        let mut ui = enabled();
+        assert!(!tick(&mut ui, (100.0, 38.0), PRESS, &[], |f| f.button("ok")));
+        assert!(ui.wants_mouse());
+        assert!(!tick(&mut ui, (300.0, 38.0), RELEASE, &[], |f| f.button("ok")));
+        assert!(ui.active.is_none());
+        // and a release over it without the press is no click either
+        assert!(!tick(&mut ui, (100.0, 38.0), RELEASE, &[], |f| f.button("ok")));
+    }
+
+    fn slide(ui: &mut Ui, value: &mut f32, x: f32, mouse: (bool, bool, bool)) -> bool {
+        tick(ui, (x, 38.0), mouse, &[], |f| f.slider("value", value, 0.0, 4.0))
+    }
+
+    #[test]
+    fn the_slider_follows_a_drag_and_a_click() {
+        let mut ui = enabled();
+        let mut value = 0.0;
+        assert!(slide(&mut ui, &mut value, 53.0, PRESS));
+        assert!((value - 1.0).abs() < 1e-5, "{}", value);
+        assert!(slide(&mut ui, &mut value, 147.0, HELD));
+        assert!((value - 3.0).abs() < 1e-5, "{}", value);
+        // dragged past the end it stays at the maximum, still held outside the row
+        assert!(slide(&mut ui, &mut value, 400.0, HELD));
+        assert!(!slide(&mut ui, &mut value, 400.0, RELEASE));
+        assert_eq!(value, 4.0);
+        assert!(!slide(&mut ui, &mut value, 53.0, UP));
+        // pressed and released on the same tick
+        assert!(slide(&mut ui, &mut value, 100.0, CLICK));
+        assert!((value - 2.0).abs() < 1e-5, "{}", value);
+    }
+
+    #[test]
+    fn text_fields_take_the_keyboard_until_a_click_elsewhere() {
+        let mut ui = enabled();
+        let mut name = String::from("ab");
+        let typed = [TextEvent::Char('c'), TextEvent::Left, TextEvent::Backspace];
+        // not focused yet, the text goes nowhere
+        assert!(!tick(&mut ui, (100.0, 38.0), UP, &typed, |f| f.text_field("name", &mut name)));
+        assert!(!ui.has_focus());
+        tick(&mut ui, (100.0, 38.0), CLICK, &[], |f| f.text_field("name", &mut name));
+        assert!(ui.has_focus());
+        assert!(tick(&mut ui, (100.0, 38.0), UP, &typed, |f| f.text_field("name", &mut name)));
+        assert_eq!(name, "ac");
+        tick(&mut ui, (300.0, 300.0), PRESS, &[], |f| f.text_field("name", &mut name));
+        assert!(!ui.has_focus());
+        assert!(!tick(&mut ui, (300.0, 300.0), RELEASE, &typed, |f| f.text_field("name", &mut name)));
+        assert_eq!(name, "ac");
+
+        // enter gives the keyboard back too, and a disabled ui never has it
+        tick(&mut ui, (100.0, 38.0), CLICK, &[], |f| f.text_field("name", &mut name));
+        tick(&mut ui, (100.0, 38.0), UP, &[TextEvent::Enter], |f| f.text_field("name", &mut name));
+        assert!(!ui.has_focus());
+        tick(&mut ui, (100.0, 38.0), CLICK, &[], |f| f.text_field("name", &mut name));
+        ui.enabled = false;
+        assert!(!ui.has_focus());
+    }
+}
@@ -5,19 +5,138 @@ pub mod my_renderer;
 #[path = "./engine/loaders.rs"]
 pub mod my_loaders;
 
+#[path = "./engine/bounds.rs"]
+pub mod my_bounds;
+
+#[path = "./engine/postprocess.rs"]
+pub mod my_postprocess;
+
+#[path = "./engine/capture.rs"]
+pub mod my_capture;
+
+#[path = "./engine/skybox.rs"]
+pub mod my_skybox;
+
+#[path = "./engine/debug_draw.rs"]
+pub mod my_debug_draw;
+
+#[path = "./engine/text.rs"]
+pub mod my_text;
+
+#[path = "./engine/overlay.rs"]
+pub mod my_overlay;
+
+#[path = "./engine/ui.rs"]
+pub mod my_ui;
+
+#[path = "./engine/shaders.rs"]
+pub mod my_shaders;
+
+#[path = "./engine/camera.rs"]
+pub mod my_camera;
+
+#[path = "./engine/picking.rs"]
+pub mod my_picking;
+
+#[path = "./engine/camera_path.rs"]
+pub mod my_camera_path;
+
+#[path = "./engine/input.rs"]
+pub mod my_input;
+
+#[path = "./engine/gamepad.rs"]
+pub mod my_gamepad;
+
+#[path = "./engine/random.rs"]
+pub mod my_random;
+
+#[path = "./engine/replay.rs"]
+pub mod my_replay;
+
+#[path = "./engine/text_input.rs"]
+pub mod my_text_input;
+
+#[path = "./engine/console.rs"]
+pub mod my_console;
+
+#[path = "./engine/egl.rs"]
+pub mod my_egl;
+
 use std::thread;
 use std::sync::mpsc;
-use cgmath::Matrix4;
-use std::sync::Arc;
-use  std::sync::atomic::{AtomicUsize,Ordering};
-use crate::my_game_engine::my_game_logic::my_renderer::{Renderer,Cam,ModelRst,MyVertex,StaticMesh,AnimatedMesh};
+use cgmath::{Matrix4,Vector3,InnerSpace};
+use std::rc::Rc;
+use crate::my_game_engine::my_game_logic::my_bounds::Bounds;
+use crate::my_game_engine::my_game_logic::my_skybox::Sky;
+use crate::my_game_engine::my_game_logic::my_postprocess::PostEffect;
+use crate::my_game_engine::my_game_logic::my_ui::Ui;
+use crate::my_game_engine::my_game_logic::my_camera::{CameraController,FreeFly};
+use crate::my_game_engine::my_game_logic::my_camera_path::{CameraPath,PathPlayer};
+use crate::my_game_engine::my_game_logic::my_input::{InputMap,Binding};
+use crate::my_game_engine::my_game_logic::my_gamepad::Gamepads;
+use crate::my_game_engine::my_game_logic::my_random::Rng;
+use crate::my_game_engine::my_game_logic::my_replay::{InputRecording,InputSession};
+use crate::my_game_engine::my_game_logic::my_text_input::TextInput;
+use crate::my_game_engine::my_game_logic::my_console::Console;
+use crate::my_game_engine::my_game_logic::my_bounds::Aabb;
+use crate::my_game_engine::my_game_logic::my_picking::{PickHit,Picked};
+use crate::my_game_engine::my_game_logic::my_renderer::{Renderer,RenderQueue,Material,Cam,Projection,ModelRst,MyVertex,StaticMesh,AnimatedMesh};
+
+// renderer settings changed on a tick, made with the next frame
+pub enum RendererChange {
+    Hud,
+    Culling,
+    Oit,
+    // the order of DebugDraw::toggle
+    Debug(usize),
+    Post(usize),
+    // of the tonemap pass at the index
+    Exposure(usize, f32),
+}
+
+// what the debug panel shows of the renderer, copied every frame. the panel runs on the tick
+// and its changes go back as RendererChange, so a replay makes them on the same ticks
+#[derive(Clone, Default)]
+pub struct RendererSettings {
+    pub hud: bool,
+    pub culling: bool,
+    pub oit: bool,
+    // bounds, skeletons, normals and grid
+    pub debug: [bool; 4],
+    // name, enabled and the exposure of tonemap passes
+    pub post: Vec<(&'static str, bool, Option<f32>)>,
+}
+
+impl RendererSettings {
+
+    pub fn of(renderer: &Renderer) -> RendererSettings {
+        let debug = &renderer.debug;
+        RendererSettings{
+            hud: renderer.hud.visible,
+            culling: renderer.culling,
+            oit: renderer.oit,
+            debug: [debug.show_bounds, debug.show_skeletons, debug.show_normals, debug.show_grid],
+            post: renderer.post.passes.iter().map(|pass| {
+                let exposure = match pass.effect { PostEffect::Tonemap { exposure } => Some(exposure), _ => None };
+                (pass.effect.name(), pass.enabled, exposure)
+            }).collect(),
+        }
+    }
+}
 
 pub struct CCGame{
     pub models:Vec<(ModelRst, StaticMesh)>,
     pub animated_models:Vec<(ModelRst, AnimatedMesh)>,
     pub textures:Vec<glium::texture::SrgbTexture2d>,
-    pub key_states:[glutin::ElementState;7],
-    pub toggle_key_states:[glutin::ElementState;1],
+    pub input: InputMap,
+    pub gamepads: Gamepads,
+    // turns window events into text, the console and the ui read it from the input map
+    pub text: TextInput,
+    pub console: Console,
+    // fixed updates since the start or since recording or replaying began
+    pub tick: u64,
+    pub rng: Rng,
+    pub session: InputSession,
     pub prev_cursor_pos:glutin::dpi::LogicalPosition,
     pub running: bool,
     pub resized : bool,
@@ -29,8 +148,29 @@ pub struct CCGame{
     pub cam:Cam,
     pub rx:mpsc::Receiver<(Vec<MyVertex>, Vec<u16>)>,
     pub tx:mpsc::Sender<(Vec<MyVertex>, Vec<u16>)>,
-    pub ready_in_que: Arc<AtomicUsize>,
-    pub mics_alive: f64
+    // started by load_in_background and not added to models yet
+    pub loading: usize,
+    pub mics_alive: f64,
+    pub renderer_changes: Vec<RendererChange>,
+    pub renderer_settings: RendererSettings,
+    pub freeze_frustum: bool,
+    pub screenshot_requested: bool,
+    pub screenshot_name: String,
+    pub recording_toggled: bool,
+    pub clear_color: (f32, f32, f32, f32),
+    pub sky: Sky,
+    pub ui: Ui,
+    pub camera: CameraController,
+    // the cursor of the tick the click came in, picked with the next frame
+    pub pick_at: Option<glutin::dpi::LogicalPosition>,
+    // the model last clicked on while the cursor is free
+    pub selected: Option<PickHit>
+}
+
+impl Default for CCGame {
+    fn default() -> CCGame {
+        CCGame::new()
+    }
 }
 
 impl CCGame{
@@ -49,12 +189,8 @@ impl CCGame{
             y: 0.0,
             z: 1.0,
         };
-        use cgmath::InnerSpace;
         let look_dir = look_dir.normalize();
 
-        let fv = cgmath::Rad(std::f32::consts::PI / 3.0);
-        let perspective: cgmath::Matrix4<f32> = cgmath::perspective(fv, (4 / 3) as f32, 0.1, 1024.0);
-
         let vt = Vec::new();
 
         let (tx, rx) = mpsc::channel();
@@ -63,8 +199,13 @@ impl CCGame{
             models: rst_v,
             animated_models: rst_av,
             textures: vt,
-            key_states: [glutin::ElementState::Released;7],
-            toggle_key_states: [glutin::ElementState::Released;1],
+            input: InputMap::load_or_defaults("./res/input.cfg"),
+            gamepads: Gamepads::new(),
+            text: TextInput::new(),
+            console: Console::new(),
+            tick: 0,
+            rng: Rng::new(my_random::seed_from_time()),
+            session: InputSession::Live,
             prev_cursor_pos: glutin::dpi::LogicalPosition {x:0.0,y:0.0},
             running : true,
             resized : false,
@@ -73,22 +214,27 @@ impl CCGame{
             window_size: glutin::dpi::LogicalSize {height:400.0,width:300.0},
             moved : false,
             window_position: glutin::dpi::LogicalPosition {x:0.0,y:0.0},
-            cam: Cam {
-                pos,
-                look_dir,
-                ha: std::f32::consts::PI,
-                va: std::f32::consts::PI,
-                perspective,
-                speed: 0.001
-            },
+            cam: Cam::new(pos, look_dir, Projection::default_perspective()),
             rx: (rx),
             tx: (tx),
-            ready_in_que: Arc::new(AtomicUsize::new(0)),
-            mics_alive: 0.0
+            loading: 0,
+            mics_alive: 0.0,
+            renderer_changes: Vec::new(),
+            renderer_settings: RendererSettings::default(),
+            freeze_frustum: false,
+            screenshot_requested: false,
+            screenshot_name: String::new(),
+            recording_toggled: false,
+            clear_color: (0.1, 0.1, 0.1, 1.0),
+            sky: Sky::default_gradient(),
+            ui: Ui::new(),
+            camera: CameraController::FreeFly(FreeFly::new()),
+            pick_at: None,
+            selected: None
         }
     }
 
-    pub fn init(&mut self,display:&mut glium::Display){
+    pub fn init<F: glium::backend::Facade>(&mut self,display:&F){
 
         /*
 
@@ -108,141 +254,187 @@ impl CCGame{
         self.models.push(m);
 
         */
-        let mut am = my_loaders::loaders::load_animated_collada_mesh(display,"./res/untitled.dae");
-
-        let t:cgmath::Matrix4<f32> = cgmath::Matrix4::from_translation(cgmath::Vector3{
-            x: (0.0),
-            y: (0.0),
-            z: (1.0)
-        });
-
-        let s:cgmath::Matrix4<f32> = cgmath::Matrix4::from_scale(0.1);
-
-        am.0.translation = t;
-        am.0.scale = s;
-        am.1.running = true;
-
-        self.animated_models.push(am);
-        let mut am = my_loaders::loaders::load_animated_collada_mesh(display,"./res/untitled.dae");
-
-        let t:cgmath::Matrix4<f32> = cgmath::Matrix4::from_translation(cgmath::Vector3{
-            x: (1.0),
-            y: (0.0),
-            z: (1.0)
-        });
-
-        let s:cgmath::Matrix4<f32> = cgmath::Matrix4::from_scale(0.1);
-
-        am.0.translation = t;
-        am.0.scale = s;
-        am.1.running = true;
-
-        self.animated_models.push(am);
-        let mut am = my_loaders::loaders::load_animated_collada_mesh(display,"./res/untitled.dae");
-
-        let t:cgmath::Matrix4<f32> = cgmath::Matrix4::from_translation(cgmath::Vector3{
-            x: (2.0),
-            y: (0.0),
-            z: (1.0)
-        });
-
-        let s:cgmath::Matrix4<f32> = cgmath::Matrix4::from_scale(0.1);
-
-        am.0.translation = t;
-        am.0.scale = s;
-        am.1.running = true;
-
-        self.animated_models.push(am);
-        let mut am = my_loaders::loaders::load_animated_collada_mesh(display,"./res/untitled.dae");
-
-        let t:cgmath::Matrix4<f32> = cgmath::Matrix4::from_translation(cgmath::Vector3{
-            x: (3.0),
-            y: (0.0),
-            z: (1.0)
-        });
+        let character = my_loaders::loaders::load_animated_collada_mesh(display,"./res/untitled.dae");
+
+        for x in 0..4 {
+            let t:cgmath::Matrix4<f32> = cgmath::Matrix4::from_translation(cgmath::Vector3{
+                x: (x as f32),
+                y: (0.0),
+                z: (1.0)
+            });
+
+            let s:cgmath::Matrix4<f32> = cgmath::Matrix4::from_scale(0.1);
+
+            let mut am = (ModelRst{
+                rotation: character.0.rotation,
+                scale: s,
+                translation: t
+            }, character.1.instance(display));
+            am.1.running = true;
+            // every instance starts somewhere else in the clip, so they don't move in lockstep
+            let clip_length = *am.1.skeleton[0].time_stamps.last().unwrap();
+            am.1.current_time_sec = self.rng.range(0.0, clip_length);
+
+            self.animated_models.push(am);
+        }
 
-        let s:cgmath::Matrix4<f32> = cgmath::Matrix4::from_scale(0.1);
 
-        am.0.translation = t;
-        am.0.scale = s;
-        am.1.running = true;
+        let texture = my_loaders::loaders::load_texture(display, "./res/cubeTex.png");
+        self.textures.push(texture);
 
-        self.animated_models.push(am);
+        self.load_in_background("./res/cubeStackBendingRotating.dae");
+    }
 
+    // both start from tick 0 with the seed of the recording, call them before the first update
+    pub fn record_input(&mut self, path: &str, seed: u64){
+        println!("recording input to {} with seed {}", path, seed);
+        self.tick = 0;
+        self.rng = Rng::new(seed);
+        self.input.capture = true;
+        self.session = InputSession::Recording{ recording: InputRecording::new(seed), path: path.to_string() };
+    }
 
-        let texture = my_loaders::loaders::load_texture(display, "./res/cubeTex.png");
-        self.textures.push(texture);
+    // returns the tick of the last recorded event
+    pub fn replay_input(&mut self, path: &str) -> Result<u64, String>{
+        let recording = InputRecording::load(path)?;
+        let last_tick = recording.last_tick();
+        println!("replaying {} input events from {}", recording.events.len(), path);
+        self.tick = 0;
+        self.rng = Rng::new(recording.seed);
+        self.input.live = false;
+        self.session = InputSession::Replaying{ recording, next: 0 };
+        Ok(last_tick)
     }
 
     pub fn input(&mut self,events_loop :&mut glutin::EventsLoop){
+        self.gamepads.poll(&mut self.input);
 
-        use glutin::ElementState::{Pressed,Released};
         events_loop.poll_events(|event| {
             match event {
-                glutin::Event::DeviceEvent{ event, ..} => match event {
-                    glutin::DeviceEvent::MouseMotion { delta, ..} => {
-                        if self.ego_mode{
-                            let mouse_speed:f32 = 0.001;
-                            self.cam.rotate(-delta.0 as f32*mouse_speed,delta.1 as f32*mouse_speed);
-                        }
-                    },
-                    _ => ()
-                },
-                glutin::Event::WindowEvent { event, .. } => match event {
-                    glutin::WindowEvent::Resized(size) => {
-                        let fv = cgmath::Rad(std::f32::consts::PI / 3.0);
-                        let perspective: cgmath::Matrix4<f32> = cgmath::perspective(fv, (size.width / size.height) as f32, 0.1, 1024.0);
-                        self.cam.perspective = perspective;
-                        self.window_size = size;
-                    },
-                    glutin::WindowEvent::CloseRequested => self.running = false,
-                    glutin::WindowEvent::KeyboardInput { input, .. } => {
-                        match input.virtual_keycode {
-                            Some(glutin::VirtualKeyCode::W) => if input.state == Pressed {self.key_states[0] = Pressed} else {self.key_states[0] = Released} ,
-                            Some(glutin::VirtualKeyCode::S) => if input.state == Pressed {self.key_states[1] = Pressed} else {self.key_states[1] = Released} ,
-                            Some(glutin::VirtualKeyCode::A) => if input.state == Pressed {self.key_states[2] = Pressed} else {self.key_states[2] = Released} ,
-                            Some(glutin::VirtualKeyCode::D) => if input.state == Pressed {self.key_states[3] = Pressed} else {self.key_states[3] = Released} ,
-                            Some(glutin::VirtualKeyCode::X) => if input.state == Pressed {self.key_states[4] = Pressed} else {self.key_states[4] = Released} ,
-                            Some(glutin::VirtualKeyCode::Y) => if input.state == Pressed {self.key_states[5] = Pressed} else {self.key_states[5] = Released} ,
-                            Some(glutin::VirtualKeyCode::LShift) => if input.state == Pressed {self.key_states[6] = Pressed} else {self.key_states[6] = Released} ,
-                            Some(glutin::VirtualKeyCode::F) => if input.state == Pressed {self.mode_changed = true; if self.toggle_key_states[0] == Pressed {self.toggle_key_states[0] = Released} else { self.toggle_key_states[0] = Pressed }} ,
-                            Some(glutin::VirtualKeyCode::Escape) => self.running = false,
-                            Some(glutin::VirtualKeyCode::Space) => if input.state == Pressed{
-                                /*if self.animated_models[0].1.running {self.animated_models[0].1.running = false} else { self.animated_models[0].1.running = true }*/},
-                            _ => (),
-                        }
-                    },
-                    _ => (),
+                glutin::Event::DeviceEvent{ event, ..} => self.input.handle_device_event(&event),
+                glutin::Event::WindowEvent { event, .. } => {
+                    self.ui.handle_event(&event);
+                    if let Some(text) = self.text.handle_event(&event) {
+                        self.input.handle_text(text);
+                    }
+                    self.input.handle_window_event(&event);
+                    match event {
+                        glutin::WindowEvent::Resized(size) => {
+                            let physical = size.to_physical(self.ui.hidpi_factor);
+                            self.cam.set_viewport(physical.width, physical.height);
+                            self.window_size = size;
+                        },
+                        glutin::WindowEvent::CloseRequested => self.running = false,
+                        _ => (),
+                    }
                 },
                 _ => ()
             }
         });
     }
 
-    pub fn load_que(&mut self, display: &mut glium::Display) {
-        match self.rx.try_recv() {
-            Ok(x) => {
-                let vb = glium::VertexBuffer::new(display, &x.0).unwrap();
-                let ib = glium::IndexBuffer::new(display, glium::index::PrimitiveType::TrianglesList, &x.1).unwrap();
-                let scale = Matrix4::from_scale(0.1);
-                let translation = Matrix4::from_translation(cgmath::Vector3 {
-                    x: (self.models.len() as f32),
-                    y: (0.0),
-                    z: (2.0)
-                });
-                let rotation: Matrix4<f32> = cgmath::SquareMatrix::identity();
-
-                self.models.push((ModelRst {
-                        rotation: rotation,
-                        scale: scale,
-                        translation: translation
-                },  StaticMesh {
-                        vertices: (vb),
-                        indices: (ib)
-                }));
-            },
-            Err(e) => println!("{}", e)
+    // the one shot actions, held ones are read where they are used
+    fn apply_actions(&mut self){
+        let input = &self.input;
+
+        if input.just_pressed("quit") {
+            self.running = false;
+        }
+        if input.just_pressed("toggle_cursor") {
+            self.ego_mode = !self.ego_mode;
+            self.mode_changed = true;
+        }
+        if input.just_pressed("screenshot") {
+            self.screenshot_requested = true;
         }
+        if input.just_pressed("record") {
+            self.recording_toggled = true;
+        }
+        for (i, action) in ["debug_bounds", "debug_skeletons", "debug_normals", "debug_grid"].iter().enumerate() {
+            if input.just_pressed(action) {
+                self.renderer_changes.push(RendererChange::Debug(i));
+            }
+        }
+        // one action per default post pass
+        for i in 0..5 {
+            if input.just_pressed(&format!("post_{}", i + 1)) {
+                self.renderer_changes.push(RendererChange::Post(i));
+            }
+        }
+        if input.just_pressed("freeze_frustum") {
+            self.freeze_frustum = true;
+        }
+        if input.just_pressed("toggle_hud") {
+            self.renderer_changes.push(RendererChange::Hud);
+        }
+        // handled after the console, so the key that opened it is not typed into it
+        if input.just_pressed("console") {
+            self.console.open = true;
+        }
+        if input.just_pressed("select") && !self.ego_mode && !self.ui.wants_mouse() {
+            let (x, y) = input.cursor();
+            self.pick_at = Some(glutin::dpi::LogicalPosition::new(x as f64, y as f64));
+        }
+        if input.just_pressed("camera_next") {
+            self.camera = self.camera.next(&self.cam);
+            println!("camera: {}", self.camera.name());
+        }
+        if input.just_pressed("camera_path") {
+            match CameraPath::load("./res/paths/intro.path") {
+                Ok(path) => self.camera = CameraController::Path(PathPlayer::new(path)),
+                Err(e) => println!("could not load camera path: {}", e),
+            }
+        }
+    }
+
+    // parses a collada file on a thread, the mesh is added by load_que once it arrives
+    pub fn load_in_background(&mut self, path: &str){
+        let tx = self.tx.clone();
+        let path = path.to_string();
+        thread::spawn(move || {
+            tx.send(my_loaders::loaders::load_static_collada_mesh_rawdata(&path)).unwrap();
+        });
+        self.loading += 1;
+    }
+
+    // adds the meshes that finished loading since the last call
+    pub fn load_que<F: glium::backend::Facade>(&mut self, display: &F) {
+        while let Ok(x) = self.rx.try_recv() {
+            self.add_loaded(display, x);
+        }
+    }
+
+    // waits for every mesh still loading, so what is on screen does not depend on the thread timing
+    pub fn finish_loading<F: glium::backend::Facade>(&mut self, display: &F) {
+        while self.loading > 0 {
+            let x = self.rx.recv().unwrap();
+            self.add_loaded(display, x);
+        }
+    }
+
+    fn add_loaded<F: glium::backend::Facade>(&mut self, display: &F, x: (Vec<MyVertex>, Vec<u16>)) {
+        let vb = glium::VertexBuffer::new(display, &x.0).unwrap();
+        let ib = glium::IndexBuffer::new(display, glium::index::PrimitiveType::TrianglesList, &x.1).unwrap();
+        let scale = Matrix4::from_scale(0.1);
+        let translation = Matrix4::from_translation(cgmath::Vector3 {
+            x: (self.models.len() as f32),
+            y: (0.0),
+            z: (2.0)
+        });
+        let rotation: Matrix4<f32> = cgmath::SquareMatrix::identity();
+
+        self.models.push((ModelRst {
+                rotation,
+                scale,
+                translation
+        },  StaticMesh {
+                vertices: Rc::new(vb),
+                indices: Rc::new(ib),
+                bounds: Bounds::from_points(x.0.iter().map(|v| v.position)),
+                cpu_vertices: Rc::new(x.0),
+                cpu_indices: Rc::new(x.1),
+        }));
+        self.loading -= 1;
     }
 
     pub fn update(&mut self,dt: &f32){
@@ -254,58 +446,253 @@ impl CCGame{
             }
         }
 
-        use glutin::ElementState::Pressed;
-
-        if (self.key_states[0] == Pressed && self.key_states[1] == Pressed) || (self.key_states[2] == Pressed && self.key_states[3] == Pressed ) || (self.key_states[4] == Pressed && self.key_states[5] == Pressed ){
-            return;
+        self.session.begin_tick(self.tick, &mut self.input);
+        self.input.suspend_keys(self.ui.has_focus() || self.console.open);
+        let (x, y) = self.input.cursor();
+        let cursor = glutin::dpi::LogicalPosition::new(x as f64, y as f64).to_physical(self.ui.hidpi_factor);
+        let left = Binding::Mouse(glutin::MouseButton::Left);
+        self.ui.update_mouse((cursor.x as f32, cursor.y as f32), self.input.binding_down(left), self.input.binding_pressed(left), self.input.binding_released(left));
+        for line in self.console.handle(self.input.text()) {
+            self.run_command(&line);
         }
-
-        if self.key_states[0] == Pressed && self.key_states[2] == Pressed {
-            self.cam.move_angle(dt, &(std::f32::consts::PI*0.25));
-        }else if self.key_states[0] == Pressed && self.key_states[3] == Pressed {
-            self.cam.move_angle(dt, &(std::f32::consts::PI * 1.75));
-        } else if self.key_states[1] == Pressed && self.key_states[2] == Pressed {
-            self.cam.move_angle(dt, &(std::f32::consts::PI*0.75));
-        }else if self.key_states[1] == Pressed && self.key_states[3] == Pressed {
-            self.cam.move_angle(dt, &(std::f32::consts::PI * 1.25));
-        }else if self.key_states[0] == Pressed {
-            self.cam.forward(dt);
-        }else if self.key_states[1] == Pressed {
-            self.cam.backward(dt);
-        }else if self.key_states[2] == Pressed {
-            self.cam.right(dt);
-        }else if self.key_states[3] == Pressed {
-            self.cam.left(dt);
-        }else if self.key_states[4] == Pressed {
-            self.cam.up(dt);
-        }else if self.key_states[5] == Pressed {
-            self.cam.down(dt);
+        self.apply_actions();
+        if !self.ego_mode {
+            self.debug_panel();
         }
 
-        if self.key_states[6] == Pressed {
-            self.cam.speed = 0.005;
-        }else {
-            self.cam.speed = 0.001;
+        let mut path_finished = false;
+        if let CameraController::Path(ref mut player) = self.camera {
+            for event in player.fired.drain(..) {
+                println!("camera path event: {}", event);
+            }
+            path_finished = player.finished();
+        }
+        if path_finished {
+            self.camera = CameraController::FreeFly(FreeFly::new());
         }
 
-        if self.toggle_key_states[0] == Pressed {
-            self.ego_mode = false;
-        }else {
-            self.ego_mode= true
+        if self.ego_mode {
+            self.cam.rotate(-self.input.axis_value("look_x"), -self.input.axis_value("look_y"));
+        }
+        self.camera.zoom(self.input.axis_value("zoom"));
+
+        // x to the right, y up and z forward, keys and sticks add up and opposite ones cancel out
+        let input = &self.input;
+        let mut movement = Vector3::new(
+            input.action_axis("move_right", "move_left") + input.axis_value("move_x"),
+            input.action_axis("move_up", "move_down") + input.axis_value("move_y"),
+            input.action_axis("move_forward", "move_backward") + input.axis_value("move_z"),
+        );
+        if movement.magnitude2() > 1.0 {
+            movement = movement.normalize();
         }
+        let sprint = input.pressed("sprint");
+
+        self.camera.steer(&mut self.cam, movement, sprint, dt);
+        if !self.camera.is_free() {
+            // follow tracks the first animated model, or the first static one without any. its own
+            // box is not a collider, the arm starts inside it
+            let follows_animated = !self.animated_models.is_empty();
+            let colliders: Vec<Aabb> = self.models.iter().skip(if follows_animated { 0 } else { 1 })
+                .map(|m| m.1.bounds.aabb.transformed(&m.0.matrix()))
+                .chain(self.animated_models.iter().skip(1).map(|m| m.1.bounds.aabb.transformed(&m.0.matrix())))
+                .collect();
+            let target = self.animated_models.first().map(|m| &m.0).or(self.models.first().map(|m| &m.0));
+            self.camera.update(&mut self.cam, dt, target, &colliders);
+        }
+
+        self.input.end_update();
+        self.tick += 1;
+        // the ui takes the mouse while the cursor is released
+        self.ui.enabled = !self.ego_mode;
     }
 
-    pub fn render(&mut self,renderer: &mut Renderer,target_frame: &mut glium::Frame,display:&mut glium::Display){
+    pub fn render<F: glium::backend::Facade, S: glium::Surface>(&mut self,renderer: &mut Renderer,target_frame: &mut S,display:&F){
+
+        for change in self.renderer_changes.drain(..) {
+            match change {
+                RendererChange::Hud => renderer.hud.visible = !renderer.hud.visible,
+                RendererChange::Culling => renderer.culling = !renderer.culling,
+                RendererChange::Oit => renderer.oit = !renderer.oit,
+                RendererChange::Debug(i) => renderer.debug.toggle(i),
+                RendererChange::Post(i) => renderer.post.toggle(i),
+                RendererChange::Exposure(i, value) => {
+                    if let Some(PostEffect::Tonemap { ref mut exposure }) = renderer.post.passes.get_mut(i).map(|p| &mut p.effect) {
+                        *exposure = value;
+                    }
+                },
+            }
+        }
+        self.renderer_settings = RendererSettings::of(renderer);
+        // keeps the current view volume around for a while to inspect culling from outside
+        if self.freeze_frustum {
+            renderer.debug.frustum(self.cam.view_projection(), my_debug_draw::YELLOW, 10.0);
+            self.freeze_frustum = false;
+        }
 
         for model in &mut self.animated_models{
             model.1.calculate_current_pose();
+            model.1.update_joint_buffer();
         }
 
+        if let Some(cursor) = self.pick_at.take() {
+            let (width, height) = target_frame.get_dimensions();
+            let cursor = cursor.to_physical(self.ui.hidpi_factor);
+            self.selected = self.cam.screen_ray(cursor.x as f32, cursor.y as f32, width as f32, height as f32)
+                .and_then(|ray| my_picking::pick(&ray, &self.models, &self.animated_models));
+            if let Some(hit) = self.selected {
+                self.console.print(&format!("picked {:?} at {:.2}", hit.picked, hit.distance));
+            }
+        }
+        if let Some(hit) = self.selected {
+            let (aabb, transform) = match hit.picked {
+                Picked::Static(i) => (self.models[i].1.bounds.aabb, self.models[i].0.matrix()),
+                Picked::Animated(i) => (self.animated_models[i].1.bounds.aabb, self.animated_models[i].0.matrix()),
+            };
+            renderer.debug.transformed_aabb(&aabb, &transform, my_debug_draw::GREEN, 0.0);
+        }
+
+        let mut queue = RenderQueue::new();
+        queue.set_sky(&self.sky);
         for model in &self.models{
-            renderer.draw_textured_static_mesh(target_frame,&self.cam,model,&self.textures[0]);
+            queue.submit_static(model,Material::Textured(&self.textures[0]));
         }
         for model in &self.animated_models{
-            renderer.draw_textured_animated_mesh(target_frame,display,&self.cam,model,&self.textures[0]);
+            queue.submit_animated(model,Material::Textured(&self.textures[0]));
+        }
+        renderer.draw_queue(target_frame,display,&self.cam,&mut queue);
+
+        self.console.draw(&mut renderer.overlay, target_frame.get_dimensions().0 as f32);
+
+        if !self.ego_mode {
+            self.ui.draw(&mut renderer.overlay);
+        }
+    }
+
+    fn run_command(&mut self, line: &str){
+        let words: Vec<&str> = line.split_whitespace().collect();
+        let number = |i: usize| words.get(i).and_then(|w| w.parse::<f32>().ok());
+        match words[0] {
+            "help" => self.console.print("fov <degrees>\nprojection, switches between perspective and orthographic\nreversed_z, switches the depth direction\nspeed <walk> <sprint> in units per second\nbind <action> <kind> <name>\nsave_bindings\nquit"),
+            "fov" => match (number(1), self.cam.projection) {
+                (Some(degrees), Projection::Perspective { near, far, infinite, .. }) => {
+                    self.cam.set_projection(Projection::Perspective { fov: degrees.to_radians(), near, far, infinite });
+                },
+                (None, _) => self.console.print("fov <degrees>"),
+                _ => self.console.print("the camera is not perspective"),
+            },
+            "projection" => {
+                self.toggle_projection();
+                let name = match self.cam.projection { Projection::Orthographic { .. } => "orthographic", _ => "perspective" };
+                self.console.print(name);
+            },
+            "reversed_z" => {
+                self.cam.set_reversed_z(!self.cam.reversed_z);
+                self.console.print(if self.cam.reversed_z { "near at depth 1, far at 0" } else { "near at depth 0, far at 1" });
+            },
+            "speed" => match (number(1), number(2), &mut self.camera) {
+                (Some(walk), Some(sprint), CameraController::FreeFly(fly)) => {
+                    fly.walk_speed = walk / 1000.0;
+                    fly.sprint_speed = sprint / 1000.0;
+                },
+                (Some(_), Some(_), _) => self.console.print("the camera is not free fly"),
+                _ => self.console.print("speed <walk> <sprint>"),
+            },
+            "bind" if words.len() == 4 => match Binding::parse(words[2], words[3]) {
+                Some(binding) => {
+                    self.input.rebind(words[1], binding);
+                    self.console.print(&format!("{}: {}", words[1], self.input.describe_action(words[1])));
+                },
+                None => self.console.print(&format!("unknown binding {} {}", words[2], words[3])),
+            },
+            "save_bindings" => match self.input.save("./res/input.cfg") {
+                Ok(()) => self.console.print("saved to ./res/input.cfg"),
+                Err(e) => self.console.print(&format!("could not save bindings: {}", e)),
+            },
+            "quit" => self.running = false,
+            other => self.console.print(&format!("unknown command {}, try help", other)),
+        }
+    }
+
+    // the orthographic view is sized for the orbit or follow distance, or 5 units in front
+    fn toggle_projection(&mut self){
+        let distance = self.camera.distance().unwrap_or(5.0);
+        self.cam.set_projection(self.cam.projection.toggled(distance));
+    }
+
+    // runs on the tick, the renderer settings are changed with the next frame
+    fn debug_panel(&mut self){
+        // the input map is borrowed again for rebinding below
+        let text = self.input.text().to_vec();
+        let mut ui = self.ui.begin(&text);
+        let settings = &mut self.renderer_settings;
+        let changes = &mut self.renderer_changes;
+
+        ui.begin_window("renderer", 10.0, 140.0, 240.0);
+        if ui.checkbox("stats hud", &mut settings.hud) {
+            changes.push(RendererChange::Hud);
+        }
+        if ui.checkbox("culling", &mut settings.culling) {
+            changes.push(RendererChange::Culling);
+        }
+        if ui.checkbox("oit", &mut settings.oit) {
+            changes.push(RendererChange::Oit);
+        }
+        for (i, label) in ["bounds", "skeletons", "normals", "grid"].iter().enumerate() {
+            if ui.checkbox(label, &mut settings.debug[i]) {
+                changes.push(RendererChange::Debug(i));
+            }
+        }
+        let was_orthographic = matches!(self.cam.projection, Projection::Orthographic { .. });
+        let mut orthographic = was_orthographic;
+        ui.checkbox("orthographic", &mut orthographic);
+        let mut reversed_z = self.cam.reversed_z;
+        if ui.checkbox("reversed z", &mut reversed_z) {
+            self.cam.set_reversed_z(reversed_z);
+        }
+        ui.end_window();
+
+        ui.begin_window("post", 260.0, 140.0, 240.0);
+        for (i, (name, enabled, exposure)) in settings.post.iter_mut().enumerate() {
+            if ui.checkbox(name, enabled) {
+                changes.push(RendererChange::Post(i));
+            }
+            if let Some(exposure) = exposure {
+                if ui.slider("exposure", exposure, 0.1, 4.0) {
+                    changes.push(RendererChange::Exposure(i, *exposure));
+                }
+            }
+        }
+        ui.end_window();
+
+        ui.begin_window("capture", 510.0, 140.0, 240.0);
+        ui.text_field("name", &mut self.screenshot_name);
+        if ui.button("screenshot") {
+            self.screenshot_requested = true;
+        }
+        ui.end_window();
+
+        ui.begin_window("input", 760.0, 140.0, 240.0);
+        match self.input.rebinding() {
+            Some(action) => ui.label(&format!("press a key for {}", action)),
+            None => {
+                for action in self.input.action_names() {
+                    if ui.button(&format!("{}: {}", action, self.input.describe_action(&action))) {
+                        self.input.start_rebind(&action);
+                    }
+                }
+            },
+        }
+        if ui.button("save bindings") {
+            if let Err(e) = self.input.save("./res/input.cfg") {
+                println!("could not save bindings: {}", e);
+            }
+        }
+        ui.end_window();
+        drop(ui);
+
+        if orthographic != was_orthographic {
+            self.toggle_projection();
         }
     }
 }
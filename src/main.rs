@@ -5,12 +5,62 @@ extern crate collada;
 #[macro_use] extern crate glium;
 
 extern  crate assimp;
+extern crate rusttype;
+extern crate notify;
+#[cfg(feature = "gamepad")]
+extern crate gilrs;
+extern crate clipboard;
+extern crate libloading;
 
 #[path = "./game/engine/cc_game_engine.rs"]
 pub mod my_game_engine;
 
 fn main(){
-    let ga = my_game_engine::my_game_logic::CCGame::new();
+    let args: Vec<String> = std::env::args().collect();
+    let mut ga = my_game_engine::my_game_logic::CCGame::new();
+
+    // --record <file> [--seed <n>] or --replay <file>, anywhere after the other options
+    let option = |name: &str| args.iter().position(|a| a == name).and_then(|i| args.get(i + 1)).cloned();
+    let mut replay_ticks = None;
+    if let Some(path) = option("--replay") {
+        replay_ticks = Some(ga.replay_input(&path).unwrap());
+    }else if let Some(path) = option("--record") {
+        let seed = option("--seed").and_then(|s| s.parse::<u64>().ok()).unwrap_or_else(my_game_engine::my_game_logic::my_random::seed_from_time);
+        ga.record_input(&path, seed);
+    }
+
+    // --headless <out.png> [--golden <reference.png>], a replay is run to its end before the frame
+    if args.len() > 2 && args[1] == "--headless" {
+        // without a recording the seed is fixed too, the frame has to be the same on every run
+        if ga.session.is_live() {
+            let seed = option("--seed").and_then(|s| s.parse::<u64>().ok()).unwrap_or(0);
+            ga.rng = my_game_engine::my_game_logic::my_random::Rng::new(seed);
+        }
+        let mut ge = my_game_engine::CCHeadlessEngine::new(ga,800,600);
+        ge.init();
+        if let Some(ticks) = replay_ticks {
+            ge.run_ticks(ticks + 1);
+        }
+        let frame = ge.render_to_image();
+        frame.save(&args[2]).unwrap();
+
+        if args.len() > 4 && args[3] == "--golden" {
+            let golden = image::open(&args[4]).unwrap().to_rgba();
+            match my_game_engine::image_difference(&frame,&golden) {
+                Some(d) if d <= 0.01 => println!("golden image matches, difference: {}",d),
+                Some(d) => {
+                    println!("golden image mismatch, difference: {}",d);
+                    std::process::exit(1);
+                },
+                None => {
+                    println!("golden image has a different size");
+                    std::process::exit(1);
+                }
+            }
+        }
+        return;
+    }
+
     let mut ge = my_game_engine::CCGameEngine::new(ga,"test123",false);
     ge.start();
 }